@@ -12,25 +12,45 @@ fn search_pat(base: &str, pat: &str) -> bool {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum Gssapi {
     Mit,
     Heimdal,
     Apple,
 }
 
+// the implementation selected with the mit or heimdal feature, if
+// exactly one of them is enabled, otherwise we use whatever we find
+fn selected() -> Option<Gssapi> {
+    let mit = env::var_os("CARGO_FEATURE_MIT").is_some();
+    let heimdal = env::var_os("CARGO_FEATURE_HEIMDAL").is_some();
+    match (mit, heimdal) {
+        (true, false) => Some(Gssapi::Mit),
+        (false, true) => Some(Gssapi::Heimdal),
+        (true, true) | (false, false) => None,
+    }
+}
+
+fn allowed(imp: Gssapi) -> bool {
+    match selected() {
+        None => true,
+        Some(sel) => sel == imp,
+    }
+}
+
 fn builder_from_pkgconfig(lib: pkg_config::Library) -> bindgen::Builder {
     bindgen::Builder::default()
         .clang_args(lib.include_paths.iter().map(|path| format!("-I{}", path.to_string_lossy())))
 }
 
-fn try_pkgconfig() -> Result<(Gssapi, bindgen::Builder), pkg_config::Error> {
-    match pkg_config::probe_library("mit-krb5-gssapi") {
-        Ok(lib) => Ok((Gssapi::Mit, builder_from_pkgconfig(lib))),
-        Err(_) => match pkg_config::probe_library("heimdal-gssapi") {
-            Ok(lib) => Ok((Gssapi::Heimdal, builder_from_pkgconfig(lib))),
-            Err(lib) => Err(lib),
-        }
-    }
+fn try_pkgconfig() -> Option<(Gssapi, bindgen::Builder)> {
+    [(Gssapi::Mit, "mit-krb5-gssapi"), (Gssapi::Heimdal, "heimdal-gssapi")]
+        .iter()
+        .filter(|(imp, _)| allowed(*imp))
+        .find_map(|(imp, name)| {
+            let lib = pkg_config::probe_library(name).ok()?;
+            Some((*imp, builder_from_pkgconfig(lib)))
+        })
 }
 
 fn which() -> Gssapi {
@@ -52,17 +72,23 @@ fn which() -> Gssapi {
         let krb5_path = krb5_path.as_ref().map(|s| s.trim());
         for path in krb5_path.into_iter().chain(ldpath.split(':')).chain(paths) {
             if !path.is_empty() {
-                if search_pat(path, "libgssapi_krb5.so*") {
+                if allowed(Gssapi::Mit) && search_pat(path, "libgssapi_krb5.so*") {
                     println!("cargo:rustc-link-lib=gssapi_krb5");
                     return Gssapi::Mit;
                 }
-                if search_pat(path, "libgssapi.so*") {
+                if allowed(Gssapi::Heimdal) && search_pat(path, "libgssapi.so*") {
                     println!("cargo:rustc-link-lib=gssapi");
                     return Gssapi::Heimdal;
                 }
             }
         }
-        panic!("no gssapi implementation found, install mit kerberos or heimdal");
+        match selected() {
+            Some(Gssapi::Mit) => panic!("the mit feature is enabled but mit kerberos wasn't found"),
+            Some(Gssapi::Heimdal) => panic!("the heimdal feature is enabled but heimdal wasn't found"),
+            Some(Gssapi::Apple) | None => {
+                panic!("no gssapi implementation found, install mit kerberos or heimdal")
+            }
+        }
     } else {
         panic!("libgssapi isn't ported to this platform yet")
     }
@@ -70,8 +96,8 @@ fn which() -> Gssapi {
 
 fn main() {
     let (imp, builder) = match try_pkgconfig() {
-        Ok((imp, builder)) => (imp, builder),
-        Err(_) => {
+        Some((imp, builder)) => (imp, builder),
+        None => {
             let imp = which();
             let builder = bindgen::Builder::default();
             let nix_cflags = env::var("NIX_CFLAGS_COMPILE");
@@ -86,6 +112,15 @@ fn main() {
             (imp, builder)
         }
     };
+    // dependents' build scripts can read this as DEP_GSSAPI_KRB5_IMPLEMENTATION
+    println!(
+        "cargo:implementation={}",
+        match imp {
+            Gssapi::Mit => "mit",
+            Gssapi::Heimdal => "heimdal",
+            Gssapi::Apple => "apple",
+        }
+    );
     let bindings = builder
         .allowlist_type("(OM_.+|gss_.+)")
         .allowlist_var("_?GSS_.+|gss_.+")
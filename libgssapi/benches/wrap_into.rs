@@ -0,0 +1,84 @@
+//! Compare framing wrapped tokens with `wrap` and a copy into a new
+//! `Vec` against `wrap_into` reusing one `Vec`. gssapi allocates the
+//! token itself either way, what is counted here is allocations made
+//! from Rust. Run with `cargo bench --bench wrap_into`, it uses the
+//! krb5 test environment so it only works with MIT.
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use libgssapi::context::SecurityContext;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+struct Counting;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const MESSAGES: usize = 10_000;
+
+fn run(name: &str, mut f: impl FnMut()) -> f64 {
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..MESSAGES {
+        f()
+    }
+    let elapsed = start.elapsed();
+    let per_msg = (ALLOCS.load(Ordering::Relaxed) - allocs) as f64 / MESSAGES as f64;
+    println!(
+        "{:<12} {:>6.2} allocations/message {:>8.2?}/message",
+        name,
+        per_msg,
+        elapsed / MESSAGES as u32
+    );
+    per_msg
+}
+
+fn main() {
+    let (mut client, _server) = common::established();
+    let msg = vec![0x5a; 1024];
+    let mut frame = Vec::new();
+    let wrap = run("wrap", || {
+        let tok = client.wrap(true, &msg).unwrap();
+        frame = Vec::with_capacity(4 + tok.len());
+        frame.extend_from_slice(&(tok.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&tok);
+    });
+    let wrap_into = run("wrap_into", || {
+        frame.clear();
+        frame.extend_from_slice(&[0; 4]);
+        let len = client.wrap_into(true, &msg, &mut frame).unwrap();
+        frame[..4].copy_from_slice(&(len as u32).to_be_bytes());
+    });
+    let mic = run("get_mic", || {
+        let tok = client.get_mic(&msg).unwrap();
+        frame = tok.to_vec();
+    });
+    let mic_into = run("get_mic_into", || {
+        frame.clear();
+        client.get_mic_into(&msg, &mut frame).unwrap();
+    });
+    assert!(wrap_into < wrap);
+    assert!(mic_into < mic);
+}
@@ -0,0 +1,90 @@
+/*
+An example program running the GS2-KRB5 SASL mechanism, and the
+GS2-KRB5-PLUS variant with matching and mismatched channel bindings,
+between a client and a server in the same process. You need the same
+kerberos environment as the krb5 example (a krb5.conf, a KDC, a
+keytab for the service, and a TGT), then
+
+KRB5_KTNAME=FILE:/path/to/krb5.keytab cargo run --features sasl --example gs2 nfs@host.example.com
+GS2-KRB5: authenticated user@EXAMPLE.COM as authzid Some("admin"), channel binding None
+GS2-KRB5-PLUS: authenticated user@EXAMPLE.COM as authzid None, channel binding Some("tls-exporter")
+GS2-KRB5-PLUS with mismatched channel bindings: rejected as expected
+*/
+
+use std::env::args;
+use libgssapi::{
+    name::Name,
+    credential::Cred,
+    error::Error,
+    context::{CtxFlags, ClientCtx, ServerCtx, SecurityContext},
+    oid::{GSS_NT_HOSTBASED_SERVICE, GSS_MECH_KRB5},
+    sasl::gs2::{self, CbFlag, Gs2Client, Gs2Server},
+};
+
+fn exchange(
+    service_name: &[u8],
+    mech_name: &str,
+    authzid: Option<&str>,
+    client_cb: &[u8],
+    server_cb: &[u8],
+) -> Result<(), Error> {
+    let (mech, plus) = gs2::mech_for_saslname(mech_name)?;
+    let name = Name::new(service_name, Some(&GSS_NT_HOSTBASED_SERVICE))?;
+    let cname = name.canonicalize(Some(&GSS_MECH_KRB5))?;
+    let server_cred = Cred::acquire_for_service(&cname)?;
+    let client_ctx = ClientCtx::new(
+        None, cname, CtxFlags::GSS_C_MUTUAL_FLAG, Some(&GSS_MECH_KRB5)
+    );
+    let (cb_flag, server_binding) = if plus {
+        (CbFlag::Used(String::from("tls-exporter")), Some(("tls-exporter", server_cb)))
+    } else {
+        (CbFlag::NotSupported, None)
+    };
+    let mut client = Gs2Client::new(client_ctx, authzid, cb_flag, client_cb);
+    let mut server = Gs2Server::new(ServerCtx::new(server_cred), mech, server_binding);
+    let mut challenge: Option<Vec<u8>> = None;
+    loop {
+        match client.step(challenge.as_deref())? {
+            None => break,
+            Some(response) => match server.step(&response)? {
+                None => break,
+                Some(tok) => { challenge = Some(tok); }
+            }
+        }
+    }
+    println!(
+        "{}: authenticated {} as authzid {:?}, channel binding {:?}",
+        mech_name,
+        server.context_mut().source_name()?,
+        server.authzid(),
+        server.channel_binding(),
+    );
+    Ok(())
+}
+
+fn run(service_name: &[u8]) -> Result<(), Error> {
+    let mech_name = gs2::saslname_for_mech(&GSS_MECH_KRB5)?;
+    let plus = format!("{}{}", mech_name, gs2::PLUS);
+    exchange(service_name, &mech_name, Some("admin"), b"", b"")?;
+    exchange(service_name, &plus, None, b"connection binding", b"connection binding")?;
+    match exchange(service_name, &plus, None, b"connection binding", b"something else") {
+        Ok(()) => println!("{} with mismatched channel bindings: accepted!", plus),
+        Err(e) => {
+            println!("{} with mismatched channel bindings: rejected as expected", plus);
+            println!("{}", e)
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args = args().collect::<Vec<_>>();
+    if args.len() != 2 {
+        println!("usage: {}: <service@host>", args[0]);
+    } else {
+        match run(args[1].as_bytes()) {
+            Ok(()) => (),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
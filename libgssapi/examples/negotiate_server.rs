@@ -0,0 +1,78 @@
+/*
+An example HTTP server that requires clients to authenticate with
+Negotiate (SPNEGO) before it will greet them. In order to run this
+example you need,
+
+* a valid krb5.conf
+* a service principal for the server e.g. HTTP/server.example.com@EXAMPLE.COM
+* a keytab containing the service principal's key and that is readable by the user
+  you want to run the example as. e.g. if not running as root set the environment
+  variable KRB5_KTNAME=FILE:/path/to/keytab
+
+KRB5_KTNAME=FILE:/path/to/http.keytab cargo run --features tower --example negotiate_server 127.0.0.1:8080
+
+then, with a valid TGT,
+
+curl --negotiate -u : http://server.example.com:8080/
+hello user@EXAMPLE.COM
+*/
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{body::Incoming, server::conn::http1, Request, Response};
+use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+use libgssapi::{
+    credential::{Cred, CredUsage},
+    negotiate::tower::{Authenticated, NegotiateLayer},
+};
+use std::{
+    convert::Infallible,
+    env::args,
+    future::{ready, Ready},
+    task::{Context, Poll},
+};
+use tokio::net::TcpListener;
+use tower_layer::Layer;
+use tower_service::Service;
+
+#[derive(Debug, Clone, Copy)]
+struct Hello;
+
+impl Service<Request<Incoming>> for Hello {
+    type Response = Response<Full<Bytes>>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Incoming>) -> Self::Future {
+        // the layer guarantees this is present
+        let auth = req.extensions().get::<Authenticated>().unwrap();
+        let body = format!(
+            "hello {}{}\n",
+            auth.name,
+            if auth.delegated_cred.is_some() { " (delegated)" } else { "" }
+        );
+        ready(Ok(Response::new(Full::new(Bytes::from(body)))))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let addr = args().nth(1).unwrap_or_else(|| String::from("127.0.0.1:8080"));
+    let layer = NegotiateLayer::new(|| Cred::acquire_default(CredUsage::Accept));
+    let listener = TcpListener::bind(&addr).await?;
+    println!("listening on {}", addr);
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        // one service per connection, so multi leg handshakes work
+        let service = TowerToHyperService::new(layer.layer(Hello));
+        tokio::spawn(async move {
+            let conn = http1::Builder::new().serve_connection(TokioIo::new(stream), service);
+            if let Err(e) = conn.await {
+                eprintln!("{}: {}", peer, e);
+            }
+        });
+    }
+}
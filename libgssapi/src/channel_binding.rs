@@ -0,0 +1,225 @@
+//! Channel binding data for TLS connections, to pass to
+//! `ClientCtx::step` or `ServerCtx::step_with_channel_bindings` (or
+//! as the `cb_data` of a GS2 exchange) so the gssapi context is bound
+//! to the TLS connection it was established over, which defeats
+//! credential forwarding man in the middle attacks (e.g. HTTPS
+//! extended protection).
+use crate::error::{Error, MajorFlags};
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+
+/// The prefix of tls-server-end-point channel binding data (rfc5929)
+pub const TLS_SERVER_END_POINT: &[u8] = b"tls-server-end-point:";
+
+const RSA_MD5: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x04";
+const RSA_SHA1: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x05";
+const RSA_PSS: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0a";
+const RSA_SHA256: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0b";
+const RSA_SHA384: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0c";
+const RSA_SHA512: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0d";
+const RSA_SHA224: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0e";
+const ECDSA_SHA1: &[u8] = b"\x2a\x86\x48\xce\x3d\x04\x01";
+const ECDSA_SHA224: &[u8] = b"\x2a\x86\x48\xce\x3d\x04\x03\x01";
+const ECDSA_SHA256: &[u8] = b"\x2a\x86\x48\xce\x3d\x04\x03\x02";
+const ECDSA_SHA384: &[u8] = b"\x2a\x86\x48\xce\x3d\x04\x03\x03";
+const ECDSA_SHA512: &[u8] = b"\x2a\x86\x48\xce\x3d\x04\x03\x04";
+const DSA_SHA1: &[u8] = b"\x2a\x86\x48\xce\x38\x04\x03";
+const DSA_SHA224: &[u8] = b"\x60\x86\x48\x01\x65\x03\x04\x03\x01";
+const DSA_SHA256: &[u8] = b"\x60\x86\x48\x01\x65\x03\x04\x03\x02";
+const SHA1: &[u8] = b"\x2b\x0e\x03\x02\x1a";
+const SHA256: &[u8] = b"\x60\x86\x48\x01\x65\x03\x04\x02\x01";
+const SHA384: &[u8] = b"\x60\x86\x48\x01\x65\x03\x04\x02\x02";
+const SHA512: &[u8] = b"\x60\x86\x48\x01\x65\x03\x04\x02\x03";
+const SHA224: &[u8] = b"\x60\x86\x48\x01\x65\x03\x04\x02\x04";
+
+const SEQUENCE: u8 = 0x30;
+const OID: u8 = 0x06;
+const PSS_HASH: u8 = 0xa0;
+
+/// The hash functions rfc5929 can select
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertDigest {
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl CertDigest {
+    /// Pick the hash function for the certificate `cert` (DER). Per
+    /// rfc5929 this is SHA-256 if the certificate is signed using MD5
+    /// or SHA-1, otherwise it's the hash function of the signature
+    /// algorithm. Signature algorithms that don't use a single hash
+    /// function (e.g. Ed25519) have no tls-server-end-point binding,
+    /// and fail with `GSS_S_FAILURE`, as do malformed certificates.
+    pub fn for_cert(cert: &[u8]) -> Result<CertDigest, Error> {
+        signature_digest(cert).ok_or(Error {
+            major: MajorFlags::GSS_S_FAILURE,
+            minor: 0,
+        })
+    }
+
+    fn hash(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            CertDigest::Sha224 => Sha224::digest(data).to_vec(),
+            CertDigest::Sha256 => Sha256::digest(data).to_vec(),
+            CertDigest::Sha384 => Sha384::digest(data).to_vec(),
+            CertDigest::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+/// Split the DER element at the start of `b` into its tag, contents,
+/// and whatever follows it
+fn element(b: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *b.first()?;
+    let first = *b.get(1)?;
+    let (len, b) = if first < 0x80 {
+        (first as usize, &b[2..])
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let len = b
+            .get(2..2 + n)?
+            .iter()
+            .fold(0, |len, c| len << 8 | *c as usize);
+        (len, &b[2 + n..])
+    };
+    if len > b.len() {
+        None
+    } else {
+        Some((tag, &b[..len], &b[len..]))
+    }
+}
+
+fn expect(b: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match element(b)? {
+        (t, contents, rest) if t == tag => Some((contents, rest)),
+        _ => None,
+    }
+}
+
+fn hash_digest(oid: &[u8]) -> Option<CertDigest> {
+    match oid {
+        SHA1 | SHA256 => Some(CertDigest::Sha256),
+        SHA224 => Some(CertDigest::Sha224),
+        SHA384 => Some(CertDigest::Sha384),
+        SHA512 => Some(CertDigest::Sha512),
+        _ => None,
+    }
+}
+
+fn signature_digest(cert: &[u8]) -> Option<CertDigest> {
+    // Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signature }
+    let (cert, _) = expect(cert, SEQUENCE)?;
+    let (_, cert) = expect(cert, SEQUENCE)?;
+    let (algorithm, _) = expect(cert, SEQUENCE)?;
+    let (oid, params) = expect(algorithm, OID)?;
+    match oid {
+        RSA_MD5 | RSA_SHA1 | ECDSA_SHA1 | DSA_SHA1 => Some(CertDigest::Sha256),
+        RSA_SHA256 | ECDSA_SHA256 | DSA_SHA256 => Some(CertDigest::Sha256),
+        RSA_SHA224 | ECDSA_SHA224 | DSA_SHA224 => Some(CertDigest::Sha224),
+        RSA_SHA384 | ECDSA_SHA384 => Some(CertDigest::Sha384),
+        RSA_SHA512 | ECDSA_SHA512 => Some(CertDigest::Sha512),
+        RSA_PSS => {
+            // the hash is in the parameters, and defaults to SHA-1
+            let (params, _) = expect(params, SEQUENCE)?;
+            match expect(params, PSS_HASH) {
+                None => Some(CertDigest::Sha256),
+                Some((hash, _)) => {
+                    let (hash, _) = expect(hash, SEQUENCE)?;
+                    let (oid, _) = expect(hash, OID)?;
+                    hash_digest(oid)
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Compute the tls-server-end-point (rfc5929) channel binding data
+/// for the server certificate `cert` (DER), which is
+/// `TLS_SERVER_END_POINT` followed by the hash of the certificate
+/// using `CertDigest::for_cert`. Both sides compute it from the same
+/// certificate, the server from its own and the client from the one
+/// the server presented.
+///
+/// ```
+/// # use libgssapi::channel_binding::{tls_server_end_point, CertDigest};
+/// // a certificate skeleton, an empty tbsCertificate, the
+/// // sha384WithRSAEncryption signature algorithm, and an empty signature
+/// let cert = b"\x30\x13\x30\x00\x30\x0d\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x0c\
+///              \x05\x00\x03\x00";
+/// assert_eq!(CertDigest::for_cert(cert).unwrap(), CertDigest::Sha384);
+/// let cb = tls_server_end_point(cert).unwrap();
+/// assert_eq!(&cb[..21], b"tls-server-end-point:");
+/// assert_eq!(cb.len(), 21 + 48);
+///
+/// // the same with sha1WithRSAEncryption, which is upgraded to SHA-256
+/// let cert = b"\x30\x13\x30\x00\x30\x0d\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x01\x05\
+///              \x05\x00\x03\x00";
+/// assert_eq!(CertDigest::for_cert(cert).unwrap(), CertDigest::Sha256);
+/// ```
+pub fn tls_server_end_point(cert: &[u8]) -> Result<Vec<u8>, Error> {
+    let digest = CertDigest::for_cert(cert)?;
+    let mut cb = Vec::from(TLS_SERVER_END_POINT);
+    cb.extend_from_slice(&digest.hash(cert));
+    Ok(cb)
+}
+
+#[cfg(feature = "rustls")]
+mod rustls_cb {
+    use super::*;
+    use rustls::{ClientConnection, ConnectionCommon};
+
+    /// The prefix of tls-exporter channel binding data (rfc9266)
+    pub const TLS_EXPORTER: &[u8] = b"tls-exporter:";
+
+    const EXPORTER_LABEL: &[u8] = b"EXPORTER-Channel-Binding";
+    const EXPORTER_LEN: usize = 32;
+
+    fn failed() -> Error {
+        Error {
+            major: MajorFlags::GSS_S_FAILURE,
+            minor: 0,
+        }
+    }
+
+    /// Compute the tls-exporter (rfc9266) channel binding data for a
+    /// rustls client or server connection, which is `TLS_EXPORTER`
+    /// followed by 32 bytes of keying material exported from the
+    /// connection. Both sides of the connection derive the same data,
+    /// so each can pass it straight to the gssapi step functions. For
+    /// GS2 pass `&cb[TLS_EXPORTER.len()..]` as the `cb_data` instead.
+    ///
+    /// The handshake must be complete. This is the right binding for
+    /// TLS 1.3, where tls-unique is not defined. rustls doesn't expose
+    /// the Finished messages tls-unique is built from, so there is no
+    /// tls-unique binding at all, use this or tls-server-end-point.
+    pub fn tls_exporter<Data>(conn: &ConnectionCommon<Data>) -> Result<Vec<u8>, Error> {
+        if conn.is_handshaking() {
+            return Err(failed());
+        }
+        let mut cb = Vec::from(TLS_EXPORTER);
+        let key = conn
+            .export_keying_material([0u8; EXPORTER_LEN], EXPORTER_LABEL, None)
+            .map_err(|_| failed())?;
+        cb.extend_from_slice(&key);
+        Ok(cb)
+    }
+
+    /// Compute the tls-server-end-point channel binding data from the
+    /// certificate the server presented to this client. The server
+    /// computes the same thing by passing its own certificate to
+    /// `tls_server_end_point`.
+    pub fn tls_server_end_point_for_client(
+        conn: &ClientConnection,
+    ) -> Result<Vec<u8>, Error> {
+        let certs = conn.peer_certificates().ok_or_else(failed)?;
+        tls_server_end_point(certs.first().ok_or_else(failed)?)
+    }
+}
+
+#[cfg(feature = "rustls")]
+pub use rustls_cb::*;
@@ -4,23 +4,25 @@ use crate::{
     credential::{Cred, NO_CRED},
     error::{gss_error, Error, MajorFlags},
     name::Name,
-    oid::{Oid, NO_OID},
-    util::{Buf, BufRef},
+    oid::{Oid, OwnedOid, NO_OID},
+    util::{Buf, BufRef, BufSet},
 };
 use libgssapi_sys::{
     gss_OID, gss_accept_sec_context, gss_buffer_desc, gss_channel_bindings_struct,
-    gss_channel_bindings_t, gss_cred_id_struct, gss_cred_id_t, gss_ctx_id_t,
-    gss_delete_sec_context, gss_init_sec_context, gss_inquire_context, gss_name_t,
-    gss_unwrap, gss_wrap, OM_uint32, GSS_C_ANON_FLAG, GSS_C_CONF_FLAG, GSS_C_DELEG_FLAG,
-    GSS_C_DELEG_POLICY_FLAG, GSS_C_INTEG_FLAG, GSS_C_MUTUAL_FLAG, GSS_C_PROT_READY_FLAG,
-    GSS_C_QOP_DEFAULT, GSS_C_REPLAY_FLAG, GSS_C_SEQUENCE_FLAG, GSS_C_TRANS_FLAG,
-    GSS_S_COMPLETE, _GSS_C_INDEFINITE, _GSS_S_CONTINUE_NEEDED,
+    gss_channel_bindings_t, gss_context_time, gss_cred_id_struct, gss_cred_id_t,
+    gss_ctx_id_t, gss_delete_sec_context, gss_get_mic, gss_init_sec_context,
+    gss_inquire_context, gss_inquire_sec_context_by_oid, gss_name_t, gss_unwrap,
+    gss_verify_mic, gss_wrap, gss_wrap_size_limit, OM_uint32, GSS_C_ANON_FLAG,
+    GSS_C_CONF_FLAG, GSS_C_DELEG_FLAG, GSS_C_DELEG_POLICY_FLAG, GSS_C_INTEG_FLAG,
+    GSS_C_MUTUAL_FLAG, GSS_C_PROT_READY_FLAG, GSS_C_QOP_DEFAULT, GSS_C_REPLAY_FLAG,
+    GSS_C_SEQUENCE_FLAG, GSS_C_TRANS_FLAG, GSS_S_COMPLETE, _GSS_C_INDEFINITE,
+    _GSS_S_CONTINUE_NEEDED,
 };
 #[cfg(feature = "iov")]
 use libgssapi_sys::{
     gss_iov_buffer_desc, gss_unwrap_iov, gss_wrap_iov, gss_wrap_iov_length,
 };
-use std::{ffi, ptr, time::Duration, os::raw::c_int};
+use std::{ffi, ptr, sync::Arc, time::Duration, os::raw::c_int};
 
 bitflags! {
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -35,9 +37,28 @@ bitflags! {
         const GSS_C_PROT_READY_FLAG = GSS_C_PROT_READY_FLAG;
         const GSS_C_TRANS_FLAG = GSS_C_TRANS_FLAG;
         const GSS_C_DELEG_POLICY_FLAG = GSS_C_DELEG_POLICY_FLAG;
+        /// Set by MIT when both sides used the same channel bindings,
+        /// not every implementation defines it
+        const GSS_C_CHANNEL_BOUND_FLAG = 0x800;
     }
 }
 
+/// Short names for the flags, so you can write
+/// `info.flags.contains(CtxFlags::MUTUAL)`
+impl CtxFlags {
+    pub const DELEG: CtxFlags = CtxFlags::GSS_C_DELEG_FLAG;
+    pub const MUTUAL: CtxFlags = CtxFlags::GSS_C_MUTUAL_FLAG;
+    pub const REPLAY: CtxFlags = CtxFlags::GSS_C_REPLAY_FLAG;
+    pub const SEQUENCE: CtxFlags = CtxFlags::GSS_C_SEQUENCE_FLAG;
+    pub const CONF: CtxFlags = CtxFlags::GSS_C_CONF_FLAG;
+    pub const INTEG: CtxFlags = CtxFlags::GSS_C_INTEG_FLAG;
+    pub const ANON: CtxFlags = CtxFlags::GSS_C_ANON_FLAG;
+    pub const PROT_READY: CtxFlags = CtxFlags::GSS_C_PROT_READY_FLAG;
+    pub const TRANS: CtxFlags = CtxFlags::GSS_C_TRANS_FLAG;
+    pub const DELEG_POLICY: CtxFlags = CtxFlags::GSS_C_DELEG_POLICY_FLAG;
+    pub const CHANNEL_BOUND: CtxFlags = CtxFlags::GSS_C_CHANNEL_BOUND_FLAG;
+}
+
 fn delete_ctx(mut ctx: gss_ctx_id_t) {
     if !ctx.is_null() {
         let mut minor = GSS_S_COMPLETE;
@@ -51,21 +72,203 @@ fn delete_ctx(mut ctx: gss_ctx_id_t) {
     }
 }
 
-unsafe fn wrap(ctx: gss_ctx_id_t, encrypt: bool, msg: &[u8]) -> Result<Buf, Error> {
+/// The channel bindings struct with `cb` as the application data,
+/// which is all anyone uses these days. It borrows `cb`, so it must
+/// not outlive it.
+fn bindings_struct(cb: &[u8]) -> gss_channel_bindings_struct {
+    fn empty_buffer() -> gss_buffer_desc {
+        gss_buffer_desc {
+            length: 0,
+            value: ptr::null_mut(),
+        }
+    }
+    gss_channel_bindings_struct {
+        initiator_addrtype: 0,
+        initiator_address: empty_buffer(),
+        acceptor_addrtype: 0,
+        acceptor_address: empty_buffer(),
+        application_data: gss_buffer_desc {
+            length: cb.len(),
+            value: cb.as_ptr() as *mut ffi::c_void,
+        },
+    }
+}
+
+unsafe fn wrap_conf(
+    ctx: gss_ctx_id_t,
+    encrypt: bool,
+    msg: &[u8],
+) -> Result<(Buf, bool), Error> {
     let mut minor = GSS_S_COMPLETE;
     let mut msg = BufRef::from(msg);
     let mut enc_msg = Buf::empty();
+    let mut conf_state: i32 = 0;
     let major = gss_wrap(
         &mut minor as *mut OM_uint32,
         ctx,
         if encrypt { 1 } else { 0 },
         GSS_C_QOP_DEFAULT,
         msg.to_c(),
-        ptr::null_mut(),
+        &mut conf_state as *mut i32,
         enc_msg.to_c(),
     );
     if major == GSS_S_COMPLETE {
-        Ok(enc_msg)
+        Ok((enc_msg, conf_state != 0))
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
+unsafe fn wrap(ctx: gss_ctx_id_t, encrypt: bool, msg: &[u8]) -> Result<Buf, Error> {
+    match wrap_conf(ctx, encrypt, msg)? {
+        (_, false) if encrypt => Err(Error::not_encrypted()),
+        (tok, _) => Ok(tok),
+    }
+}
+
+unsafe fn wrap_into_slice(
+    ctx: gss_ctx_id_t,
+    encrypt: bool,
+    msg: &[u8],
+    out: &mut [u8],
+) -> Result<(usize, bool), Error> {
+    // check before wrapping, a token we can't return would still use
+    // up a sequence number
+    let max_output = out.len().min(u32::MAX as usize);
+    if msg.len() > wrap_size_limit(ctx, encrypt, max_output)? {
+        return Err(Error::buffer_too_small(wrapped_len(ctx, encrypt, msg.len())?));
+    }
+    let (tok, conf) = wrap_conf(ctx, encrypt, msg)?;
+    if encrypt && !conf {
+        return Err(Error::not_encrypted());
+    }
+    if tok.len() > out.len() {
+        return Err(Error::buffer_too_small(tok.len()));
+    }
+    out[..tok.len()].copy_from_slice(&tok);
+    Ok((tok.len(), conf))
+}
+
+unsafe fn encrypt(ctx: gss_ctx_id_t, msg: &[u8]) -> Result<Buf, Error> {
+    // MIT encrypts whatever was negotiated, so check the flag too
+    if !flags(ctx)?.contains(CtxFlags::GSS_C_CONF_FLAG) {
+        return Err(Error::not_encrypted());
+    }
+    wrap(ctx, true, msg)
+}
+
+unsafe fn wrap_size_limit(
+    ctx: gss_ctx_id_t,
+    encrypt: bool,
+    max_output: usize,
+) -> Result<usize, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut max_input: OM_uint32 = 0;
+    let major = gss_wrap_size_limit(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        if encrypt { 1 } else { 0 },
+        GSS_C_QOP_DEFAULT,
+        max_output as OM_uint32,
+        &mut max_input as *mut OM_uint32,
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(max_input as usize)
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
+/// The smallest output buffer `wrap_size_limit` says a message of
+/// `len` bytes fits in
+unsafe fn wrapped_len(
+    ctx: gss_ctx_id_t,
+    encrypt: bool,
+    len: usize,
+) -> Result<usize, Error> {
+    let fits = |n: usize| wrap_size_limit(ctx, encrypt, n).map(|max| max >= len);
+    let mut lo = len;
+    let mut hi = len.saturating_add(64).min(u32::MAX as usize);
+    while !fits(hi)? {
+        if hi == u32::MAX as usize {
+            return Err(Error::buffer_too_small(hi));
+        }
+        lo = hi;
+        hi = hi.saturating_mul(2).min(u32::MAX as usize);
+    }
+    // fits(hi) and the token is always bigger than the message
+    while hi - lo > 1 {
+        let mid = lo + (hi - lo) / 2;
+        if fits(mid)? {
+            hi = mid
+        } else {
+            lo = mid
+        }
+    }
+    Ok(hi)
+}
+
+unsafe fn wrap_into(
+    ctx: gss_ctx_id_t,
+    encrypt: bool,
+    msg: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<usize, Error> {
+    let tok = wrap(ctx, encrypt, msg)?;
+    out.extend_from_slice(&tok);
+    Ok(tok.len())
+}
+
+unsafe fn get_mic(ctx: gss_ctx_id_t, msg: &[u8]) -> Result<Buf, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut msg = BufRef::from(msg);
+    let mut mic = Buf::empty();
+    let major = gss_get_mic(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        GSS_C_QOP_DEFAULT,
+        msg.to_c(),
+        mic.to_c(),
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(mic)
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
+unsafe fn get_mic_into(
+    ctx: gss_ctx_id_t,
+    msg: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<usize, Error> {
+    let mic = get_mic(ctx, msg)?;
+    out.extend_from_slice(&mic);
+    Ok(mic.len())
+}
+
+unsafe fn verify_mic(ctx: gss_ctx_id_t, msg: &[u8], mic: &[u8]) -> Result<(), Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut msg = BufRef::from(msg);
+    let mut mic = BufRef::from(mic);
+    let major = gss_verify_mic(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        msg.to_c(),
+        mic.to_c(),
+        ptr::null_mut::<OM_uint32>(),
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(())
     } else {
         Err(Error {
             major: MajorFlags::from_bits_retain(major),
@@ -126,20 +329,37 @@ unsafe fn wrap_iov_length(
     }
 }
 
-unsafe fn unwrap(ctx: gss_ctx_id_t, msg: &[u8]) -> Result<Buf, Error> {
+/// The result of `SecurityContext::unwrap_into`
+#[derive(Debug, Clone, Copy)]
+pub struct Unwrapped {
+    /// The length of the message appended to the output
+    pub len: usize,
+    /// True if the message was encrypted, false if it was only
+    /// integrity protected
+    pub encrypted: bool,
+    /// The supplementary status bits, e.g. `GSS_S_DUPLICATE_TOKEN`
+    /// or `GSS_S_GAP_TOKEN`, empty if the message arrived in order
+    pub status: MajorFlags,
+}
+
+unsafe fn unwrap_conf(
+    ctx: gss_ctx_id_t,
+    msg: &[u8],
+) -> Result<(Buf, bool, MajorFlags), Error> {
     let mut minor = GSS_S_COMPLETE;
     let mut msg = BufRef::from(msg);
     let mut out = Buf::empty();
+    let mut conf: c_int = 0;
     let major = gss_unwrap(
         &mut minor as *mut OM_uint32,
         ctx,
         msg.to_c(),
         out.to_c(),
-        ptr::null_mut::<i32>(),
+        &mut conf as *mut c_int,
         ptr::null_mut::<OM_uint32>(),
     );
-    if major == GSS_S_COMPLETE {
-        Ok(out)
+    if gss_error(major) == 0 {
+        Ok((out, conf != 0, MajorFlags::from_bits_retain(major)))
     } else {
         Err(Error {
             major: MajorFlags::from_bits_retain(major),
@@ -148,6 +368,40 @@ unsafe fn unwrap(ctx: gss_ctx_id_t, msg: &[u8]) -> Result<Buf, Error> {
     }
 }
 
+unsafe fn unwrap_with_status(
+    ctx: gss_ctx_id_t,
+    msg: &[u8],
+) -> Result<(Buf, MajorFlags), Error> {
+    let (out, _, status) = unwrap_conf(ctx, msg)?;
+    Ok((out, status))
+}
+
+unsafe fn unwrap_into(
+    ctx: gss_ctx_id_t,
+    msg: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<Unwrapped, Error> {
+    let (buf, encrypted, status) = unwrap_conf(ctx, msg)?;
+    out.extend_from_slice(&buf);
+    Ok(Unwrapped {
+        len: buf.len(),
+        encrypted,
+        status,
+    })
+}
+
+unsafe fn unwrap(ctx: gss_ctx_id_t, msg: &[u8]) -> Result<Buf, Error> {
+    let (out, status) = unwrap_with_status(ctx, msg)?;
+    if status.is_empty() {
+        Ok(out)
+    } else {
+        Err(Error {
+            major: status,
+            minor: 0,
+        })
+    }
+}
+
 #[cfg(feature = "iov")]
 unsafe fn unwrap_iov(ctx: gss_ctx_id_t, msg: &mut [GssIov]) -> Result<(), Error> {
     let mut minor = GSS_S_COMPLETE;
@@ -312,6 +566,30 @@ unsafe fn lifetime(ctx: gss_ctx_id_t) -> Result<Duration, Error> {
     Ok(Duration::from_secs(c.lifetime.unwrap() as u64))
 }
 
+unsafe fn time_remaining(ctx: gss_ctx_id_t) -> Result<Option<Duration>, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut secs: OM_uint32 = 0;
+    let major = gss_context_time(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        &mut secs as *mut OM_uint32,
+    );
+    let res = if major == GSS_S_COMPLETE {
+        Ok(secs)
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    };
+    match res {
+        Ok(_GSS_C_INDEFINITE) => Ok(None),
+        Ok(secs) => Ok(Some(Duration::from_secs(secs as u64))),
+        Err(e) if e.is_context_expired() => Ok(Some(Duration::from_secs(0))),
+        Err(e) => Err(e),
+    }
+}
+
 unsafe fn mechanism(ctx: gss_ctx_id_t) -> Result<&'static Oid, Error> {
     let c = info(
         ctx,
@@ -356,13 +634,104 @@ unsafe fn open(ctx: gss_ctx_id_t) -> Result<bool, Error> {
     Ok(c.open.unwrap() > 0)
 }
 
+unsafe fn negotiated(ctx: gss_ctx_id_t, flag: CtxFlags) -> bool {
+    flags(ctx).map(|f| f.contains(flag)).unwrap_or(false)
+}
+
+unsafe fn inquire_by_oid(ctx: gss_ctx_id_t, oid: &Oid) -> Result<BufSet, Error> {
+    let mut out = BufSet::empty();
+    let mut minor = GSS_S_COMPLETE;
+    let major = gss_inquire_sec_context_by_oid(
+        &mut minor as *mut OM_uint32,
+        ctx,
+        oid.to_c(),
+        out.to_c(),
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(out)
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
 pub trait SecurityContext {
     /// Wrap a message with optional encryption. If `encrypt` is true
     /// then only the other side of the context can read the
     /// message. In any case the other side can always verify message
-    /// integrity.
+    /// integrity. If `encrypt` is true but the mechanism didn't
+    /// encrypt the message this fails with an error whose `kind` is
+    /// `WellKnownError::NotEncrypted` rather than return a token that
+    /// would send it in the clear. Some mechanisms (e.g. MIT's krb5)
+    /// encrypt even if confidentiality wasn't negotiated, use
+    /// `encrypt` to require that as well.
     fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Buf, Error>;
 
+    /// Wrap a message with encryption. Unlike `wrap(true, msg)` this
+    /// first checks that `GSS_C_CONF_FLAG` was negotiated, so the
+    /// peer agreed to confidentiality, at the cost of inquiring the
+    /// context. Fails with an error whose `kind` is
+    /// `WellKnownError::NotEncrypted` if it wasn't, or if the
+    /// mechanism didn't encrypt the message.
+    fn encrypt(&mut self, msg: &[u8]) -> Result<Buf, Error>;
+
+    /// Sign a message, this is the same as `get_mic`
+    fn sign(&mut self, msg: &[u8]) -> Result<Buf, Error>;
+
+    /// The same as `wrap_into`, but the token is written to the start
+    /// of `out`, e.g. an io buffer that can't grow, and whether it was
+    /// actually encrypted is returned with its length. gssapi always
+    /// allocates the token itself, so it is still copied. `out` is
+    /// big enough if `msg` is no longer than
+    /// `wrap_size_limit(encrypt, out.len())`, if it isn't this fails
+    /// before wrapping, so no sequence number is used, with an error
+    /// whose `kind` is `WellKnownError::BufferTooSmall` and whose
+    /// `required_len` is the size `out` must be. Like `wrap` this fails
+    /// with `WellKnownError::NotEncrypted` if `encrypt` is true but
+    /// the token isn't encrypted.
+    fn wrap_into_slice(
+        &mut self,
+        encrypt: bool,
+        msg: &[u8],
+        out: &mut [u8],
+    ) -> Result<(usize, bool), Error>;
+
+    /// Return the largest message that can be passed to `wrap` such
+    /// that the wrapped token is no bigger than `max_output`
+    /// bytes. The value of the encrypt flag must match what you pass
+    /// to `wrap`.
+    fn wrap_size_limit(
+        &mut self,
+        encrypt: bool,
+        max_output: usize,
+    ) -> Result<usize, Error>;
+
+    /// The same as `wrap`, but the token is appended to `out`, so
+    /// its capacity can be reused across messages. Returns the length
+    /// of the token.
+    fn wrap_into(
+        &mut self,
+        encrypt: bool,
+        msg: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<usize, Error>;
+
+    /// Compute a message integrity code (a signature) for `msg`,
+    /// which the other side can check with `verify_mic`. The message
+    /// itself is not included, you send it alongside.
+    fn get_mic(&mut self, msg: &[u8]) -> Result<Buf, Error>;
+
+    /// The same as `get_mic`, but the mic is appended to `out`.
+    /// Returns the length of the mic.
+    fn get_mic_into(&mut self, msg: &[u8], out: &mut Vec<u8>) -> Result<usize, Error>;
+
+    /// Check that `mic` is the other side's message integrity code
+    /// for `msg`. Duplicated, old, or out of order messages are
+    /// reported as errors.
+    fn verify_mic(&mut self, msg: &[u8], mic: &[u8]) -> Result<(), Error>;
+
     /** From the MIT kerberos documentation,
 
     > Sign and optionally encrypt a sequence of buffers. The buffers
@@ -413,6 +782,13 @@ pub trait SecurityContext {
     /// the data buffer, which must be provided as it will be to
     /// wrap_iov. The value of the encrypt flag must match what you
     /// pass to `wrap_iov`.
+    ///
+    /// No cryptography is done, so this is cheap. Typically you pass
+    /// `GssIovFake`s of type `Header`, `Data` (made with
+    /// `GssIovFake::with_len`), `Padding` and `Trailer`, then
+    /// allocate the real header, padding and trailer buffers using
+    /// the lengths it sets (`GssIovFake::len`) before calling
+    /// `wrap_iov`.
     #[cfg(feature = "iov")]
     fn wrap_iov_length(
         &mut self,
@@ -424,6 +800,20 @@ pub trait SecurityContext {
     /// decrypting it if necessary.
     fn unwrap(&mut self, msg: &[u8]) -> Result<Buf, Error>;
 
+    /// Unwrap a wrapped message like `unwrap`, but rather than
+    /// failing if the token is out of sequence return the
+    /// supplementary status bits (e.g. `GSS_S_DUPLICATE_TOKEN`,
+    /// `GSS_S_GAP_TOKEN`) along with the message. This is useful for
+    /// protocols that can tolerate reordering or loss.
+    fn unwrap_with_status(&mut self, msg: &[u8]) -> Result<(Buf, MajorFlags), Error>;
+
+    /// Unwrap a wrapped message, appending it to `out` so its
+    /// capacity can be reused across messages. Like
+    /// `unwrap_with_status` out of sequence messages are not errors,
+    /// check `Unwrapped::status` (and `Unwrapped::encrypted` if you
+    /// require confidentiality).
+    fn unwrap_into(&mut self, msg: &[u8], out: &mut Vec<u8>) -> Result<Unwrapped, Error>;
+
     /** From the MIT Kerberos documentation,
 
     > gss_unwrap_iov may be called with an IOV list just like one which
@@ -467,8 +857,49 @@ pub trait SecurityContext {
     /// Return true if the security context is open
     fn open(&mut self) -> Result<bool, Error>;
 
+    /// Ask the mechanism for context information it doesn't expose
+    /// any other way, identified by `oid`, e.g.
+    /// `GSS_INQ_SSPI_SESSION_KEY` or `GSS_KRB5_GET_TKT_FLAGS_X`. What
+    /// the buffers contain depends on the oid. Mechanisms fail with
+    /// `GSS_S_UNAVAILABLE` for oids they don't know.
+    fn inquire_by_oid(&mut self, oid: &Oid) -> Result<BufSet, Error>;
+
+    /// Return true if the context is complete and integrity
+    /// protection (`GSS_C_INTEG_FLAG`) was negotiated, so `wrap` and
+    /// `get_mic` can be used
+    fn can_wrap(&self) -> bool;
+
+    /// Return true if the context is complete and confidentiality
+    /// (`GSS_C_CONF_FLAG`) was negotiated, so `wrap(true, ..)` and
+    /// `encrypt` can be used
+    fn can_encrypt(&self) -> bool;
+
     /// Return true if the security context is fully initialized
     fn is_complete(&self) -> bool;
+
+    /// Return true if the handshake is under way and needs another
+    /// round trip
+    fn is_partial(&self) -> bool;
+
+    /// Return true if a step failed. A failed context can't be
+    /// recovered, you need to start over with a new one.
+    fn is_failed(&self) -> bool;
+
+    /// Return the state of the context, e.g. to log state transitions
+    fn state(&self) -> CtxState;
+}
+
+/// The state of a security context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CtxState {
+    /// `step` has not been called yet
+    Uninit,
+    /// The handshake is under way and needs another round trip
+    Partial,
+    /// The context is established and ready to use
+    Complete,
+    /// A step failed, the context can't be used
+    Failed,
 }
 
 #[derive(Debug)]
@@ -479,14 +910,126 @@ enum ServerCtxState {
     Complete,
 }
 
+/// Build a `ServerCtx` with requirements the client must meet
+#[derive(Debug)]
+pub struct ServerCtxBuilder {
+    cred: Option<Cred>,
+    required_flags: CtxFlags,
+    channel_bindings: Option<Vec<u8>>,
+    accept_delegation: bool,
+    allowed_mechs: Option<Vec<OwnedOid>>,
+    replay_detection: bool,
+}
+
+impl ServerCtxBuilder {
+    /// Start building a server context that accepts with `cred`, or
+    /// the default acceptor credential if `None`, see
+    /// `ServerCtx::new_default`.
+    pub fn new(cred: Option<Cred>) -> ServerCtxBuilder {
+        ServerCtxBuilder {
+            cred,
+            required_flags: CtxFlags::empty(),
+            channel_bindings: None,
+            accept_delegation: true,
+            allowed_mechs: None,
+            replay_detection: true,
+        }
+    }
+
+    /// Fail the context with `GSS_S_UNAUTHORIZED` when it completes
+    /// if the negotiated flags don't include `flags`, e.g.
+    /// `GSS_C_MUTUAL_FLAG | GSS_C_CONF_FLAG`.
+    #[must_use]
+    pub fn require_flags(mut self, flags: CtxFlags) -> Self {
+        self.required_flags = flags;
+        self
+    }
+
+    /// Require the client to pass `channel_bindings` to
+    /// `ClientCtx::step`, `ServerCtx::step` will fail with
+    /// `GSS_S_BAD_BINDINGS` if it passes different ones, or none.
+    /// Clients that pass none are detected by the absence of
+    /// `GSS_C_CHANNEL_BOUND_FLAG`, which only MIT reports, so with
+    /// other implementations every client is rejected.
+    #[must_use]
+    pub fn channel_bindings(mut self, channel_bindings: &[u8]) -> Self {
+        self.channel_bindings = Some(Vec::from(channel_bindings));
+        self
+    }
+
+    /// Whether to keep credentials the client delegates, so they are
+    /// available from `ServerCtx::take_delegated_cred`. The default is to
+    /// keep them, if you don't need them set this to false and they
+    /// are released immediately.
+    #[must_use]
+    pub fn accept_delegation(mut self, accept: bool) -> Self {
+        self.accept_delegation = accept;
+        self
+    }
+
+    /// Fail the context with `GSS_S_BAD_MECH` when it completes if
+    /// the negotiated mechanism isn't one of `mechs`. With SPNEGO
+    /// this is the mechanism SPNEGO negotiated, e.g. `GSS_MECH_KRB5`
+    /// or `GSS_MECH_NTLM`. To stop SPNEGO offering a mechanism in the
+    /// first place use `Cred::set_neg_mechs` on the acceptor
+    /// credential.
+    #[must_use]
+    pub fn allowed_mechs(mut self, mechs: &[&Oid]) -> Self {
+        self.allowed_mechs = Some(mechs.iter().map(|m| OwnedOid::from(*m)).collect());
+        self
+    }
+
+    /// Don't rely on gssapi for replay detection, for stateless
+    /// servers behind a load balancer, where the per process replay
+    /// cache of one instance can't see tokens sent to another.
+    /// `GSS_C_REPLAY_FLAG` is never required, even if it is passed to
+    /// `require_flags`.
+    ///
+    /// The server must then detect replays itself, e.g. by keeping
+    /// the authenticators it has seen in a shared store. This only
+    /// affects the policy of this context, the replay cache used to
+    /// accept krb5 tickets is configured in the kerberos library, for
+    /// MIT with `KRB5RCACHETYPE=none` or `rcache_type` in krb5.conf.
+    #[must_use]
+    pub fn disable_replay_detection(mut self) -> Self {
+        self.replay_detection = false;
+        self
+    }
+
+    /// Build the server context. You must then call `step` until it
+    /// is fully initialized.
+    pub fn build(self) -> ServerCtx {
+        let mut required_flags = self.required_flags;
+        if !self.replay_detection {
+            required_flags.remove(CtxFlags::GSS_C_REPLAY_FLAG);
+        }
+        ServerCtx {
+            ctx: ptr::null_mut(),
+            cred: self.cred.map(Arc::new),
+            delegated_cred: None,
+            flags: CtxFlags::empty(),
+            state: ServerCtxState::Uninitialized,
+            required_flags,
+            channel_bindings: self.channel_bindings,
+            accept_delegation: self.accept_delegation,
+            allowed_mechs: self.allowed_mechs,
+        }
+    }
+}
+
 /// The server side of a security context
 #[derive(Debug)]
 pub struct ServerCtx {
     ctx: gss_ctx_id_t,
-    cred: Cred,
+    // shared with the copy step_async runs on the blocking pool
+    cred: Option<Arc<Cred>>,
     delegated_cred: Option<Cred>,
     flags: CtxFlags,
     state: ServerCtxState,
+    required_flags: CtxFlags,
+    channel_bindings: Option<Vec<u8>>,
+    accept_delegation: bool,
+    allowed_mechs: Option<Vec<OwnedOid>>,
 }
 
 impl Drop for ServerCtx {
@@ -504,13 +1047,15 @@ impl ServerCtx {
     /// fully initialized. The mechanism is not specified because it
     /// is dictated by the client.
     pub fn new(cred: Cred) -> ServerCtx {
-        ServerCtx {
-            ctx: ptr::null_mut(),
-            cred,
-            delegated_cred: None,
-            flags: CtxFlags::empty(),
-            state: ServerCtxState::Uninitialized,
-        }
+        ServerCtxBuilder::new(Some(cred)).build()
+    }
+
+    /// Create a new uninitialized server context that accepts with
+    /// the default acceptor credential. The mechanism picks the key
+    /// matching the client's token, e.g. any service principal in
+    /// the keytab named by `KRB5_KTNAME`.
+    pub fn new_default() -> ServerCtx {
+        ServerCtxBuilder::new(None).build()
     }
 
     /// Perform 1 step in the initialization of the server context,
@@ -520,11 +1065,31 @@ impl ServerCtx {
     /// return a token that needs to be sent to the client and fed to
     /// `ClientCtx::step`.
     pub fn step(&mut self, tok: &[u8]) -> Result<Option<Buf>, Error> {
+        self.step_with_channel_bindings(tok, None)
+    }
+
+    /// The same as `step`, but the client must have passed the same
+    /// `channel_bindings` to `ClientCtx::step` or the context will
+    /// fail with `GSS_S_BAD_BINDINGS`, see
+    /// `ServerCtxBuilder::channel_bindings`. This overrides the
+    /// channel bindings set there, `None` uses them.
+    pub fn step_with_channel_bindings(
+        &mut self,
+        tok: &[u8],
+        channel_bindings: Option<&[u8]>,
+    ) -> Result<Option<Buf>, Error> {
         match self.state {
             ServerCtxState::Uninitialized | ServerCtxState::Partial => (),
             ServerCtxState::Failed(e) => return Err(e),
             ServerCtxState::Complete => return Ok(None),
         }
+        let channel_bindings = channel_bindings.or(self.channel_bindings.as_deref());
+        let bound = channel_bindings.is_some();
+        let mut cbs = channel_bindings.map(bindings_struct);
+        let bindings = cbs.as_mut().map_or(
+            ptr::null_mut::<gss_channel_bindings_struct>(),
+            |cbs| cbs as gss_channel_bindings_t,
+        );
         let mut minor = GSS_S_COMPLETE;
         let mut tok = BufRef::from(tok);
         let mut out_tok = Buf::empty();
@@ -534,9 +1099,12 @@ impl ServerCtx {
             gss_accept_sec_context(
                 &mut minor as *mut OM_uint32,
                 &mut self.ctx as *mut gss_ctx_id_t,
-                self.cred.to_c(),
+                match &self.cred {
+                    None => NO_CRED,
+                    Some(cred) => cred.to_c(),
+                },
                 tok.to_c(),
-                ptr::null_mut::<gss_channel_bindings_struct>(),
+                bindings,
                 ptr::null_mut::<gss_name_t>(),
                 ptr::null_mut::<gss_OID>(),
                 out_tok.to_c(),
@@ -545,7 +1113,9 @@ impl ServerCtx {
                 &mut delegated_cred as *mut gss_cred_id_t,
             )
         };
-        if !delegated_cred.is_null() {
+        if !delegated_cred.is_null() && !self.accept_delegation {
+            drop(unsafe { Cred::from_c(delegated_cred) });
+        } else if !delegated_cred.is_null() {
             match &self.delegated_cred {
                 None => unsafe {
                     self.delegated_cred = Some(Cred::from_c(delegated_cred));
@@ -557,9 +1127,9 @@ impl ServerCtx {
                 },
             }
         }
-        if let Some(new_flags) = CtxFlags::from_bits(flag_bits) {
-            self.flags.insert(new_flags);
-        }
+        // keep bits we don't have names for (e.g. MIT's
+        // GSS_C_CHANNEL_BOUND_FLAG) rather than dropping every flag
+        self.flags.insert(CtxFlags::from_bits_retain(flag_bits));
         if gss_error(major) > 0 {
             let e = Error {
                 major: MajorFlags::from_bits_retain(major),
@@ -570,6 +1140,12 @@ impl ServerCtx {
         } else if major & _GSS_S_CONTINUE_NEEDED > 0 {
             self.state = ServerCtxState::Partial;
             Ok(Some(out_tok))
+        } else if let Err(e) = self.check_policy(bound) {
+            // don't leave a usable context lying around
+            delete_ctx(self.ctx);
+            self.ctx = ptr::null_mut();
+            self.state = ServerCtxState::Failed(e);
+            Err(e)
         } else {
             self.state = ServerCtxState::Complete;
             if out_tok.len() > 0 {
@@ -579,6 +1155,72 @@ impl ServerCtx {
             }
         }
     }
+
+    /// Check the established context meets the builder's requirements
+    fn check_policy(&self, bound: bool) -> Result<(), Error> {
+        if !self.flags.contains(self.required_flags) {
+            return Err(Error {
+                major: MajorFlags::GSS_S_UNAUTHORIZED,
+                minor: 0,
+            });
+        }
+        // acceptors let clients that sent no bindings through
+        if bound && !self.flags.contains(CtxFlags::GSS_C_CHANNEL_BOUND_FLAG) {
+            return Err(Error {
+                major: MajorFlags::GSS_S_BAD_BINDINGS,
+                minor: 0,
+            });
+        }
+        if let Some(allowed) = &self.allowed_mechs {
+            let mech = unsafe { mechanism(self.ctx)? };
+            if !allowed.iter().any(|m| **m == *mech) {
+                return Err(Error {
+                    major: MajorFlags::GSS_S_BAD_MECH,
+                    minor: 0,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the flags gssapi reported when the context was
+    /// accepted, e.g. to check that mutual authentication was
+    /// actually performed rather than just requested. Returns `None`
+    /// if the context is not yet complete. Bits without a name in
+    /// `CtxFlags` are preserved, check them with `bits()`.
+    pub fn ret_flags(&self) -> Option<CtxFlags> {
+        match self.state {
+            ServerCtxState::Complete => Some(self.flags),
+            ServerCtxState::Failed(_)
+            | ServerCtxState::Partial
+            | ServerCtxState::Uninitialized => None,
+        }
+    }
+
+    /// Return the time remaining before the context expires, `None`
+    /// if it never expires, or zero if it already has, so a long
+    /// lived connection can authenticate again before `wrap` and
+    /// `unwrap` start failing with `GSS_S_CONTEXT_EXPIRED`.
+    pub fn time_remaining(&self) -> Result<Option<Duration>, Error> {
+        unsafe { time_remaining(self.ctx) }
+    }
+
+    /// Take the credentials the client delegated to us, if it set
+    /// `GSS_C_DELEG_FLAG` and the mechanism supports delegation.
+    pub fn take_delegated_cred(&mut self) -> Option<Cred> {
+        self.delegated_cred.take()
+    }
+
+    /// Map the authenticated client to the local account it should
+    /// run as, using the rules of the negotiated mechanism (for krb5
+    /// the `auth_to_local` rules in krb5.conf). This is the same as
+    /// `source_name()?.localname(Some(mechanism()?))`.
+    #[cfg(feature = "localname")]
+    pub fn localname(&mut self) -> Result<Buf, Error> {
+        let name = self.source_name()?;
+        let mech = self.mechanism()?;
+        name.localname(Some(mech))
+    }
 }
 
 impl SecurityContext for ServerCtx {
@@ -586,6 +1228,52 @@ impl SecurityContext for ServerCtx {
         unsafe { wrap(self.ctx, encrypt, msg) }
     }
 
+    fn encrypt(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { encrypt(self.ctx, msg) }
+    }
+
+    fn sign(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx, msg) }
+    }
+
+    fn wrap_into_slice(
+        &mut self,
+        encrypt: bool,
+        msg: &[u8],
+        out: &mut [u8],
+    ) -> Result<(usize, bool), Error> {
+        unsafe { wrap_into_slice(self.ctx, encrypt, msg, out) }
+    }
+
+    fn wrap_size_limit(
+        &mut self,
+        encrypt: bool,
+        max_output: usize,
+    ) -> Result<usize, Error> {
+        unsafe { wrap_size_limit(self.ctx, encrypt, max_output) }
+    }
+
+    fn wrap_into(
+        &mut self,
+        encrypt: bool,
+        msg: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<usize, Error> {
+        unsafe { wrap_into(self.ctx, encrypt, msg, out) }
+    }
+
+    fn get_mic(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx, msg) }
+    }
+
+    fn get_mic_into(&mut self, msg: &[u8], out: &mut Vec<u8>) -> Result<usize, Error> {
+        unsafe { get_mic_into(self.ctx, msg, out) }
+    }
+
+    fn verify_mic(&mut self, msg: &[u8], mic: &[u8]) -> Result<(), Error> {
+        unsafe { verify_mic(self.ctx, msg, mic) }
+    }
+
     #[cfg(feature = "iov")]
     fn wrap_iov(&mut self, encrypt: bool, msg: &mut [GssIov]) -> Result<(), Error> {
         unsafe { wrap_iov(self.ctx, encrypt, msg) }
@@ -604,6 +1292,14 @@ impl SecurityContext for ServerCtx {
         unsafe { unwrap(self.ctx, msg) }
     }
 
+    fn unwrap_with_status(&mut self, msg: &[u8]) -> Result<(Buf, MajorFlags), Error> {
+        unsafe { unwrap_with_status(self.ctx, msg) }
+    }
+
+    fn unwrap_into(&mut self, msg: &[u8], out: &mut Vec<u8>) -> Result<Unwrapped, Error> {
+        unsafe { unwrap_into(self.ctx, msg, out) }
+    }
+
     #[cfg(feature = "iov")]
     fn unwrap_iov(&mut self, msg: &mut [GssIov]) -> Result<(), Error> {
         unsafe { unwrap_iov(self.ctx, msg) }
@@ -641,6 +1337,18 @@ impl SecurityContext for ServerCtx {
         unsafe { open(self.ctx) }
     }
 
+    fn inquire_by_oid(&mut self, oid: &Oid) -> Result<BufSet, Error> {
+        unsafe { inquire_by_oid(self.ctx, oid) }
+    }
+
+    fn can_wrap(&self) -> bool {
+        self.is_complete() && unsafe { negotiated(self.ctx, CtxFlags::GSS_C_INTEG_FLAG) }
+    }
+
+    fn can_encrypt(&self) -> bool {
+        self.is_complete() && unsafe { negotiated(self.ctx, CtxFlags::GSS_C_CONF_FLAG) }
+    }
+
     fn is_complete(&self) -> bool {
         match self.state {
             ServerCtxState::Complete => true,
@@ -649,6 +1357,33 @@ impl SecurityContext for ServerCtx {
             | ServerCtxState::Uninitialized => false,
         }
     }
+
+    fn is_partial(&self) -> bool {
+        match self.state {
+            ServerCtxState::Partial => true,
+            ServerCtxState::Failed(_)
+            | ServerCtxState::Complete
+            | ServerCtxState::Uninitialized => false,
+        }
+    }
+
+    fn is_failed(&self) -> bool {
+        match self.state {
+            ServerCtxState::Failed(_) => true,
+            ServerCtxState::Complete
+            | ServerCtxState::Partial
+            | ServerCtxState::Uninitialized => false,
+        }
+    }
+
+    fn state(&self) -> CtxState {
+        match self.state {
+            ServerCtxState::Uninitialized => CtxState::Uninit,
+            ServerCtxState::Partial => CtxState::Partial,
+            ServerCtxState::Complete => CtxState::Complete,
+            ServerCtxState::Failed(_) => CtxState::Failed,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -663,8 +1398,9 @@ enum ClientCtxState {
 #[derive(Debug)]
 pub struct ClientCtx {
     ctx: gss_ctx_id_t,
-    cred: Option<Cred>,
-    target: Name,
+    // shared with the copy step_async runs on the blocking pool
+    cred: Option<Arc<Cred>>,
+    target: Arc<Name>,
     flags: CtxFlags,
     state: ClientCtxState,
     mech: Option<&'static Oid>,
@@ -693,8 +1429,8 @@ impl ClientCtx {
     ) -> ClientCtx {
         ClientCtx {
             ctx: ptr::null_mut(),
-            cred,
-            target,
+            cred: cred.map(Arc::new),
+            target: Arc::new(target),
             flags,
             state: ClientCtxState::Uninitialized,
             mech,
@@ -717,34 +1453,16 @@ impl ClientCtx {
         tok: Option<&[u8]>,
         channel_bindings: Option<&[u8]>,
     ) -> Result<Option<Buf>, Error> {
-        #[inline]
-        fn empty_buffer() -> gss_buffer_desc {
-            gss_buffer_desc {
-                length: 0,
-                value: ptr::null_mut(),
-            }
-        }
         match self.state {
             ClientCtxState::Uninitialized | ClientCtxState::Partial => (),
             ClientCtxState::Failed(e) => return Err(e),
             ClientCtxState::Complete => return Ok(None),
         };
-        let mut cbs = gss_channel_bindings_struct {
-            initiator_addrtype: 0,
-            initiator_address: empty_buffer(),
-            acceptor_addrtype: 0,
-            acceptor_address: empty_buffer(),
-            application_data: empty_buffer(),
-        };
-        let bindings = if let Some(cb) = channel_bindings {
-            cbs.application_data = gss_buffer_desc {
-                length: cb.len(),
-                value: cb.as_ptr() as *mut ffi::c_void,
-            };
-            &mut cbs as gss_channel_bindings_t
-        } else {
-            ptr::null_mut::<gss_channel_bindings_struct>()
-        };
+        let mut cbs = channel_bindings.map(bindings_struct);
+        let bindings = cbs.as_mut().map_or(
+            ptr::null_mut::<gss_channel_bindings_struct>(),
+            |cbs| cbs as gss_channel_bindings_t,
+        );
         let mut minor = GSS_S_COMPLETE;
         let mut tok = tok.map(BufRef::from);
         let mut out_tok = Buf::empty();
@@ -793,6 +1511,23 @@ impl ClientCtx {
             }
         }
     }
+
+    /// Throw away the security context, keeping the credentials,
+    /// target, flags, and mechanism, so the handshake can be done
+    /// again with `step`, e.g. after the context expired.
+    pub fn restart(&mut self) {
+        delete_ctx(self.ctx);
+        self.ctx = ptr::null_mut();
+        self.state = ClientCtxState::Uninitialized;
+    }
+
+    /// Return the time remaining before the context expires, `None`
+    /// if it never expires, or zero if it already has, so a long
+    /// lived connection can authenticate again before `wrap` and
+    /// `unwrap` start failing with `GSS_S_CONTEXT_EXPIRED`.
+    pub fn time_remaining(&self) -> Result<Option<Duration>, Error> {
+        unsafe { time_remaining(self.ctx) }
+    }
 }
 
 impl SecurityContext for ClientCtx {
@@ -800,6 +1535,52 @@ impl SecurityContext for ClientCtx {
         unsafe { wrap(self.ctx, encrypt, msg) }
     }
 
+    fn encrypt(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { encrypt(self.ctx, msg) }
+    }
+
+    fn sign(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx, msg) }
+    }
+
+    fn wrap_into_slice(
+        &mut self,
+        encrypt: bool,
+        msg: &[u8],
+        out: &mut [u8],
+    ) -> Result<(usize, bool), Error> {
+        unsafe { wrap_into_slice(self.ctx, encrypt, msg, out) }
+    }
+
+    fn wrap_size_limit(
+        &mut self,
+        encrypt: bool,
+        max_output: usize,
+    ) -> Result<usize, Error> {
+        unsafe { wrap_size_limit(self.ctx, encrypt, max_output) }
+    }
+
+    fn wrap_into(
+        &mut self,
+        encrypt: bool,
+        msg: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<usize, Error> {
+        unsafe { wrap_into(self.ctx, encrypt, msg, out) }
+    }
+
+    fn get_mic(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx, msg) }
+    }
+
+    fn get_mic_into(&mut self, msg: &[u8], out: &mut Vec<u8>) -> Result<usize, Error> {
+        unsafe { get_mic_into(self.ctx, msg, out) }
+    }
+
+    fn verify_mic(&mut self, msg: &[u8], mic: &[u8]) -> Result<(), Error> {
+        unsafe { verify_mic(self.ctx, msg, mic) }
+    }
+
     #[cfg(feature = "iov")]
     fn wrap_iov(&mut self, encrypt: bool, msg: &mut [GssIov]) -> Result<(), Error> {
         unsafe { wrap_iov(self.ctx, encrypt, msg) }
@@ -818,6 +1599,14 @@ impl SecurityContext for ClientCtx {
         unsafe { unwrap(self.ctx, msg) }
     }
 
+    fn unwrap_with_status(&mut self, msg: &[u8]) -> Result<(Buf, MajorFlags), Error> {
+        unsafe { unwrap_with_status(self.ctx, msg) }
+    }
+
+    fn unwrap_into(&mut self, msg: &[u8], out: &mut Vec<u8>) -> Result<Unwrapped, Error> {
+        unsafe { unwrap_into(self.ctx, msg, out) }
+    }
+
     #[cfg(feature = "iov")]
     fn unwrap_iov(&mut self, msg: &mut [GssIov]) -> Result<(), Error> {
         unsafe { unwrap_iov(self.ctx, msg) }
@@ -855,6 +1644,18 @@ impl SecurityContext for ClientCtx {
         unsafe { open(self.ctx) }
     }
 
+    fn inquire_by_oid(&mut self, oid: &Oid) -> Result<BufSet, Error> {
+        unsafe { inquire_by_oid(self.ctx, oid) }
+    }
+
+    fn can_wrap(&self) -> bool {
+        self.is_complete() && unsafe { negotiated(self.ctx, CtxFlags::GSS_C_INTEG_FLAG) }
+    }
+
+    fn can_encrypt(&self) -> bool {
+        self.is_complete() && unsafe { negotiated(self.ctx, CtxFlags::GSS_C_CONF_FLAG) }
+    }
+
     fn is_complete(&self) -> bool {
         match self.state {
             ClientCtxState::Complete => true,
@@ -863,4 +1664,558 @@ impl SecurityContext for ClientCtx {
             | ClientCtxState::Uninitialized => false,
         }
     }
+
+    fn is_partial(&self) -> bool {
+        match self.state {
+            ClientCtxState::Partial => true,
+            ClientCtxState::Failed(_)
+            | ClientCtxState::Complete
+            | ClientCtxState::Uninitialized => false,
+        }
+    }
+
+    fn is_failed(&self) -> bool {
+        match self.state {
+            ClientCtxState::Failed(_) => true,
+            ClientCtxState::Complete
+            | ClientCtxState::Partial
+            | ClientCtxState::Uninitialized => false,
+        }
+    }
+
+    fn state(&self) -> CtxState {
+        match self.state {
+            ClientCtxState::Uninitialized => CtxState::Uninit,
+            ClientCtxState::Partial => CtxState::Partial,
+            ClientCtxState::Complete => CtxState::Complete,
+            ClientCtxState::Failed(_) => CtxState::Failed,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Protected {
+    Client(ClientCtx),
+    Server(ServerCtx),
+}
+
+/// A completed security context that can only be used to protect
+/// messages, made with `ClientCtx::into_protector` or
+/// `ServerCtx::into_protector`, so the handshake can't accidentally
+/// be stepped again.
+#[derive(Debug)]
+pub struct GssProtector(Protected);
+
+impl GssProtector {
+    fn ctx(&self) -> gss_ctx_id_t {
+        match &self.0 {
+            Protected::Client(c) => c.ctx,
+            Protected::Server(s) => s.ctx,
+        }
+    }
+
+    fn new(ctx: Protected, state: CtxState) -> Result<GssProtector, Error> {
+        if state == CtxState::Complete {
+            Ok(GssProtector(ctx))
+        } else {
+            Err(Error {
+                major: MajorFlags::GSS_S_NO_CONTEXT,
+                minor: 0,
+            })
+        }
+    }
+
+    /// See `SecurityContext::wrap`
+    pub fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { wrap(self.ctx(), encrypt, msg) }
+    }
+
+    /// See `SecurityContext::encrypt`
+    pub fn encrypt(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { encrypt(self.ctx(), msg) }
+    }
+
+    /// See `SecurityContext::wrap_into`
+    pub fn wrap_into(
+        &mut self,
+        encrypt: bool,
+        msg: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<usize, Error> {
+        unsafe { wrap_into(self.ctx(), encrypt, msg, out) }
+    }
+
+    /// See `SecurityContext::wrap_size_limit`
+    pub fn wrap_size_limit(
+        &mut self,
+        encrypt: bool,
+        max_output: usize,
+    ) -> Result<usize, Error> {
+        unsafe { wrap_size_limit(self.ctx(), encrypt, max_output) }
+    }
+
+    /// See `SecurityContext::unwrap`
+    pub fn unwrap(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { unwrap(self.ctx(), msg) }
+    }
+
+    /// See `SecurityContext::unwrap_into`
+    pub fn unwrap_into(
+        &mut self,
+        msg: &[u8],
+        out: &mut Vec<u8>,
+    ) -> Result<Unwrapped, Error> {
+        unsafe { unwrap_into(self.ctx(), msg, out) }
+    }
+
+    /// See `SecurityContext::get_mic`
+    pub fn get_mic(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        unsafe { get_mic(self.ctx(), msg) }
+    }
+
+    /// See `SecurityContext::verify_mic`
+    pub fn verify_mic(&mut self, msg: &[u8], mic: &[u8]) -> Result<(), Error> {
+        unsafe { verify_mic(self.ctx(), msg, mic) }
+    }
+}
+
+impl ClientCtx {
+    /// Give up the handshake half of a complete context, keeping
+    /// only message protection. Fails with `GSS_S_NO_CONTEXT` if the
+    /// context isn't `CtxState::Complete`.
+    pub fn into_protector(self) -> Result<GssProtector, Error> {
+        let state = self.state();
+        GssProtector::new(Protected::Client(self), state)
+    }
+}
+
+impl ServerCtx {
+    /// Give up the handshake half of a complete context, keeping
+    /// only message protection. Call `take_delegated_cred` first if
+    /// you need it. Fails with `GSS_S_NO_CONTEXT` if the context
+    /// isn't `CtxState::Complete`.
+    pub fn into_protector(self) -> Result<GssProtector, Error> {
+        let state = self.state();
+        GssProtector::new(Protected::Server(self), state)
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod async_step {
+    use super::*;
+    use std::mem;
+    use tokio::task::spawn_blocking;
+
+    /// The state a context is left in if a `step_async` future is
+    /// dropped before the step finishes. The step itself runs to
+    /// completion on the blocking pool, and the context is then
+    /// deleted.
+    const DETACHED: Error = Error {
+        major: MajorFlags::GSS_S_NO_CONTEXT,
+        minor: 0,
+    };
+
+    impl ClientCtx {
+        /// Move the context handle and state into a copy that shares
+        /// everything else, leaving this one detached
+        fn detach(&mut self) -> ClientCtx {
+            let ctx = ClientCtx {
+                ctx: self.ctx,
+                cred: self.cred.clone(),
+                target: self.target.clone(),
+                flags: self.flags,
+                state: mem::replace(&mut self.state, ClientCtxState::Failed(DETACHED)),
+                mech: self.mech,
+            };
+            self.ctx = ptr::null_mut();
+            ctx
+        }
+
+        fn attach(&mut self, mut ctx: ClientCtx) {
+            self.ctx = mem::replace(&mut ctx.ctx, ptr::null_mut());
+            self.state = mem::replace(&mut ctx.state, ClientCtxState::Uninitialized);
+        }
+
+        /// The same as `step`, but run on tokio's blocking thread
+        /// pool, since a step may involve talking to the KDC.
+        ///
+        /// If the future is dropped before it completes the context
+        /// is left failed, it's never left half way through a step.
+        /// The step runs to completion anyway and its context is
+        /// then deleted. The credentials, target, flags and
+        /// mechanism are kept, so `restart` and another `step` begin
+        /// the handshake again.
+        pub async fn step_async(
+            &mut self,
+            tok: Option<&[u8]>,
+            channel_bindings: Option<&[u8]>,
+        ) -> Result<Option<Buf>, Error> {
+            let tok = tok.map(Vec::from);
+            let channel_bindings = channel_bindings.map(Vec::from);
+            let mut ctx = self.detach();
+            let res = spawn_blocking(move || {
+                let res = ctx.step(tok.as_deref(), channel_bindings.as_deref());
+                (ctx, res)
+            })
+            .await;
+            match res {
+                Err(_) => Err(DETACHED),
+                Ok((ctx, res)) => {
+                    self.attach(ctx);
+                    res
+                }
+            }
+        }
+    }
+
+    impl ServerCtx {
+        /// Move the context handle and the state a step changes into
+        /// a copy that shares everything else, leaving this one
+        /// detached
+        fn detach(&mut self) -> ServerCtx {
+            let ctx = ServerCtx {
+                ctx: self.ctx,
+                cred: self.cred.clone(),
+                delegated_cred: self.delegated_cred.take(),
+                flags: mem::replace(&mut self.flags, CtxFlags::empty()),
+                state: mem::replace(&mut self.state, ServerCtxState::Failed(DETACHED)),
+                required_flags: self.required_flags,
+                channel_bindings: self.channel_bindings.clone(),
+                accept_delegation: self.accept_delegation,
+                allowed_mechs: self.allowed_mechs.clone(),
+            };
+            self.ctx = ptr::null_mut();
+            ctx
+        }
+
+        fn attach(&mut self, mut ctx: ServerCtx) {
+            self.ctx = mem::replace(&mut ctx.ctx, ptr::null_mut());
+            self.delegated_cred = ctx.delegated_cred.take();
+            self.flags = ctx.flags;
+            self.state = mem::replace(&mut ctx.state, ServerCtxState::Uninitialized);
+        }
+
+        /// The same as `step`, but run on tokio's blocking thread
+        /// pool.
+        ///
+        /// If the future is dropped before it completes the context
+        /// is left failed, it's never left half way through a step.
+        pub async fn step_async(&mut self, tok: &[u8]) -> Result<Option<Buf>, Error> {
+            let tok = Vec::from(tok);
+            let mut ctx = self.detach();
+            let res = spawn_blocking(move || {
+                let res = ctx.step(&tok);
+                (ctx, res)
+            })
+            .await;
+            match res {
+                Err(_) => Err(DETACHED),
+                Ok((ctx, res)) => {
+                    self.attach(ctx);
+                    res
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "mit_kerberos")]
+mod krb5 {
+    use super::*;
+    use libgssapi_sys::{
+        gss_krb5_export_lucid_sec_context, gss_krb5_free_lucid_sec_context,
+        gss_krb5_get_tkt_flags, gss_krb5_lucid_context_v1, gss_krb5_lucid_key_t,
+        gss_set_sec_context_option, krb5_flags,
+    };
+    use std::{fmt, slice};
+
+    bitflags! {
+        /// The flags of the kerberos ticket a context was
+        /// established with
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub struct TktFlags: u32 {
+            const FORWARDABLE = 0x40000000;
+            const FORWARDED = 0x20000000;
+            const PROXIABLE = 0x10000000;
+            const PROXY = 0x08000000;
+            const MAY_POSTDATE = 0x04000000;
+            const POSTDATED = 0x02000000;
+            const INVALID = 0x01000000;
+            const RENEWABLE = 0x00800000;
+            const INITIAL = 0x00400000;
+            const PRE_AUTH = 0x00200000;
+            const HW_AUTH = 0x00100000;
+            const TRANSIT_POLICY_CHECKED = 0x00080000;
+            const OK_AS_DELEGATE = 0x00040000;
+            const ENC_PA_REP = 0x00010000;
+            const ANONYMOUS = 0x00008000;
+        }
+    }
+
+    /// A session key from a lucid context
+    #[derive(Clone, PartialEq, Eq)]
+    pub struct LucidKey {
+        /// The kerberos encryption type, e.g. 18 for
+        /// aes256-cts-hmac-sha1-96
+        pub enctype: u32,
+        /// The key
+        pub key: Vec<u8>,
+    }
+
+    // don't put keys in logs
+    impl fmt::Debug for LucidKey {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("LucidKey")
+                .field("enctype", &self.enctype)
+                .field("len", &self.key.len())
+                .finish()
+        }
+    }
+
+    impl LucidKey {
+        unsafe fn from_c(key: &gss_krb5_lucid_key_t) -> LucidKey {
+            let key_data = if key.data.is_null() {
+                Vec::new()
+            } else {
+                slice::from_raw_parts(key.data as *const u8, key.length as usize).to_vec()
+            };
+            LucidKey {
+                enctype: key.type_,
+                key: key_data,
+            }
+        }
+    }
+
+    /// The keys of a lucid context, which depend on the token format
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum LucidKeys {
+        /// The original rfc1964 tokens (DES and RC4)
+        Rfc1964 {
+            sign_alg: u32,
+            seal_alg: u32,
+            ctx_key: LucidKey,
+        },
+        /// The rfc4121 tokens (AES and newer)
+        Cfx {
+            ctx_key: LucidKey,
+            acceptor_subkey: Option<LucidKey>,
+        },
+    }
+
+    /// Everything needed to continue a krb5 security context outside
+    /// of gssapi, e.g. in a kernel NFS or SMB client (version 1 of
+    /// MIT's lucid context)
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct LucidContext {
+        /// True if this side initiated the context
+        pub initiate: bool,
+        /// When the context expires, in seconds since the epoch
+        pub endtime: u32,
+        /// The next sequence number to send
+        pub send_seq: u64,
+        /// The next sequence number expected
+        pub recv_seq: u64,
+        /// The session keys
+        pub keys: LucidKeys,
+    }
+
+    /// Export `ctx` as a version 1 lucid context. MIT deletes the
+    /// gssapi context in the process.
+    unsafe fn export_lucid(ctx: &mut gss_ctx_id_t) -> Result<LucidContext, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut lucid = ptr::null_mut::<ffi::c_void>();
+        let major = gss_krb5_export_lucid_sec_context(
+            &mut minor as *mut OM_uint32,
+            ctx as *mut gss_ctx_id_t,
+            1,
+            &mut lucid as *mut *mut ffi::c_void,
+        );
+        if major != GSS_S_COMPLETE {
+            return Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            });
+        }
+        let c = &*(lucid as *const gss_krb5_lucid_context_v1);
+        let keys = if c.protocol == 0 {
+            LucidKeys::Rfc1964 {
+                sign_alg: c.rfc1964_kd.sign_alg,
+                seal_alg: c.rfc1964_kd.seal_alg,
+                ctx_key: LucidKey::from_c(&c.rfc1964_kd.ctx_key),
+            }
+        } else {
+            LucidKeys::Cfx {
+                ctx_key: LucidKey::from_c(&c.cfx_kd.ctx_key),
+                acceptor_subkey: if c.cfx_kd.have_acceptor_subkey != 0 {
+                    Some(LucidKey::from_c(&c.cfx_kd.acceptor_subkey))
+                } else {
+                    None
+                },
+            }
+        };
+        let res = LucidContext {
+            initiate: c.initiate != 0,
+            endtime: c.endtime,
+            send_seq: c.send_seq,
+            recv_seq: c.recv_seq,
+            keys,
+        };
+        let mut minor = GSS_S_COMPLETE;
+        gss_krb5_free_lucid_sec_context(&mut minor as *mut OM_uint32, lucid);
+        Ok(res)
+    }
+
+    unsafe fn set_option(
+        ctx: &mut gss_ctx_id_t,
+        oid: &Oid,
+        value: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut value = value.map(BufRef::from);
+        let major = gss_set_sec_context_option(
+            &mut minor as *mut OM_uint32,
+            ctx as *mut gss_ctx_id_t,
+            oid.to_c(),
+            match value {
+                None => ptr::null_mut::<gss_buffer_desc>(),
+                Some(ref mut v) => v.to_c(),
+            },
+        );
+        if major == GSS_S_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            })
+        }
+    }
+
+    impl ClientCtx {
+        /// Export the complete krb5 context with its session keys as
+        /// a lucid context, consuming it, because gssapi can't use
+        /// the context after the keys are handed out. This is an MIT
+        /// extension.
+        pub fn export_lucid_context(mut self) -> Result<LucidContext, Error> {
+            unsafe { export_lucid(&mut self.ctx) }
+        }
+
+        /// Set the mechanism specific option `oid` on the context,
+        /// with an optional `value` whose meaning depends on the
+        /// option. The context must already exist, i.e. at least one
+        /// step has been taken. Options the mechanism doesn't know
+        /// fail with `GSS_S_UNAVAILABLE`. This is an MIT extension.
+        pub fn set_option(
+            &mut self,
+            oid: &Oid,
+            value: Option<&[u8]>,
+        ) -> Result<(), Error> {
+            unsafe { set_option(&mut self.ctx, oid, value) }
+        }
+    }
+
+    impl ServerCtx {
+        /// Export the complete krb5 context with its session keys as
+        /// a lucid context, consuming it, because gssapi can't use
+        /// the context after the keys are handed out. This is an MIT
+        /// extension.
+        pub fn export_lucid_context(mut self) -> Result<LucidContext, Error> {
+            unsafe { export_lucid(&mut self.ctx) }
+        }
+
+        /// Set the mechanism specific option `oid` on the context,
+        /// with an optional `value` whose meaning depends on the
+        /// option. The context must already exist, i.e. at least one
+        /// step has been taken. Options the mechanism doesn't know
+        /// fail with `GSS_S_UNAVAILABLE`. This is an MIT extension.
+        pub fn set_option(
+            &mut self,
+            oid: &Oid,
+            value: Option<&[u8]>,
+        ) -> Result<(), Error> {
+            unsafe { set_option(&mut self.ctx, oid, value) }
+        }
+
+        /// Return the flags of the ticket the client authenticated
+        /// with, e.g. to require `PRE_AUTH`, or refuse `FORWARDED`
+        /// tickets. The context must be complete and use krb5. This
+        /// is an MIT extension.
+        pub fn krb5_tkt_flags(&self) -> Result<TktFlags, Error> {
+            let mut minor = GSS_S_COMPLETE;
+            let mut flags: krb5_flags = 0;
+            let major = unsafe {
+                gss_krb5_get_tkt_flags(
+                    &mut minor as *mut OM_uint32,
+                    self.ctx,
+                    &mut flags as *mut krb5_flags,
+                )
+            };
+            if major == GSS_S_COMPLETE {
+                Ok(TktFlags::from_bits_retain(flags as u32))
+            } else {
+                Err(Error {
+                    major: MajorFlags::from_bits_retain(major),
+                    minor,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(feature = "mit_kerberos")]
+pub use krb5::*;
+
+#[cfg(feature = "name_attributes")]
+mod pac {
+    use super::*;
+    use crate::name::NameAttribute;
+    use libgssapi_sys::_GSS_S_UNAVAILABLE;
+
+    const MSPAC: &[u8] = b"urn:mspac:";
+
+    fn authenticated(attr: NameAttribute) -> Result<NameAttribute, Error> {
+        if attr.authenticated {
+            Ok(attr)
+        } else {
+            Err(Error {
+                major: MajorFlags::GSS_S_UNAUTHORIZED,
+                minor: 0,
+            })
+        }
+    }
+
+    impl ServerCtx {
+        /// Return the raw MS-PAC the client's ticket carried, e.g. to
+        /// read its Active Directory group memberships, or `None` if
+        /// there isn't one. A PAC that the mechanism couldn't verify
+        /// is rejected with `GSS_S_UNAUTHORIZED`. The context must be
+        /// complete.
+        pub fn pac(&self) -> Result<Option<Buf>, Error> {
+            let name = unsafe { source_name(self.ctx)? };
+            match name.attribute(MSPAC) {
+                Ok(attr) => Ok(authenticated(attr)?
+                    .values
+                    .into_iter()
+                    .next()
+                    .map(|v| v.value)),
+                Err(e) if e.routine_error() == _GSS_S_UNAVAILABLE => Ok(None),
+                Err(e) => Err(e),
+            }
+        }
+
+        /// Return the individual buffers of the client's MS-PAC, e.g.
+        /// `urn:mspac:logon-info`, with their values, like `pac`. It
+        /// is empty if there is no PAC.
+        pub fn pac_buffers(&self) -> Result<Vec<(Buf, NameAttribute)>, Error> {
+            let name = unsafe { source_name(self.ctx)? };
+            let mut res = Vec::new();
+            for attr in name.attributes()? {
+                if attr.len() > MSPAC.len() && attr.starts_with(MSPAC) {
+                    let value = authenticated(name.attribute(&attr)?)?;
+                    res.push((attr, value));
+                }
+            }
+            Ok(res)
+        }
+    }
 }
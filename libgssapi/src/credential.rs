@@ -1,13 +1,29 @@
-use crate::{error::{Error, MajorFlags, gss_error}, name::Name, oid::{OidSet, NO_OID_SET}};
+use crate::{
+    error::{Error, MajorFlags, gss_error},
+    name::Name,
+    oid::{Oid, OidSet, NO_OID_SET},
+    util::BufSet,
+};
 use libgssapi_sys::{
-    gss_OID_set, gss_acquire_cred, gss_cred_id_struct, gss_cred_id_t, gss_cred_usage_t,
-    gss_name_struct, gss_name_t, gss_release_cred, gss_inquire_cred, OM_uint32,
+    gss_OID_set, gss_acquire_cred, gss_add_cred, gss_cred_id_struct, gss_cred_id_t,
+    gss_cred_usage_t, gss_name_struct, gss_name_t, gss_release_cred, gss_inquire_cred,
+    gss_inquire_cred_by_oid, gss_set_neg_mechs, OM_uint32,
     GSS_C_ACCEPT, GSS_C_BOTH, GSS_C_INITIATE, GSS_S_COMPLETE, _GSS_C_INDEFINITE,
 };
+#[cfg(any(feature = "s4u", feature = "mit_kerberos"))]
+use libgssapi_sys::gss_acquire_cred_impersonate_name;
 #[cfg(feature = "s4u")]
-use libgssapi_sys::{gss_acquire_cred_impersonate_name, gss_inquire_cred_by_oid};
-#[cfg(feature = "s4u")]
-use crate::{oid::{GSS_NT_HOSTBASED_SERVICE, GSS_KRB5_GET_CRED_IMPERSONATOR}, util::BufSet};
+use crate::oid::{GSS_NT_HOSTBASED_SERVICE, GSS_KRB5_GET_CRED_IMPERSONATOR};
+#[cfg(feature = "mit_kerberos")]
+use libgssapi_sys::{
+    gss_acquire_cred_from, gss_buffer_desc, gss_export_cred, gss_import_cred,
+    gss_key_value_element_desc, gss_key_value_set_desc, gss_krb5_import_cred,
+    gss_set_cred_option, gss_store_cred_into,
+};
+#[cfg(feature = "mit_kerberos")]
+use crate::{oid::NO_OID, util::{Buf, BufRef}};
+#[cfg(feature = "mit_kerberos")]
+use std::ffi::{c_void, CString, NulError};
 use std::{ptr, fmt, time::Duration};
 
 pub(crate) const NO_CRED: gss_cred_id_t = ptr::null_mut();
@@ -65,6 +81,68 @@ impl CredUsage {
     }
 }
 
+/// A credential store, a set of key value pairs telling the
+/// mechanism where to find or put credentials instead of the
+/// defaults, e.g. `ccache` or `keytab`. This is an MIT extension, see
+/// gss_store_cred_into(3) for the keys it understands.
+#[cfg(feature = "mit_kerberos")]
+#[derive(Debug, Clone, Default)]
+pub struct CredStore(Vec<(String, String)>);
+
+#[cfg(feature = "mit_kerberos")]
+impl CredStore {
+    /// An empty store, meaning the defaults
+    pub fn new() -> CredStore {
+        CredStore(Vec::new())
+    }
+
+    /// A store using the credential cache `path`, e.g.
+    /// `FILE:/tmp/krb5cc_1234`
+    pub fn ccache(path: &str) -> CredStore {
+        CredStore::new().with("ccache", path)
+    }
+
+    /// A store using the keytab `path`, e.g. `FILE:/etc/app.keytab`
+    pub fn keytab(path: &str) -> CredStore {
+        CredStore::new().with("keytab", path)
+    }
+
+    /// Add `key` = `value` to the store, e.g.
+    /// `CredStore::ccache(cc).with("client_keytab", kt)`
+    #[must_use]
+    pub fn with(mut self, key: &str, value: &str) -> CredStore {
+        self.0.push((String::from(key), String::from(value)));
+        self
+    }
+
+    /// Call `f` with the store as a `gss_key_value_set_desc`, which
+    /// is only valid during the call. Keys or values containing a nul
+    /// byte fail with `GSS_S_FAILURE`.
+    fn with_c<T>(&self, f: impl FnOnce(&gss_key_value_set_desc) -> T) -> Result<T, Error> {
+        let strings = self
+            .0
+            .iter()
+            .map(|(k, v)| Ok((CString::new(k.as_str())?, CString::new(v.as_str())?)))
+            .collect::<Result<Vec<_>, NulError>>()
+            .map_err(|_| Error {
+                major: MajorFlags::GSS_S_FAILURE,
+                minor: 0,
+            })?;
+        let mut elements = strings
+            .iter()
+            .map(|(k, v)| gss_key_value_element_desc {
+                key: k.as_ptr(),
+                value: v.as_ptr(),
+            })
+            .collect::<Vec<_>>();
+        let store = gss_key_value_set_desc {
+            count: elements.len() as OM_uint32,
+            elements: elements.as_mut_ptr(),
+        };
+        Ok(f(&store))
+    }
+}
+
 /// gssapi credentials.
 pub struct Cred(gss_cred_id_t);
 
@@ -94,6 +172,61 @@ impl fmt::Debug for Cred {
     }
 }
 
+/// The lifetimes granted to a credential element added with
+/// `Cred::add`, `None` means indefinite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddedLifetimes {
+    pub initiate: Option<Duration>,
+    pub accept: Option<Duration>,
+}
+
+/// Add an element to `cred`, the result goes in `out`, or `cred`
+/// itself if `out` is null
+unsafe fn add_cred(
+    cred: gss_cred_id_t,
+    out: *mut gss_cred_id_t,
+    name: Option<&Name>,
+    mech: &Oid,
+    usage: CredUsage,
+    init_lifetime: Option<Duration>,
+    accept_lifetime: Option<Duration>,
+) -> Result<AddedLifetimes, Error> {
+    let time_req = |d: Option<Duration>| {
+        d.map(|d| d.as_secs() as u32).unwrap_or(_GSS_C_INDEFINITE)
+    };
+    let lifetime = |t: OM_uint32| match t {
+        _GSS_C_INDEFINITE => None,
+        secs => Some(Duration::from_secs(secs as u64)),
+    };
+    let mut minor = GSS_S_COMPLETE;
+    let mut init_rec: OM_uint32 = 0;
+    let mut accept_rec: OM_uint32 = 0;
+    let major = gss_add_cred(
+        &mut minor as *mut OM_uint32,
+        cred,
+        match name {
+            None => ptr::null_mut::<gss_name_struct>(),
+            Some(n) => n.to_c(),
+        },
+        mech.to_c(),
+        usage.to_c() as gss_cred_usage_t,
+        time_req(init_lifetime),
+        time_req(accept_lifetime),
+        out,
+        ptr::null_mut::<gss_OID_set>(),
+        &mut init_rec as *mut OM_uint32,
+        &mut accept_rec as *mut OM_uint32,
+    );
+    if major == GSS_S_COMPLETE {
+        Ok(AddedLifetimes {
+            initiate: lifetime(init_rec),
+            accept: lifetime(accept_rec),
+        })
+    } else {
+        Err(Error { major: MajorFlags::from_bits_retain(major), minor })
+    }
+}
+
 impl Cred {
     /// Acquire gssapi credentials for `name` or the default name,
     /// lasting for `time_req` or as long as possible, for the purpose
@@ -137,6 +270,97 @@ impl Cred {
         }
     }
 
+    /// Acquire the default credentials for `usage`, lasting as long
+    /// as possible, for use with the default mechanism.
+    pub fn acquire_default(usage: CredUsage) -> Result<Cred, Error> {
+        Cred::acquire(None, None, usage, None)
+    }
+
+    /// Acquire acceptor credentials for the service `name`, e.g. from
+    /// the keytab.
+    pub fn acquire_for_service(name: &Name) -> Result<Cred, Error> {
+        Cred::acquire(Some(name), None, CredUsage::Accept, None)
+    }
+
+    /// Acquire credentials for `name` or the default name from
+    /// `store` rather than the default ccache or keytab, for the
+    /// purpose of `usage`, lasting as long as possible, for use with
+    /// the default mechanism. This is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn acquire_from(
+        name: Option<&Name>,
+        store: &CredStore,
+        usage: CredUsage,
+    ) -> Result<Cred, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut cred = ptr::null_mut::<gss_cred_id_struct>();
+        let major = store.with_c(|store| unsafe {
+            gss_acquire_cred_from(
+                &mut minor as *mut OM_uint32,
+                match name {
+                    None => ptr::null_mut::<gss_name_struct>(),
+                    Some(n) => n.to_c(),
+                },
+                _GSS_C_INDEFINITE,
+                NO_OID_SET,
+                usage.to_c() as gss_cred_usage_t,
+                store as *const gss_key_value_set_desc,
+                &mut cred as *mut gss_cred_id_t,
+                ptr::null_mut::<gss_OID_set>(),
+                ptr::null_mut::<OM_uint32>(),
+            )
+        })?;
+        if major == GSS_S_COMPLETE {
+            Ok(Cred(cred))
+        } else {
+            Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            })
+        }
+    }
+
+    /// Wrap a krb5 credential cache and/or keytab opened with the
+    /// krb5 api (e.g. by a krb5 binding crate) as a gssapi credential.
+    /// `ccache` is a `krb5_ccache`, used to initiate, and `keytab` is
+    /// a `krb5_keytab`, used to accept; either may be null, but not
+    /// both. This is an MIT extension.
+    ///
+    /// # Safety
+    ///
+    /// The pointers must be null or valid handles from the same MIT
+    /// krb5 library this crate is linked with, and must stay open for
+    /// as long as the credential is used.
+    #[cfg(feature = "mit_kerberos")]
+    pub unsafe fn from_raw_krb5_cred(
+        ccache: *mut c_void,
+        keytab: *mut c_void,
+    ) -> Result<Cred, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut cred = ptr::null_mut::<gss_cred_id_struct>();
+        let major = gss_krb5_import_cred(
+            &mut minor as *mut OM_uint32,
+            ccache as _,
+            ptr::null_mut(),
+            keytab as _,
+            &mut cred as *mut gss_cred_id_t,
+        );
+        if major == GSS_S_COMPLETE {
+            Ok(Cred(cred))
+        } else {
+            Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            })
+        }
+    }
+
+    /// Acquire a credential for the user `name` on their behalf
+    /// (S4U2Self), without their involvement, using this service
+    /// credential, e.g. for constrained delegation from a web
+    /// application. The KDC must allow the service to do this.
+    /// `time_req` of `None` asks for the longest lifetime available,
+    /// and `desired_mechs` of `None` for the default mechanisms.
     #[cfg(feature = "s4u")]
     pub fn impersonate(
         &self,
@@ -144,6 +368,32 @@ impl Cred {
         time_req: Option<Duration>,
         usage: CredUsage,
         desired_mechs: Option<&OidSet>,
+    ) -> Result<Cred, Error> {
+        self.impersonate_c(name, time_req, usage, desired_mechs)
+    }
+
+    /// Acquire a credential for the user `name` on their behalf
+    /// (S4U2Self) using the service credential `impersonator`, for
+    /// the purpose of `usage`, lasting for `time_req` or as long as
+    /// possible, for use with the default mechanism. This is
+    /// `impersonate` for MIT builds without the `s4u` feature.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn acquire_impersonate(
+        impersonator: &Cred,
+        name: &Name,
+        time_req: Option<Duration>,
+        usage: CredUsage,
+    ) -> Result<Cred, Error> {
+        impersonator.impersonate_c(name, time_req, usage, None)
+    }
+
+    #[cfg(any(feature = "s4u", feature = "mit_kerberos"))]
+    fn impersonate_c(
+        &self,
+        name: &Name,
+        time_req: Option<Duration>,
+        usage: CredUsage,
+        desired_mechs: Option<&OidSet>,
     ) -> Result<Cred, Error> {
         let time_req = time_req.map(|d| d.as_secs() as u32).unwrap_or(_GSS_C_INDEFINITE);
         let mut minor = GSS_S_COMPLETE;
@@ -175,6 +425,211 @@ impl Cred {
         }
     }
 
+    /// Store this credential (e.g. one delegated to a server) into
+    /// the credential cache `ccache`, e.g. `FILE:/tmp/krb5cc_1234`,
+    /// rather than the default one, so a server handling many users
+    /// can keep each user's credentials separate. If `overwrite` is
+    /// false and `ccache` already holds credentials this will fail
+    /// with `GSS_S_DUPLICATE_ELEMENT`. This is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn store_into(
+        &self,
+        ccache: &str,
+        usage: CredUsage,
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let major = CredStore::ccache(ccache).with_c(|store| unsafe {
+            gss_store_cred_into(
+                &mut minor as *mut OM_uint32,
+                self.0,
+                usage.to_c() as gss_cred_usage_t,
+                NO_OID,
+                if overwrite { 1 } else { 0 },
+                0,
+                store as *const gss_key_value_set_desc,
+                ptr::null_mut::<gss_OID_set>(),
+                ptr::null_mut::<gss_cred_usage_t>(),
+            )
+        })?;
+        if major == GSS_S_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            })
+        }
+    }
+
+    /// Serialize this credential, so it can be passed to another
+    /// process on the same host and turned back into a credential
+    /// with `Cred::import`. The token may contain keys, so protect it
+    /// accordingly. This is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn export(&self) -> Result<Buf, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut token = Buf::empty();
+        let major = unsafe {
+            gss_export_cred(&mut minor as *mut OM_uint32, self.0, token.to_c())
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(token)
+        } else {
+            Err(Error { major: MajorFlags::from_bits_retain(major), minor })
+        }
+    }
+
+    /// Make a credential from a token produced by `Cred::export`.
+    /// This is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn import(token: &[u8]) -> Result<Cred, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut token = BufRef::from(token);
+        let mut cred = NO_CRED;
+        let major = unsafe {
+            gss_import_cred(
+                &mut minor as *mut OM_uint32,
+                token.to_c(),
+                &mut cred as *mut gss_cred_id_t,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(Cred(cred))
+        } else {
+            Err(Error { major: MajorFlags::from_bits_retain(major), minor })
+        }
+    }
+
+    /// Make an independent copy of this credential, rather than
+    /// another handle to the same one (`Cred` deliberately isn't
+    /// `Clone`). MIT doesn't have `gss_duplicate_cred`, neither in
+    /// its headers nor in `libgssapi_krb5`, so this goes through
+    /// `export` and `import`, which build a new credential from the
+    /// same ccache and keytab. Options set on this credential, e.g.
+    /// with `set_option`, aren't copied. This is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn deep_clone(&self) -> Result<Cred, Error> {
+        Cred::import(&self.export()?)
+    }
+
+    /// Restrict the mechanisms SPNEGO will negotiate using this
+    /// credential to `mechs`, e.g. to stop an acceptor from falling
+    /// back to NTLM when only kerberos is allowed. Clients offering
+    /// only other mechanisms fail to establish a context.
+    pub fn set_neg_mechs(&self, mechs: &OidSet) -> Result<(), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let major = unsafe {
+            gss_set_neg_mechs(&mut minor as *mut OM_uint32, self.0, mechs.to_c())
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error { major: MajorFlags::from_bits_retain(major), minor })
+        }
+    }
+
+    /// Ask the mechanism for credential information it doesn't
+    /// expose any other way, identified by `oid`, e.g.
+    /// `GSS_KRB5_GET_CRED_IMPERSONATOR`. What the buffers contain
+    /// depends on the oid. Mechanisms fail with `GSS_S_UNAVAILABLE`
+    /// for oids they don't know.
+    pub fn inquire_by_oid(&self, oid: &Oid) -> Result<BufSet, Error> {
+        let mut out = BufSet::empty();
+        let mut minor = GSS_S_COMPLETE;
+        let major = unsafe {
+            gss_inquire_cred_by_oid(
+                &mut minor as *mut OM_uint32,
+                self.0,
+                oid.to_c(),
+                out.to_c(),
+            )
+        };
+        if gss_error(major) > 0 {
+            Err(Error { major: MajorFlags::from_bits_retain(major), minor })
+        } else {
+            Ok(out)
+        }
+    }
+
+    /// Set the mechanism specific option `oid` on this credential,
+    /// with an optional `value` whose meaning depends on the option,
+    /// e.g. before using it to establish a context. Options the
+    /// mechanism doesn't know fail with `GSS_S_UNAVAILABLE`. This is
+    /// an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn set_option(&mut self, oid: &Oid, value: Option<&[u8]>) -> Result<(), Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut value = value.map(BufRef::from);
+        let major = unsafe {
+            gss_set_cred_option(
+                &mut minor as *mut OM_uint32,
+                &mut self.0 as *mut gss_cred_id_t,
+                oid.to_c(),
+                match value {
+                    None => ptr::null_mut::<gss_buffer_desc>(),
+                    Some(ref mut v) => v.to_c(),
+                },
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error { major: MajorFlags::from_bits_retain(major), minor })
+        }
+    }
+
+    /// Make a new credential holding the elements of this one plus
+    /// one for `mech`, e.g. so one credential can accept both raw
+    /// krb5 and SPNEGO, for `name` or the default name. Lifetimes of
+    /// `None` ask for as long as possible, the ones granted are
+    /// returned with the credential.
+    pub fn add(
+        &self,
+        name: Option<&Name>,
+        mech: &Oid,
+        usage: CredUsage,
+        init_lifetime: Option<Duration>,
+        accept_lifetime: Option<Duration>,
+    ) -> Result<(Cred, AddedLifetimes), Error> {
+        let mut cred = NO_CRED;
+        let lifetimes = unsafe {
+            add_cred(
+                self.0,
+                &mut cred as *mut gss_cred_id_t,
+                name,
+                mech,
+                usage,
+                init_lifetime,
+                accept_lifetime,
+            )?
+        };
+        Ok((Cred(cred), lifetimes))
+    }
+
+    /// The same as `add`, but the element is added to this
+    /// credential. Not every implementation supports this.
+    pub fn add_in_place(
+        &mut self,
+        name: Option<&Name>,
+        mech: &Oid,
+        usage: CredUsage,
+        init_lifetime: Option<Duration>,
+        accept_lifetime: Option<Duration>,
+    ) -> Result<AddedLifetimes, Error> {
+        unsafe {
+            add_cred(
+                self.0,
+                ptr::null_mut::<gss_cred_id_t>(),
+                name,
+                mech,
+                usage,
+                init_lifetime,
+                accept_lifetime,
+            )
+        }
+    }
+
     pub(crate) unsafe fn from_c(cred: gss_cred_id_t) -> Cred {
         Cred(cred)
     }
@@ -252,26 +707,12 @@ impl Cred {
     /// Return the proxy service associated with this credential
     pub fn proxy(&self) -> Result<Option<Name>, Error> {
         #[cfg(feature = "s4u")]
-        unsafe {
-            let mut out = BufSet::empty();
-            let mut minor: u32 = 0;
-            let major = gss_inquire_cred_by_oid(
-                &mut minor as *mut OM_uint32,
-                self.0,
-                GSS_KRB5_GET_CRED_IMPERSONATOR.to_c(),
-                out.to_c(),
-            );
-            if gss_error(major) > 0 {
-                Err(Error {
-                    major: MajorFlags::from_bits_retain(major),
-                    minor,
-                })
+        {
+            let out = self.inquire_by_oid(&GSS_KRB5_GET_CRED_IMPERSONATOR)?;
+            if let Some(name) = out.get(0) {
+                Name::new(name, Some(&GSS_NT_HOSTBASED_SERVICE)).map(Into::into)
             } else {
-                if let Some(name) = out.first() {
-                    Name::new(name, Some(&GSS_NT_HOSTBASED_SERVICE)).map(Into::into)
-                } else {
-                    Ok(None)
-                }
+                Ok(None)
             }
         }
         #[cfg(not(feature = "s4u"))]
@@ -289,6 +730,33 @@ impl Cred {
         }
     }
 
+    /// Return the time remaining before this credential expires, or
+    /// `None` if it never expires. Unlike `lifetime` the indefinite
+    /// sentinel isn't reported as a (very long) duration.
+    pub fn remaining_lifetime(&self) -> Result<Option<Duration>, Error> {
+        unsafe {
+            let c = self.info_c(CredInfoC {
+                lifetime: Some(0),
+                .. CredInfoC::empty()
+            })?;
+            match c.lifetime.unwrap() {
+                _GSS_C_INDEFINITE => Ok(None),
+                secs => Ok(Some(Duration::from_secs(secs as u64))),
+            }
+        }
+    }
+
+    /// Return true if this credential has expired. Mechanisms that
+    /// refuse to inquire expired credentials are reported as expired
+    /// rather than as an error.
+    pub fn is_expired(&self) -> Result<bool, Error> {
+        match self.remaining_lifetime() {
+            Ok(lifetime) => Ok(lifetime == Some(Duration::from_secs(0))),
+            Err(e) if e.is_credentials_expired() => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Return the allowed usage of this credential
     pub fn usage(&self) -> Result<CredUsage, Error> {
         unsafe {
@@ -311,3 +779,75 @@ impl Cred {
         }
     }
 }
+
+#[cfg(feature = "mit_kerberos")]
+mod ccache {
+    use super::*;
+    use libgssapi_sys::gss_krb5_ccache_name;
+    use std::{ffi::CStr, marker::PhantomData, os::raw::c_char};
+
+    /// Set the credential cache the krb5 mechanism uses by default
+    /// for the calling thread, e.g. `FILE:/tmp/krb5cc_1234`, or reset
+    /// it to the default with `None`, and return the one that was set
+    /// before, if any, so it can be restored. Names are raw bytes,
+    /// the path in a `FILE:` name needn't be utf8. See
+    /// `GssCcacheGuard` for restoring it automatically. This is an
+    /// MIT extension.
+    pub fn set_ccache_name(name: Option<&CStr>) -> Result<Option<CString>, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut old = ptr::null::<c_char>();
+        let major = unsafe {
+            gss_krb5_ccache_name(
+                &mut minor as *mut OM_uint32,
+                name.map(|n| n.as_ptr()).unwrap_or(ptr::null()),
+                &mut old as *mut *const c_char,
+            )
+        };
+        if major != GSS_S_COMPLETE {
+            return Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            });
+        }
+        // old is only valid until the next call, so copy it now
+        if old.is_null() {
+            Ok(None)
+        } else {
+            Ok(Some(unsafe { CStr::from_ptr(old) }.to_owned()))
+        }
+    }
+
+    /// Sets the default credential cache for the calling thread, see
+    /// `set_ccache_name`, and restores the previous one when dropped.
+    /// It can't be sent to another thread, because the setting
+    /// belongs to the thread that made it.
+    #[derive(Debug)]
+    pub struct GssCcacheGuard {
+        old: Option<CString>,
+        _not_send: PhantomData<*const ()>,
+    }
+
+    impl GssCcacheGuard {
+        /// Make `name` the default credential cache until the guard
+        /// is dropped
+        pub fn new(name: &str) -> Result<GssCcacheGuard, Error> {
+            let name = CString::new(name).map_err(|_| Error {
+                major: MajorFlags::GSS_S_FAILURE,
+                minor: 0,
+            })?;
+            Ok(GssCcacheGuard {
+                old: set_ccache_name(Some(&name))?,
+                _not_send: PhantomData,
+            })
+        }
+    }
+
+    impl Drop for GssCcacheGuard {
+        fn drop(&mut self) {
+            let _ = set_ccache_name(self.old.as_deref());
+        }
+    }
+}
+
+#[cfg(feature = "mit_kerberos")]
+pub use ccache::*;
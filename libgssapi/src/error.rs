@@ -1,8 +1,9 @@
 use crate::util::Buf;
 use libgssapi_sys::{
     gss_OID_desc, gss_display_status, OM_uint32, GSS_C_CALLING_ERROR_OFFSET,
-    GSS_C_GSS_CODE, GSS_C_MECH_CODE, GSS_C_ROUTINE_ERROR_OFFSET, GSS_S_COMPLETE,
-    _GSS_C_CALLING_ERROR_MASK, _GSS_C_ROUTINE_ERROR_MASK, _GSS_S_BAD_BINDINGS,
+    GSS_C_GSS_CODE, GSS_C_MECH_CODE, GSS_C_ROUTINE_ERROR_OFFSET,
+    GSS_C_SUPPLEMENTARY_OFFSET, GSS_S_COMPLETE, _GSS_C_CALLING_ERROR_MASK,
+    _GSS_C_ROUTINE_ERROR_MASK, _GSS_C_SUPPLEMENTARY_MASK, _GSS_S_BAD_BINDINGS,
     _GSS_S_BAD_MECH, _GSS_S_BAD_MECH_ATTR, _GSS_S_BAD_MIC, _GSS_S_BAD_NAME,
     _GSS_S_BAD_NAMETYPE, _GSS_S_BAD_QOP, _GSS_S_BAD_SIG, _GSS_S_BAD_STATUS,
     _GSS_S_CALL_BAD_STRUCTURE, _GSS_S_CALL_INACCESSIBLE_READ,
@@ -58,6 +59,78 @@ pub(crate) fn gss_error(x: OM_uint32) -> OM_uint32 {
         | (_GSS_C_ROUTINE_ERROR_MASK << GSS_C_ROUTINE_ERROR_OFFSET))
 }
 
+// the krb5 minor statuses for a principal with no local account
+pub(crate) const KRB5_NO_LOCALNAME: u32 = -1765328227i32 as u32;
+pub(crate) const KRB5_LNAME_NOTRANS: u32 = -1765328208i32 as u32;
+
+// the minor status of the GSS_S_UNAVAILABLE error this library returns
+// instead of a token that wouldn't be encrypted, it's nothing like a
+// krb5 com_err code, and no mechanism reports 0 with it
+const NOT_ENCRYPTED: u32 = 0x6c67_0001;
+
+/// The standard routine errors, see `Error::kind`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WellKnownError {
+    /// `GSS_S_BAD_MECH`, an unsupported mechanism was requested
+    BadMech,
+    /// `GSS_S_BAD_NAME`, an invalid name was supplied
+    BadName,
+    /// `GSS_S_BAD_NAMETYPE`, a name of an unsupported type was supplied
+    BadNameType,
+    /// `GSS_S_BAD_BINDINGS`, the channel bindings don't match
+    BadBindings,
+    /// `GSS_S_BAD_STATUS`, an invalid status code was supplied
+    BadStatus,
+    /// `GSS_S_BAD_MIC` (or `GSS_S_BAD_SIG`), a token had an invalid
+    /// signature
+    BadMic,
+    /// `GSS_S_NO_CRED`, no credentials were supplied, or they
+    /// couldn't be accessed
+    NoCred,
+    /// `GSS_S_NO_CONTEXT`, there is no security context
+    NoContext,
+    /// `GSS_S_DEFECTIVE_TOKEN`, a token was invalid
+    DefectiveToken,
+    /// `GSS_S_DEFECTIVE_CREDENTIAL`, a credential was invalid
+    DefectiveCredential,
+    /// `GSS_S_CREDENTIALS_EXPIRED`, the credentials have expired
+    CredentialsExpired,
+    /// `GSS_S_CONTEXT_EXPIRED`, the security context has expired
+    ContextExpired,
+    /// `GSS_S_FAILURE`, some other failure, the minor status says
+    /// what
+    Failure,
+    /// `GSS_S_BAD_QOP`, the quality of protection can't be provided
+    BadQop,
+    /// `GSS_S_UNAUTHORIZED`, the operation is forbidden by policy
+    Unauthorized,
+    /// `GSS_S_UNAVAILABLE`, the operation or option is unavailable
+    Unavailable,
+    /// `GSS_S_DUPLICATE_ELEMENT`, the credential element already
+    /// exists
+    DuplicateElement,
+    /// `GSS_S_NAME_NOT_MN`, the name isn't a mechanism name
+    NameNotMn,
+    /// `GSS_S_BAD_MECH_ATTR`, an unknown mechanism attribute was
+    /// supplied
+    BadMechAttr,
+    /// There is no local account for the name, from `Name::localname`.
+    /// This isn't a routine error of its own, gssapi reports it as
+    /// `GSS_S_UNAVAILABLE` or `GSS_S_FAILURE` with a mechanism
+    /// specific minor status.
+    NoLocalMapping,
+    /// A message that was required to be encrypted wouldn't have
+    /// been, from `SecurityContext::encrypt` or `wrap(true, ..)`,
+    /// because `GSS_C_CONF_FLAG` wasn't negotiated or the mechanism
+    /// didn't encrypt it. This is reported as `GSS_S_UNAVAILABLE`.
+    NotEncrypted,
+    /// The output buffer passed to `SecurityContext::wrap_into_slice`
+    /// is too small for the token, `Error::required_len` says how big
+    /// it must be. This is reported as the calling error
+    /// `GSS_S_CALL_INACCESSIBLE_WRITE`.
+    BufferTooSmall,
+}
+
 #[derive(Clone, Copy, Debug)]
 enum ErrorComponent {
     Major = GSS_C_GSS_CODE as isize,
@@ -71,6 +144,112 @@ pub struct Error {
 }
 
 impl Error {
+    pub(crate) fn not_encrypted() -> Error {
+        Error {
+            major: MajorFlags::GSS_S_UNAVAILABLE,
+            minor: NOT_ENCRYPTED,
+        }
+    }
+
+    // the minor status carries the length
+    pub(crate) fn buffer_too_small(required: usize) -> Error {
+        Error {
+            major: MajorFlags::GSS_S_CALL_INACCESSIBLE_WRITE,
+            minor: required as u32,
+        }
+    }
+
+    /// The calling error field of the major status, e.g.
+    /// `_GSS_S_CALL_INACCESSIBLE_READ`, or 0 if there isn't one. Calling
+    /// errors mean the library was passed something invalid.
+    pub fn calling_error(&self) -> u32 {
+        self.major.bits() & (_GSS_C_CALLING_ERROR_MASK << GSS_C_CALLING_ERROR_OFFSET)
+    }
+
+    /// The routine error field of the major status, e.g.
+    /// `_GSS_S_CREDENTIALS_EXPIRED`, or 0 if there isn't one.
+    pub fn routine_error(&self) -> u32 {
+        self.major.bits() & (_GSS_C_ROUTINE_ERROR_MASK << GSS_C_ROUTINE_ERROR_OFFSET)
+    }
+
+    /// Return true if the security context has expired
+    /// (`GSS_S_CONTEXT_EXPIRED`), a new one must be established.
+    pub fn is_context_expired(&self) -> bool {
+        self.routine_error() == _GSS_S_CONTEXT_EXPIRED
+    }
+
+    /// Return true if the credentials have expired
+    /// (`GSS_S_CREDENTIALS_EXPIRED`), they must be renewed, e.g. with
+    /// kinit.
+    pub fn is_credentials_expired(&self) -> bool {
+        self.routine_error() == _GSS_S_CREDENTIALS_EXPIRED
+    }
+
+    /// Return true if a name has no local account, see
+    /// `WellKnownError::NoLocalMapping`.
+    pub fn is_no_local_mapping(&self) -> bool {
+        match self.routine_error() {
+            _GSS_S_UNAVAILABLE | _GSS_S_FAILURE => {
+                self.minor == KRB5_NO_LOCALNAME || self.minor == KRB5_LNAME_NOTRANS
+            }
+            _ => false,
+        }
+    }
+
+    /// The routine error as something you can match on, or `None` if
+    /// there isn't one (e.g. it's a calling error).
+    pub fn kind(&self) -> Option<WellKnownError> {
+        use WellKnownError::*;
+        if self.is_no_local_mapping() {
+            return Some(NoLocalMapping);
+        }
+        if self.routine_error() == _GSS_S_UNAVAILABLE && self.minor == NOT_ENCRYPTED {
+            return Some(NotEncrypted);
+        }
+        if self.calling_error() == _GSS_S_CALL_INACCESSIBLE_WRITE
+            && self.routine_error() == 0
+        {
+            return Some(BufferTooSmall);
+        }
+        Some(match self.routine_error() {
+            _GSS_S_BAD_MECH => BadMech,
+            _GSS_S_BAD_NAME => BadName,
+            _GSS_S_BAD_NAMETYPE => BadNameType,
+            _GSS_S_BAD_BINDINGS => BadBindings,
+            _GSS_S_BAD_STATUS => BadStatus,
+            _GSS_S_BAD_MIC => BadMic,
+            _GSS_S_NO_CRED => NoCred,
+            _GSS_S_NO_CONTEXT => NoContext,
+            _GSS_S_DEFECTIVE_TOKEN => DefectiveToken,
+            _GSS_S_DEFECTIVE_CREDENTIAL => DefectiveCredential,
+            _GSS_S_CREDENTIALS_EXPIRED => CredentialsExpired,
+            _GSS_S_CONTEXT_EXPIRED => ContextExpired,
+            _GSS_S_FAILURE => Failure,
+            _GSS_S_BAD_QOP => BadQop,
+            _GSS_S_UNAUTHORIZED => Unauthorized,
+            _GSS_S_UNAVAILABLE => Unavailable,
+            _GSS_S_DUPLICATE_ELEMENT => DuplicateElement,
+            _GSS_S_NAME_NOT_MN => NameNotMn,
+            _GSS_S_BAD_MECH_ATTR => BadMechAttr,
+            _ => return None,
+        })
+    }
+
+    /// The size of output buffer needed if this is a
+    /// `WellKnownError::BufferTooSmall` error.
+    pub fn required_len(&self) -> Option<usize> {
+        match self.kind() {
+            Some(WellKnownError::BufferTooSmall) => Some(self.minor as usize),
+            _ => None,
+        }
+    }
+
+    /// The supplementary info bits of the major status, e.g.
+    /// `_GSS_S_DUPLICATE_TOKEN`.
+    pub fn supplementary_info(&self) -> u32 {
+        self.major.bits() & (_GSS_C_SUPPLEMENTARY_MASK << GSS_C_SUPPLEMENTARY_OFFSET)
+    }
+
     fn fmt_code(f: &mut fmt::Formatter<'_>, code: u32, ctype: ErrorComponent) -> fmt::Result {
         let mut message_context: OM_uint32 = 0;
         loop {
@@ -108,7 +287,15 @@ impl Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         Error::fmt_code(f, self.major.bits(), ErrorComponent::Major)?;
-        Ok(Error::fmt_code(f, self.minor, ErrorComponent::Minor)?)
+        match self.kind() {
+            Some(WellKnownError::NotEncrypted) => {
+                write!(f, " (the message would not have been encrypted)")
+            }
+            Some(WellKnownError::BufferTooSmall) => {
+                write!(f, " (the output buffer must be {} bytes)", self.minor)
+            }
+            _ => Error::fmt_code(f, self.minor, ErrorComponent::Minor),
+        }
     }
 }
 
@@ -0,0 +1,142 @@
+//! Accept many concurrent handshakes, keeping each connection's
+//! partially established context until it completes.
+//!
+//! Tokens for different connections can be processed on different
+//! threads at the same time, e.g. from a thread pool. Tokens for the
+//! same connection are processed one at a time.
+use crate::{
+    context::{SecurityContext, ServerCtx},
+    error::Error,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Identifies a connection, e.g. a counter incremented by the accept
+/// loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ConnectionId(pub u64);
+
+type NewCtx = dyn Fn() -> Result<ServerCtx, Error> + Send + Sync;
+
+/// Holds the server context of each connection that is part way
+/// through a handshake
+pub struct GssHandshakeService {
+    new_ctx: Box<NewCtx>,
+    ctxs: Mutex<HashMap<ConnectionId, Arc<Mutex<ServerCtx>>>>,
+}
+
+impl fmt::Debug for GssHandshakeService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GssHandshakeService")
+            .field("ctxs", &self.ctxs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl GssHandshakeService {
+    /// `new_ctx` is called to make the server context when the first
+    /// token of a connection arrives, e.g.
+    /// `|| Ok(ServerCtx::new(Cred::acquire_default(CredUsage::Accept)?))`
+    /// or a `ServerCtxBuilder` with the policy you need.
+    pub fn new<F>(new_ctx: F) -> GssHandshakeService
+    where
+        F: Fn() -> Result<ServerCtx, Error> + Send + Sync + 'static,
+    {
+        GssHandshakeService {
+            new_ctx: Box::new(new_ctx),
+            ctxs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Accept with the default acceptor credential, see
+    /// `ServerCtx::new_default`
+    pub fn with_default_cred() -> GssHandshakeService {
+        GssHandshakeService::new(|| Ok(ServerCtx::new_default()))
+    }
+
+    fn get(&self, conn_id: ConnectionId) -> Result<Arc<Mutex<ServerCtx>>, Error> {
+        let mut ctxs = self.ctxs.lock().unwrap();
+        match ctxs.get(&conn_id) {
+            Some(ctx) => Ok(Arc::clone(ctx)),
+            None => {
+                let ctx = Arc::new(Mutex::new((self.new_ctx)()?));
+                ctxs.insert(conn_id, Arc::clone(&ctx));
+                Ok(ctx)
+            }
+        }
+    }
+
+    /// Feed a token from the client on connection `conn_id` to its
+    /// context, starting a new context if this is the first one.
+    /// Returns the token to send back to the client, if any. Once
+    /// `is_complete` is true, get the context with `take_context`.
+    ///
+    /// If the step fails the connection is forgotten, and the next
+    /// token for it starts a new handshake.
+    pub fn process(
+        &self,
+        conn_id: ConnectionId,
+        token: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        let ctx = self.get(conn_id)?;
+        let res = ctx.lock().unwrap().step(token);
+        match res {
+            Ok(tok) => Ok(tok.map(|tok| tok.to_vec())),
+            Err(e) => {
+                self.remove(conn_id);
+                Err(e)
+            }
+        }
+    }
+
+    /// Return true if the handshake on `conn_id` is complete
+    pub fn is_complete(&self, conn_id: ConnectionId) -> bool {
+        let ctx = self.ctxs.lock().unwrap().get(&conn_id).map(Arc::clone);
+        match ctx {
+            None => false,
+            Some(ctx) => ctx.lock().unwrap().is_complete(),
+        }
+    }
+
+    /// Remove and return the context of `conn_id` if its handshake
+    /// is complete. Returns `None` if there is no such connection, it
+    /// isn't complete yet, or a token for it is being processed.
+    pub fn take_context(&self, conn_id: ConnectionId) -> Option<ServerCtx> {
+        let mut ctxs = self.ctxs.lock().unwrap();
+        let ctx = ctxs.remove(&conn_id)?;
+        match Arc::try_unwrap(ctx) {
+            Ok(ctx) => {
+                let ctx = ctx.into_inner().unwrap();
+                if ctx.is_complete() {
+                    Some(ctx)
+                } else {
+                    ctxs.insert(conn_id, Arc::new(Mutex::new(ctx)));
+                    None
+                }
+            }
+            Err(ctx) => {
+                ctxs.insert(conn_id, ctx);
+                None
+            }
+        }
+    }
+
+    /// Forget the connection `conn_id`, e.g. because the client went
+    /// away in the middle of the handshake
+    pub fn remove(&self, conn_id: ConnectionId) {
+        self.ctxs.lock().unwrap().remove(&conn_id);
+    }
+
+    /// The number of connections with a context
+    pub fn len(&self) -> usize {
+        self.ctxs.lock().unwrap().len()
+    }
+
+    /// Return true if there are no connections with a context
+    pub fn is_empty(&self) -> bool {
+        self.ctxs.lock().unwrap().is_empty()
+    }
+}
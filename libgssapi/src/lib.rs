@@ -91,4 +91,15 @@ pub mod util;
 pub mod name;
 pub mod credential;
 pub mod context;
+pub mod reestablish;
+pub mod handshake;
+pub mod stream;
+#[cfg(feature = "tls-server-end-point")]
+pub mod channel_binding;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "negotiate")]
+pub mod negotiate;
+#[cfg(feature = "sasl")]
+pub mod sasl;
  
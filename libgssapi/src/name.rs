@@ -1,72 +1,351 @@
 use crate::{
     error::{Error, MajorFlags},
     util::{Buf, BufRef},
-    oid::Oid,
+    oid::{
+        Oid, OwnedOid, GSS_MECH_KRB5, GSS_NT_ANONYMOUS, GSS_NT_COMPOSITE_EXPORT,
+        GSS_NT_EXPORT_NAME, GSS_NT_HOSTBASED_SERVICE, GSS_NT_KRB5_ENTERPRISE_NAME,
+        GSS_NT_KRB5_PRINCIPAL, GSS_NT_MACHINE_UID_NAME, GSS_NT_STRING_UID_NAME,
+        GSS_NT_USER_NAME,
+    },
 };
 use libgssapi_sys::{
-    gss_OID, gss_OID_desc, gss_canonicalize_name, gss_display_name, gss_duplicate_name,
+    gss_OID, gss_OID_desc, gss_canonicalize_name, gss_compare_name, gss_display_name,
+    gss_duplicate_name,
     gss_import_name, gss_name_struct, gss_name_t, gss_release_name, gss_export_name,
-    OM_uint32, GSS_S_COMPLETE,
+    OM_uint32, GSS_S_COMPLETE, _GSS_S_DEFECTIVE_TOKEN,
 };
 #[cfg(feature = "localname")]
 use libgssapi_sys::gss_localname;
 #[cfg(feature = "localname")]
-use crate::oid::NO_OID;
-use std::{ptr, fmt};
+use crate::{error::KRB5_LNAME_NOTRANS, oid::NO_OID};
+#[cfg(any(feature = "mit_kerberos", feature = "heimdal"))]
+use libgssapi_sys::gss_userok;
+#[cfg(feature = "mit_kerberos")]
+use libgssapi_sys::gss_authorize_localname;
+#[cfg(all(feature = "localname", feature = "mit_kerberos"))]
+use libgssapi_sys::{gss_pname_to_uid, uid_t};
+#[cfg(any(feature = "mit_kerberos", feature = "heimdal"))]
+use std::ffi::CString;
+use std::{
+    convert::TryFrom,
+    error, fmt,
+    hash::{Hash, Hasher},
+    mem, ptr,
+    str::FromStr,
+    string::FromUtf8Error,
+    sync::OnceLock,
+};
+
+/// The type of a name, see `Name::kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NameType {
+    /// `GSS_NT_HOSTBASED_SERVICE`, e.g. `HTTP@www.example.com`
+    HostbasedService,
+    /// `GSS_NT_USER_NAME`
+    UserName,
+    /// `GSS_NT_KRB5_PRINCIPAL`
+    Krb5Principal,
+    /// `GSS_NT_KRB5_ENTERPRISE_NAME`, e.g. `user@example.com@REALM`
+    EnterprisePrincipal,
+    /// `GSS_NT_EXPORT_NAME`
+    Export,
+    /// `GSS_NT_ANONYMOUS`
+    Anonymous,
+    /// `GSS_NT_MACHINE_UID_NAME`
+    MachineUid,
+    /// `GSS_NT_STRING_UID_NAME`
+    StringUid,
+    /// Some other name type
+    Other(&'static Oid),
+    /// The mechanism didn't report a name type
+    Untyped,
+}
+
+impl From<&'static Oid> for NameType {
+    fn from(oid: &'static Oid) -> Self {
+        match oid {
+            o if *o == GSS_NT_HOSTBASED_SERVICE => NameType::HostbasedService,
+            o if *o == GSS_NT_USER_NAME => NameType::UserName,
+            o if *o == GSS_NT_KRB5_PRINCIPAL => NameType::Krb5Principal,
+            o if *o == GSS_NT_KRB5_ENTERPRISE_NAME => NameType::EnterprisePrincipal,
+            o if *o == GSS_NT_EXPORT_NAME => NameType::Export,
+            o if *o == GSS_NT_ANONYMOUS => NameType::Anonymous,
+            o if *o == GSS_NT_MACHINE_UID_NAME => NameType::MachineUid,
+            o if *o == GSS_NT_STRING_UID_NAME => NameType::StringUid,
+            o => NameType::Other(o),
+        }
+    }
+}
+
+/// Why a name couldn't be converted to a `String` by
+/// `Name::to_utf8_string`
+#[derive(Debug)]
+pub enum NameDisplayError {
+    /// gssapi couldn't display the name
+    Gss(Error),
+    /// The name isn't valid utf8, the error holds its bytes
+    Utf8(FromUtf8Error),
+}
+
+impl fmt::Display for NameDisplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameDisplayError::Gss(e) => write!(f, "{}", e),
+            NameDisplayError::Utf8(e) => write!(f, "name is not valid utf8: {}", e),
+        }
+    }
+}
+
+impl error::Error for NameDisplayError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            NameDisplayError::Gss(e) => Some(e),
+            NameDisplayError::Utf8(e) => Some(e),
+        }
+    }
+}
+
+impl From<Error> for NameDisplayError {
+    fn from(e: Error) -> Self {
+        NameDisplayError::Gss(e)
+    }
+}
 
-pub struct Name(gss_name_t);
+// the form of a name used for Eq and Hash, tagged so an exported
+// name and a display form with the same bytes don't collide
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum NameKey {
+    Exported(Vec<u8>),
+    Display(Vec<u8>),
+    // can't be exported or displayed
+    Unkeyed,
+}
+
+pub struct Name {
+    name: gss_name_t,
+    // computed on first use
+    key: OnceLock<NameKey>,
+}
 
 unsafe impl Send for Name {}
 unsafe impl Sync for Name {}
 
+// the number of names released on this thread, for leak checks
+#[cfg(test)]
+thread_local! {
+    static RELEASED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 impl Drop for Name {
     fn drop(&mut self) {
-        if !self.0.is_null() {
+        if !self.name.is_null() {
+            #[cfg(test)]
+            RELEASED.with(|n| n.set(n.get() + 1));
             let mut _minor = GSS_S_COMPLETE;
             let _major = unsafe {
                 gss_release_name(
                     &mut _minor as *mut OM_uint32,
-                    &mut self.0 as *mut gss_name_t,
+                    &mut self.name as *mut gss_name_t,
                 )
             };
         }
     }
 }
 
+/// Shows the display form and the name type in dotted decimal, e.g.
+/// `Name("host/a.example.com@REALM", type=1.2.840.113554.1.2.2.1)`.
 impl fmt::Debug for Name {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        if let Ok(buf) = self.display_name() {
-            if let Ok(s) = std::str::from_utf8(&buf) {
-                write!(f, "{}", s)
-            } else {
-                write!(f, "<name can't be decoded>")
+        match self.display_with_mech() {
+            Err(_) => write!(f, "Name(<can't be displayed>)"),
+            Ok((buf, typ)) => {
+                write!(f, "Name({:?}, type=", String::from_utf8_lossy(&buf))?;
+                match typ.map(|typ| (typ, typ.to_dotted())) {
+                    None => write!(f, "none)"),
+                    Some((_, Some(dotted))) => write!(f, "{})", dotted),
+                    Some((typ, None)) => write!(f, "{:?})", &**typ),
+                }
+            }
+        }
+    }
+}
+
+/// Names are equal if their exported canonical forms are equal,
+/// which for krb5 is what `Name::equal` decides, so differently
+/// imported spellings of one principal are equal. The canonical form
+/// is computed once per name and cached, so comparing and hashing
+/// names (e.g. as `HashMap` keys) doesn't call gssapi every time.
+///
+/// Names that can't be exported, even after canonicalizing them for
+/// krb5, are compared with `Name::equal`, and are never equal to one
+/// that can. They hash by their display form, so two of them are only
+/// equal if they also display the same, and names that can't even be
+/// displayed all hash the same. To canonicalize for another mechanism
+/// use `NameHasher`.
+///
+/// Computing the canonical form calls `gss_canonicalize_name`, which
+/// for a hostbased service name may look the host up in DNS (see
+/// `dns_canonicalize_hostname` in krb5.conf), so the first comparison
+/// or hash of such a name can block. Where that matters canonicalize
+/// names beforehand, e.g. with `Name::canonicalize` or `NameHasher`.
+///
+/// ```
+/// # use libgssapi::{name::Name, oid::*};
+/// # use std::collections::HashMap;
+/// let mut perms = HashMap::new();
+/// let alice = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+/// perms.insert(alice, "admin");
+/// let peer = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_USER_NAME)).unwrap();
+/// assert_eq!(perms.get(&peer), Some(&"admin"));
+/// let peer = peer.canonicalize(Some(&GSS_MECH_KRB5)).unwrap();
+/// assert_eq!(perms.get(&peer), Some(&"admin"));
+/// let bob = Name::new("bob@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+/// assert_eq!(perms.get(&bob), None);
+/// ```
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        match (self.key(), other.key()) {
+            (NameKey::Exported(k0), NameKey::Exported(k1)) => k0 == k1,
+            // equal names must hash the same, so the keys must match too
+            (k0, k1) => {
+                k0 == k1 && (ptr::eq(self, other) || self.equal(other).unwrap_or(false))
             }
-        } else {
-            write!(f, "<name can't be displayed>")
         }
     }
 }
 
+impl Eq for Name {}
+
+/// Consistent with `Eq`, and like it may block on DNS the first time
+/// a name is hashed, see there
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// Write the display form of the name, replacing anything that isn't
+/// valid utf8, or `<unprintable gss name>` if gssapi can't display it.
 impl fmt::Display for Name {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt::Debug::fmt(self, f)
+        match self.display_name() {
+            Ok(buf) => write!(f, "{}", String::from_utf8_lossy(&buf)),
+            Err(_) => write!(f, "<unprintable gss name>"),
+        }
+    }
+}
+
+/// The same as `Name::new` with no name type, so names containing a
+/// NUL byte are rejected
+impl TryFrom<&[u8]> for Name {
+    type Error = Error;
+
+    fn try_from(s: &[u8]) -> Result<Self, Error> {
+        Name::new(s, None)
+    }
+}
+
+/// The same as `Name::new` with no name type
+impl TryFrom<&str> for Name {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Error> {
+        Name::new(s.as_bytes(), None)
+    }
+}
+
+/// The same as `Name::new` with no name type, so names can be parsed
+/// with `"host@example.com".parse::<Name>()`
+impl FromStr for Name {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        Name::new(s.as_bytes(), None)
     }
 }
 
 impl Name {
     pub(crate) unsafe fn to_c(&self) -> gss_name_t {
-        self.0
+        self.name
     }
 
     #[allow(dead_code)]
     pub(crate) unsafe fn from_c(ptr: gss_name_t) -> Self {
-        Name(ptr)
+        Name::wrap(ptr)
+    }
+
+    /// Return the underlying `gss_name_t`, for passing to gssapi
+    /// functions this library doesn't wrap, or other libraries using
+    /// the same gssapi. The name still owns it, so it must not be
+    /// released, and is only valid while the name is alive.
+    pub fn as_raw(&self) -> gss_name_t {
+        self.name
+    }
+
+    /// Give up ownership of the underlying `gss_name_t`. The caller
+    /// becomes responsible for releasing it exactly once with
+    /// `gss_release_name`, or for handing it back with `from_raw`.
+    pub fn into_raw(mut self) -> gss_name_t {
+        // drop sees a null name and doesn't release it
+        mem::replace(&mut self.name, ptr::null_mut())
+    }
+
+    /// Take ownership of `name`, it will be released with
+    /// `gss_release_name` when the returned `Name` is dropped.
+    ///
+    /// # Safety
+    ///
+    /// `name` must be a valid name from the same gssapi library this
+    /// crate is linked with, e.g. from `into_raw`, and nothing else
+    /// may use or release it afterwards.
+    pub unsafe fn from_raw(name: gss_name_t) -> Self {
+        Name::wrap(name)
+    }
+
+    fn wrap(name: gss_name_t) -> Self {
+        Name {
+            name,
+            key: OnceLock::new(),
+        }
+    }
+
+    /// The exported krb5 canonical form of the name, or the display
+    /// form if it can't be canonicalized
+    fn key(&self) -> &NameKey {
+        self.key.get_or_init(|| {
+            match self.export().or_else(|_| self.export_for(&GSS_MECH_KRB5)) {
+                Ok(b) => NameKey::Exported(Vec::from(&*b)),
+                Err(_) => match self.display_name() {
+                    Ok(b) => NameKey::Display(Vec::from(&*b)),
+                    Err(_) => NameKey::Unkeyed,
+                },
+            }
+        })
     }
     
     /// parse the specified bytes as a gssapi name, with optional
     /// `kind` e.g. `GSS_NT_HOSTBASED_SERVICE` or
-    /// `GSS_NT_KRB5_PRINCIPAL`.
-    pub fn new(s: &[u8], kind: Option<&Oid>) -> Result<Self, Error> {
+    /// `GSS_NT_KRB5_PRINCIPAL`. Text names containing a NUL byte fail
+    /// with `GSS_S_BAD_NAME`, rather than being silently truncated by
+    /// the library. Exported names are binary and may contain them.
+    ///
+    /// ```
+    /// # use libgssapi::name::Name;
+    /// assert!(Name::new("host@example.com", None).is_ok());
+    /// assert!(Name::new("host@example.com\0.evil", None).is_err());
+    /// ```
+    pub fn new(s: impl AsRef<[u8]>, kind: Option<&Oid>) -> Result<Self, Error> {
+        let s = s.as_ref();
+        let binary = matches!(
+            kind,
+            Some(k) if *k == GSS_NT_EXPORT_NAME || *k == GSS_NT_COMPOSITE_EXPORT
+        );
+        if !binary && s.contains(&0) {
+            return Err(Error {
+                major: MajorFlags::GSS_S_BAD_NAME,
+                minor: 0,
+            });
+        }
         let mut buf = BufRef::from(s);
         let mut minor = GSS_S_COMPLETE;
         let mut name = ptr::null_mut::<gss_name_struct>();
@@ -82,7 +361,7 @@ impl Name {
             )
         };
         if major == GSS_S_COMPLETE {
-            Ok(Name(name))
+            Ok(Name::wrap(name))
         } else {
             Err(Error {
                 major: MajorFlags::from_bits_retain(major),
@@ -91,25 +370,43 @@ impl Name {
         }
     }
 
-    /// canonicalize a name for the specified mechanism (or the
-    /// default mechanism if not specified). This makes a copy of the
-    /// name.
+    /// Make the host based service name `service@host`, e.g.
+    /// `Name::service("HTTP", "www.example.com")`. The mechanism maps
+    /// it to its own form (`HTTP/www.example.com@REALM` for krb5),
+    /// including any case folding of the host. A service or host
+    /// that is empty or contains `@` or NUL fails with
+    /// `GSS_S_BAD_NAME`.
+    pub fn service(service: &str, host: &str) -> Result<Self, Error> {
+        let valid = |s: &str| !s.is_empty() && !s.contains(['@', '\0']);
+        if !valid(service) || !valid(host) {
+            return Err(Error {
+                major: MajorFlags::GSS_S_BAD_NAME,
+                minor: 0,
+            });
+        }
+        let name = format!("{}@{}", service, host);
+        Name::new(name.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE))
+    }
+
+    /// canonicalize a name for the specified mechanism (or krb5 if
+    /// not specified, gssapi has no default mechanism for this). With
+    /// SPNEGO canonicalize for the mechanism it negotiated,
+    /// e.g. `name.canonicalize(Some(ctx.mechanism()?))`. This makes a
+    /// copy of the name.
     pub fn canonicalize(&self, mech: Option<&Oid>) -> Result<Self, Error> {
+        let mech = mech.unwrap_or(&GSS_MECH_KRB5);
         let mut out = ptr::null_mut::<gss_name_struct>();
         let mut minor = GSS_S_COMPLETE;
         let major = unsafe {
             gss_canonicalize_name(
                 &mut minor as *mut OM_uint32,
                 self.to_c(),
-                match mech {
-                    None => ptr::null_mut::<gss_OID_desc>(),
-                    Some(id) => id.to_c()
-                },
+                mech.to_c(),
                 &mut out as *mut gss_name_t,
             )
         };
         if major == GSS_S_COMPLETE {
-            Ok(Name(out))
+            Ok(Name::wrap(out))
         } else {
             Err(Error {
                 major: MajorFlags::from_bits_retain(major),
@@ -118,16 +415,23 @@ impl Name {
         }
     }
 
+    /// canonicalize a name for `mech`, e.g. `GSS_MECH_SPNEGO` or
+    /// `Mechanism::Ntlm.oid()`. This makes a copy of the name.
+    pub fn canonicalize_for(&self, mech: &Oid) -> Result<Self, Error> {
+        self.canonicalize(Some(mech))
+    }
+
     /// Produce a contiguous string representation of a canonicalized
     /// name suitable for direct comparison. You must either use a
-    /// canonical name, or call canonicalize before using this method.
+    /// canonical name, or call canonicalize before using this method,
+    /// otherwise it fails with `GSS_S_NAME_NOT_MN`.
     pub fn export(&self) -> Result<Buf, Error> {
         let mut out = Buf::empty();
         let mut minor = GSS_S_COMPLETE;
         let major = unsafe {
             gss_export_name(
                 &mut minor as *mut OM_uint32,
-                self.0,
+                self.name,
                 out.to_c()
             )
         };
@@ -141,10 +445,63 @@ impl Name {
         }
     }
 
+    /// Import a name produced by `export`, e.g. from an authorization
+    /// list. The result is a mechanism name. Corrupt tokens fail with
+    /// `GSS_S_BAD_NAME`, including those MIT's mechglue rejects with
+    /// `GSS_S_DEFECTIVE_TOKEN`, which rfc2744 doesn't allow here.
+    pub fn from_exported(exported: &[u8]) -> Result<Self, Error> {
+        Name::new(exported, Some(&GSS_NT_EXPORT_NAME)).map_err(|e| {
+            if e.routine_error() == _GSS_S_DEFECTIVE_TOKEN {
+                Error {
+                    major: (e.major - MajorFlags::GSS_S_DEFECTIVE_TOKEN)
+                        | MajorFlags::GSS_S_BAD_NAME,
+                    minor: e.minor,
+                }
+            } else {
+                e
+            }
+        })
+    }
+
+    /// Canonicalize the name for `mech` and export it. The result is
+    /// the same however the name was imported, e.g.
+    /// `Name::service("nfs", "host.example.com")` and the krb5
+    /// principal `nfs/host.example.com@REALM` export the same bytes
+    /// for `GSS_MECH_KRB5`, so it is the right key for authorization
+    /// lists.
+    pub fn export_for(&self, mech: &Oid) -> Result<Buf, Error> {
+        self.canonicalize(Some(mech))?.export()
+    }
+
     /// Return the raw textual representation of the internal GSS
     /// name. Usually this will be utf8, or at least ascii, but that
     /// isn't guaranteed.
     pub fn display_name(&self) -> Result<Buf, Error> {
+        self.display_with_mech().map(|(out, _)| out)
+    }
+
+    /// Return the display form of the name as a `String`, failing if
+    /// it isn't valid utf8 (krb5 principals may contain any bytes)
+    /// rather than altering it, so it is safe to use for
+    /// authorization. The bytes are available from the error.
+    pub fn to_utf8_string(&self) -> Result<String, NameDisplayError> {
+        let buf = self.display_name()?;
+        String::from_utf8(Vec::from(&*buf)).map_err(NameDisplayError::Utf8)
+    }
+
+    /// Return the display form of the name as a `String`, replacing
+    /// any invalid utf8 with U+FFFD, which is fine for logging but
+    /// not for comparing names, see `to_utf8_string`.
+    pub fn to_string_lossy(&self) -> Result<String, Error> {
+        Ok(String::from_utf8_lossy(&self.display_name()?).into_owned())
+    }
+
+    /// Return the textual representation of the name like
+    /// `display_name`, along with the name type needed to interpret
+    /// it, e.g. `GSS_NT_HOSTBASED_SERVICE` or `GSS_NT_KRB5_PRINCIPAL`.
+    /// The name type is `None` if the name was imported without one
+    /// and hasn't been canonicalized.
+    pub fn display_with_mech(&self) -> Result<(Buf, Option<&'static Oid>), Error> {
         let mut out = Buf::empty();
         let mut minor = GSS_S_COMPLETE;
         let mut oid = ptr::null_mut::<gss_OID_desc>();
@@ -157,7 +514,12 @@ impl Name {
             )
         };
         if major == GSS_S_COMPLETE {
-            Ok(out)
+            let kind = if oid.is_null() {
+                None
+            } else {
+                Some(unsafe { Oid::from_c(oid) })
+            };
+            Ok((out, kind))
         } else {
             Err(Error {
                 major: MajorFlags::from_bits_retain(major),
@@ -166,23 +528,174 @@ impl Name {
         }
     }
 
-    /// Return the raw textual representation of the internal GSS name
-    /// as interpreted by the specified mechanism. If no mechanism is
-    /// specified then it will be assumed to be NO_OID.
+    /// Return the name type, e.g. to tell a hostbased service from a
+    /// krb5 principal or an anonymous name. It compares equal to the
+    /// constants in `oid`, e.g. `GSS_NT_HOSTBASED_SERVICE`,
+    /// `GSS_NT_USER_NAME` or `GSS_NT_ANONYMOUS`. This is the name type
+    /// half of `display_with_mech`.
+    pub fn name_type(&self) -> Result<Option<&'static Oid>, Error> {
+        self.display_with_mech().map(|(_, typ)| typ)
+    }
+
+    /// Return the name type as a `NameType` to match on, rather than
+    /// an oid, see `name_type`
+    ///
+    /// ```
+    /// # use libgssapi::{name::{Name, NameType}, oid::*};
+    /// let n = Name::new("HTTP@www.example.com", Some(&GSS_NT_HOSTBASED_SERVICE));
+    /// let n = n.unwrap();
+    /// assert_eq!(n.kind().unwrap(), NameType::HostbasedService);
+    /// let n = Name::new("user@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+    /// assert_eq!(n.kind().unwrap(), NameType::Krb5Principal);
+    /// let n = Name::new("", Some(&GSS_NT_ANONYMOUS)).unwrap();
+    /// assert_eq!(n.kind().unwrap(), NameType::Anonymous);
+    /// ```
+    pub fn kind(&self) -> Result<NameType, Error> {
+        Ok(self.name_type()?.map(NameType::from).unwrap_or(NameType::Untyped))
+    }
+
+    /// Return true if this is the anonymous name, e.g. the source
+    /// name of a context established with `GSS_C_ANON_FLAG`, which
+    /// authorization code must not treat like a real principal.
+    pub fn is_anonymous(&self) -> Result<bool, Error> {
+        Ok(self.name_type()? == Some(&GSS_NT_ANONYMOUS))
+    }
+
+    /// Map the (authenticated) name to the local account it may run
+    /// as, using the rules of `mech` (for krb5 the `auth_to_local`
+    /// rules in krb5.conf, by default `user@DEFAULT_REALM` maps to
+    /// `user`), and return the username. If there is no local account
+    /// this fails with an error whose `kind` is
+    /// `WellKnownError::NoLocalMapping`. Other errors are returned as
+    /// is.
     #[cfg(feature = "localname")]
-    pub fn local_name(&self, mechs: Option<&Oid>) -> Result<Buf, Error> {
+    pub fn localname(&self, mech: Option<&Oid>) -> Result<Buf, Error> {
         let mut out = Buf::empty();
         let mut minor = GSS_S_COMPLETE;
         let major = unsafe {
             gss_localname(
                 &mut minor as *mut OM_uint32,
-                self.0,
-                mechs.map_or(NO_OID, |o| o.to_c()),
+                self.name,
+                mech.map_or(NO_OID, |o| o.to_c()),
                 out.to_c()
             )
         };
         if major == GSS_S_COMPLETE {
             Ok(out)
+        } else {
+            Err(localname_error(major, minor))
+        }
+    }
+
+    /// Map the (authenticated) name to the uid of the local account
+    /// it may run as. If there is no local account this fails with an
+    /// error whose `kind` is `WellKnownError::NoLocalMapping`, if there
+    /// is one but it doesn't exist on this system it fails with
+    /// `GSS_S_FAILURE`.
+    /// This is an MIT extension.
+    #[cfg(all(feature = "localname", feature = "mit_kerberos"))]
+    pub fn to_uid(&self, mech: Option<&Oid>) -> Result<u32, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut uid: uid_t = 0;
+        let major = unsafe {
+            gss_pname_to_uid(
+                &mut minor as *mut OM_uint32,
+                self.name,
+                mech.map_or(NO_OID, |o| o.to_c()),
+                &mut uid as *mut uid_t,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(uid)
+        } else {
+            Err(localname_error(major, minor))
+        }
+    }
+
+    /// Return true if this (authenticated) name may act as the local
+    /// account `username`, according to the mechanism's rules, e.g.
+    /// `~username/.k5login` for krb5. This is what sshd uses to
+    /// authorize gssapi logins. A `username` containing NUL fails
+    /// with `GSS_S_BAD_NAME`. This is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn userok(&self, username: &str) -> Result<bool, Error> {
+        let username = CString::new(username).map_err(|_| Error {
+            major: MajorFlags::GSS_S_BAD_NAME,
+            minor: 0,
+        })?;
+        Ok(unsafe { gss_userok(self.name, username.as_ptr()) } != 0)
+    }
+
+    /// Return true if this (authenticated) name may act as the local
+    /// account `user`, according to the mechanism's authorization
+    /// policy (for krb5 `~user/.k5login` and the `auth_to_local`
+    /// rules in krb5.conf). With MIT this uses
+    /// `gss_authorize_localname`, which distinguishes a refusal from a
+    /// failure to decide, with Heimdal `gss_userok`, which doesn't.
+    #[cfg(any(feature = "mit_kerberos", feature = "heimdal"))]
+    pub fn authorized_for_local_user(&self, user: &str) -> Result<bool, Error> {
+        #[cfg(feature = "mit_kerberos")]
+        {
+            let user = Name::new(user.as_bytes(), Some(&GSS_NT_USER_NAME))?;
+            let mut minor = GSS_S_COMPLETE;
+            let major = unsafe {
+                gss_authorize_localname(
+                    &mut minor as *mut OM_uint32,
+                    self.name,
+                    user.name,
+                )
+            };
+            let e = Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            };
+            let unauthorized = MajorFlags::GSS_S_UNAUTHORIZED.bits();
+            match major {
+                GSS_S_COMPLETE => Ok(true),
+                _ if e.routine_error() == unauthorized => Ok(false),
+                _ => Err(e),
+            }
+        }
+        #[cfg(not(feature = "mit_kerberos"))]
+        {
+            let user = CString::new(user).map_err(|_| bad_name())?;
+            Ok(unsafe { gss_userok(self.name, user.as_ptr()) } != 0)
+        }
+    }
+
+    /// Canonicalize the name for krb5 and parse it into its
+    /// components and realm. Names that aren't valid utf8 fail with
+    /// `GSS_S_BAD_NAME`.
+    pub fn krb5_principal(&self) -> Result<Krb5Principal, Error> {
+        let name = self.canonicalize(Some(&GSS_MECH_KRB5))?.display_name()?;
+        let name = std::str::from_utf8(&name).map_err(|_| bad_name())?;
+        Krb5Principal::parse(name)
+    }
+
+    /// Make a `GSS_NT_KRB5_PRINCIPAL` name from `principal`, quoting
+    /// any separators in its components.
+    pub fn from_krb5_principal(principal: &Krb5Principal) -> Result<Self, Error> {
+        Name::new(principal.to_string().as_bytes(), Some(&GSS_NT_KRB5_PRINCIPAL))
+    }
+
+    /// Return true if this name and `other` refer to the same
+    /// entity. An imported name and its canonical form compare equal,
+    /// e.g. the hostbased `host@a.example.com` and the krb5 principal
+    /// `host/a.example.com@REALM`. Comparing two unauthenticated names
+    /// of different types may fail with `GSS_S_BAD_NAMETYPE`.
+    pub fn equal(&self, other: &Name) -> Result<bool, Error> {
+        let mut minor = GSS_S_COMPLETE;
+        let mut equal = 0;
+        let major = unsafe {
+            gss_compare_name(
+                &mut minor as *mut OM_uint32,
+                self.name,
+                other.name,
+                &mut equal as *mut _,
+            )
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(equal != 0)
         } else {
             Err(Error {
                 major: MajorFlags::from_bits_retain(major),
@@ -191,7 +704,10 @@ impl Name {
         }
     }
 
-    /// Duplicate the name.
+    /// Make an independent copy of the name, including its
+    /// attributes, so e.g. `set_attribute` on the copy doesn't affect
+    /// the original. `Name` isn't `Clone`, a shared handle would let
+    /// such changes leak between owners, and copying can fail.
     pub fn duplicate(&self) -> Result<Self, Error> {
         let mut copy = ptr::null_mut::<gss_name_struct>();
         let mut minor = GSS_S_COMPLETE;
@@ -203,7 +719,7 @@ impl Name {
             )
         };
         if major == GSS_S_COMPLETE {
-            Ok(Name(copy))
+            Ok(Name::wrap(copy))
         } else {
             Err(Error {
                 major: MajorFlags::from_bits_retain(major),
@@ -212,3 +728,588 @@ impl Name {
         }
     }
 }
+
+/// Canonicalizes names for one mechanism before they are hashed or
+/// compared, so a table of names can be keyed by the mechanism's
+/// exported form rather than the krb5 one `Name` falls back to, e.g.
+/// for `GSS_MECH_NTLM`. Names that can't be canonicalized are
+/// rejected when they are added or looked up, rather than falling
+/// back to their display form.
+///
+/// ```
+/// # use libgssapi::{name::{Name, NameHasher}, oid::*};
+/// # use std::collections::HashMap;
+/// let hasher = NameHasher::new(&GSS_MECH_KRB5);
+/// let mut perms = HashMap::new();
+/// let alice = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_USER_NAME)).unwrap();
+/// perms.insert(hasher.canonicalize(&alice).unwrap(), "admin");
+/// let peer = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+/// assert_eq!(perms.get(&hasher.canonicalize(&peer).unwrap()), Some(&"admin"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct NameHasher {
+    mech: OwnedOid,
+}
+
+impl NameHasher {
+    /// Canonicalize names for `mech`, which is copied, so it can be
+    /// e.g. an `OwnedOid` from `Oid::from_dotted`
+    pub fn new(mech: &Oid) -> Self {
+        NameHasher { mech: mech.to_owned() }
+    }
+
+    /// The mechanism name for `name`, it compares and hashes by its
+    /// exported form.
+    pub fn canonicalize(&self, name: &Name) -> Result<Name, Error> {
+        let name = name.canonicalize(Some(&self.mech))?;
+        // so the key is computed here, where the error can be seen
+        let key = name.export()?;
+        let _ = name.key.set(NameKey::Exported(Vec::from(&*key)));
+        Ok(name)
+    }
+
+    /// Hash the exported form of `name` canonicalized for the
+    /// mechanism, the same as hashing the result of `canonicalize`.
+    pub fn hash<H: Hasher>(&self, name: &Name, state: &mut H) -> Result<(), Error> {
+        self.canonicalize(name)?.hash(state);
+        Ok(())
+    }
+}
+
+/// Canonicalize for `GSS_MECH_KRB5`
+impl Default for NameHasher {
+    fn default() -> Self {
+        NameHasher::new(&GSS_MECH_KRB5)
+    }
+}
+
+// the minor status MIT's mechglue reports a missing attribute with
+#[cfg(feature = "localname")]
+const ENOENT: u32 = 2;
+
+// MIT's mechglue falls back to the local-login-user attribute when
+// the mechanism has no mapping, and reports that attribute missing
+// instead of the mechanism's status
+#[cfg(feature = "localname")]
+fn localname_error(major: OM_uint32, minor: OM_uint32) -> Error {
+    if major == MajorFlags::GSS_S_UNAVAILABLE.bits() && minor == ENOENT {
+        Error {
+            major: MajorFlags::GSS_S_UNAVAILABLE,
+            minor: KRB5_LNAME_NOTRANS,
+        }
+    } else {
+        Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        }
+    }
+}
+
+fn bad_name() -> Error {
+    Error {
+        major: MajorFlags::GSS_S_BAD_NAME,
+        minor: 0,
+    }
+}
+
+/// A kerberos principal split into its components and realm, e.g.
+/// `host/www.example.com@EXAMPLE.COM` is the components `["host",
+/// "www.example.com"]` and the realm `EXAMPLE.COM`.
+///
+/// ```
+/// # use libgssapi::name::Krb5Principal;
+/// let p = Krb5Principal::parse(r"a\/b/c\@d@EXAMPLE.COM").unwrap();
+/// assert_eq!(p.components, vec!["a/b", "c@d"]);
+/// assert_eq!(p.realm.as_deref(), Some("EXAMPLE.COM"));
+/// assert_eq!(p.to_string(), r"a\/b/c\@d@EXAMPLE.COM");
+/// assert!(Krb5Principal::parse(r"trailing\").is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Krb5Principal {
+    /// The components, the first is the primary (e.g. the user or
+    /// service) and the rest are instances (e.g. the host)
+    pub components: Vec<String>,
+    /// The realm, if the principal has one
+    pub realm: Option<String>,
+}
+
+impl Krb5Principal {
+    /// Make a principal from its parts
+    pub fn new(components: &[&str], realm: Option<&str>) -> Krb5Principal {
+        Krb5Principal {
+            components: components.iter().map(|c| String::from(*c)).collect(),
+            realm: realm.map(String::from),
+        }
+    }
+
+    /// Parse a principal in the krb5 string syntax, where `\/`, `\@`
+    /// and `\\` quote separators and backslash, and `\n`, `\t`, `\b`
+    /// and `\0` are control characters. Malformed principals fail
+    /// with `GSS_S_BAD_NAME`.
+    pub fn parse(s: &str) -> Result<Krb5Principal, Error> {
+        let mut components = vec![String::new()];
+        let mut realm: Option<String> = None;
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            let c = match c {
+                '\\' => match chars.next().ok_or_else(bad_name)? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'b' => '\x08',
+                    '0' => '\0',
+                    c => c,
+                },
+                '@' if realm.is_none() => {
+                    realm = Some(String::new());
+                    continue;
+                }
+                '@' => return Err(bad_name()),
+                '/' if realm.is_none() => {
+                    components.push(String::new());
+                    continue;
+                }
+                c => c,
+            };
+            match &mut realm {
+                Some(realm) => realm.push(c),
+                None => components.last_mut().unwrap().push(c),
+            }
+        }
+        Ok(Krb5Principal { components, realm })
+    }
+
+    /// The first component, e.g. the user or service
+    pub fn primary(&self) -> &str {
+        self.components.first().map(|c| c.as_str()).unwrap_or("")
+    }
+
+    /// The components after the primary, e.g. the host of a service
+    pub fn instance(&self) -> &[String] {
+        self.components.get(1..).unwrap_or(&[])
+    }
+}
+
+fn write_quoted(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '/' => f.write_str("\\/")?,
+            '@' => f.write_str("\\@")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\x08' => f.write_str("\\b")?,
+            '\0' => f.write_str("\\0")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    Ok(())
+}
+
+/// The krb5 string syntax, with separators in the components and
+/// realm quoted
+impl fmt::Display for Krb5Principal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, c) in self.components.iter().enumerate() {
+            if i > 0 {
+                f.write_str("/")?;
+            }
+            write_quoted(f, c)?;
+        }
+        if let Some(realm) = &self.realm {
+            f.write_str("@")?;
+            write_quoted(f, realm)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "name_attributes")]
+mod attrs {
+    use super::*;
+    use crate::util::BufSet;
+    use libgssapi_sys::{
+        gss_delete_name_attribute, gss_display_name_ext, gss_get_name_attribute,
+        gss_inquire_name, gss_set_name_attribute,
+    };
+    use std::os::raw::c_int;
+
+    /// One value of a name attribute
+    #[derive(Debug)]
+    pub struct NameAttributeValue {
+        /// The raw value
+        pub value: Buf,
+        /// The value formatted for display, it may be empty
+        pub display_value: Buf,
+    }
+
+    /// A name attribute (rfc6680) with all its values, see
+    /// `Name::attribute`
+    #[derive(Debug)]
+    pub struct NameAttribute {
+        /// True if all the values were vouched for by the mechanism
+        /// rather than asserted by the peer
+        pub authenticated: bool,
+        /// True if the values are the complete set, and the absence
+        /// of a value can be relied upon
+        pub complete: bool,
+        pub values: Vec<NameAttributeValue>,
+    }
+
+    impl Name {
+        /// Get all the values of the name attribute `attr` (rfc6680),
+        /// e.g. `urn:mspac:logon-info` for the krb5 PAC, and whether
+        /// they are all authenticated, i.e. vouched for by the
+        /// mechanism rather than asserted by the peer. A name that
+        /// doesn't have the attribute fails with `GSS_S_UNAVAILABLE`.
+        pub fn get_attr(&self, attr: &[u8]) -> Result<(BufSet, bool), Error> {
+            let attr = self.attribute(attr)?;
+            let mut values = BufSet::new()?;
+            for v in &attr.values {
+                values.add(&v.value)?;
+            }
+            Ok((values, attr.authenticated))
+        }
+
+        /// Get the name attribute `attr` (rfc6680) with all its
+        /// values and their display forms. A name that doesn't have
+        /// the attribute, or a mechanism that doesn't support
+        /// attributes, fails with `GSS_S_UNAVAILABLE`.
+        pub fn attribute(&self, attr: &[u8]) -> Result<NameAttribute, Error> {
+            let mut attr = BufRef::from(attr);
+            let mut res = NameAttribute {
+                authenticated: true,
+                complete: true,
+                values: Vec::new(),
+            };
+            // -1 asks for the first value, then it's the index of the next
+            let mut more: c_int = -1;
+            while more != 0 {
+                let mut value = Buf::empty();
+                let mut display_value = Buf::empty();
+                let mut minor = GSS_S_COMPLETE;
+                let mut auth: c_int = 0;
+                let mut complete: c_int = 0;
+                let major = unsafe {
+                    gss_get_name_attribute(
+                        &mut minor as *mut OM_uint32,
+                        self.name,
+                        attr.to_c(),
+                        &mut auth as *mut c_int,
+                        &mut complete as *mut c_int,
+                        value.to_c(),
+                        display_value.to_c(),
+                        &mut more as *mut c_int,
+                    )
+                };
+                if major != GSS_S_COMPLETE {
+                    return Err(Error {
+                        major: MajorFlags::from_bits_retain(major),
+                        minor,
+                    });
+                }
+                res.authenticated &= auth != 0;
+                res.complete &= complete != 0;
+                res.values.push(NameAttributeValue { value, display_value });
+            }
+            Ok(res)
+        }
+
+        /// List the names of the attributes the name has (rfc6680),
+        /// like `enum_attrs`, but as individual buffers.
+        pub fn attributes(&self) -> Result<Vec<Buf>, Error> {
+            Ok(self.enum_attrs()?.into_bufs())
+        }
+
+        /// Add `value` to the name attribute `attr` (rfc6680). If
+        /// `complete` is true the attribute is marked as having its
+        /// complete set of values, see `NameAttribute::complete`.
+        /// Values set locally are never authenticated. Mechanisms only
+        /// allow setting the attributes they know about, others fail
+        /// with `GSS_S_UNAVAILABLE`.
+        pub fn set_attribute(
+            &mut self,
+            attr: &[u8],
+            value: &[u8],
+            complete: bool,
+        ) -> Result<(), Error> {
+            let mut attr = BufRef::from(attr);
+            let mut value = BufRef::from(value);
+            let mut minor = GSS_S_COMPLETE;
+            let major = unsafe {
+                gss_set_name_attribute(
+                    &mut minor as *mut OM_uint32,
+                    self.name,
+                    complete as c_int,
+                    attr.to_c(),
+                    value.to_c(),
+                )
+            };
+            if major == GSS_S_COMPLETE {
+                Ok(())
+            } else {
+                Err(Error {
+                    major: MajorFlags::from_bits_retain(major),
+                    minor,
+                })
+            }
+        }
+
+        /// Remove the name attribute `attr` and all its values
+        /// (rfc6680)
+        pub fn delete_attribute(&mut self, attr: &[u8]) -> Result<(), Error> {
+            let mut attr = BufRef::from(attr);
+            let mut minor = GSS_S_COMPLETE;
+            let major = unsafe {
+                gss_delete_name_attribute(
+                    &mut minor as *mut OM_uint32,
+                    self.name,
+                    attr.to_c(),
+                )
+            };
+            if major == GSS_S_COMPLETE {
+                Ok(())
+            } else {
+                Err(Error {
+                    major: MajorFlags::from_bits_retain(major),
+                    minor,
+                })
+            }
+        }
+
+        /// Display the name as the name type `format` (rfc6680),
+        /// which need not be its own type. Mechanisms that can't
+        /// display the name that way fail with `GSS_S_UNAVAILABLE`,
+        /// MIT krb5 only displays mechanism names as
+        /// `GSS_NT_KRB5_PRINCIPAL`.
+        pub fn display_ext(&self, format: &Oid) -> Result<Buf, Error> {
+            let mut out = Buf::empty();
+            let mut minor = GSS_S_COMPLETE;
+            let major = unsafe {
+                gss_display_name_ext(
+                    &mut minor as *mut OM_uint32,
+                    self.name,
+                    format.to_c(),
+                    out.to_c(),
+                )
+            };
+            if major == GSS_S_COMPLETE {
+                Ok(out)
+            } else {
+                Err(Error {
+                    major: MajorFlags::from_bits_retain(major),
+                    minor,
+                })
+            }
+        }
+
+        /// List the names of the attributes the name has (rfc6680),
+        /// to pass to `get_attr`. Names without attributes, e.g. ones
+        /// that weren't authenticated, return an empty set.
+        pub fn enum_attrs(&self) -> Result<BufSet, Error> {
+            let mut attrs = BufSet::empty();
+            let mut minor = GSS_S_COMPLETE;
+            let major = unsafe {
+                gss_inquire_name(
+                    &mut minor as *mut OM_uint32,
+                    self.name,
+                    ptr::null_mut::<c_int>(),
+                    ptr::null_mut::<gss_OID>(),
+                    attrs.to_c(),
+                )
+            };
+            if major == GSS_S_COMPLETE {
+                Ok(attrs)
+            } else {
+                Err(Error {
+                    major: MajorFlags::from_bits_retain(major),
+                    minor,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(feature = "name_attributes")]
+pub use attrs::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::WellKnownError;
+    use std::collections::{hash_map::DefaultHasher, HashMap, HashSet};
+
+    // 1.2.3.4, a name type no mechanism knows
+    static OTHER: Oid = Oid::from_slice(b"\x2a\x03\x04");
+
+    fn hash(name: &Name) -> u64 {
+        let mut h = DefaultHasher::new();
+        name.hash(&mut h);
+        h.finish()
+    }
+
+    #[test]
+    fn spellings_of_one_principal_collide() {
+        let n0 = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+        let n1 = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_USER_NAME)).unwrap();
+        assert!(matches!(n0.key(), NameKey::Exported(_)));
+        assert_eq!(n0, n1);
+        assert_eq!(hash(&n0), hash(&n1));
+    }
+
+    #[test]
+    fn display_key_never_equals_exported_key() {
+        let n0 = Name::new("alice@EXAMPLE.COM", Some(&OTHER)).unwrap();
+        let n1 = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+        assert_eq!(n0.key(), &NameKey::Display(b"alice@EXAMPLE.COM".to_vec()));
+        assert!(matches!(n1.key(), NameKey::Exported(_)));
+        assert_ne!(n0, n1);
+    }
+
+    #[test]
+    fn display_keyed_names_use_compare_name() {
+        let n0 = Name::new("alice@EXAMPLE.COM", Some(&OTHER)).unwrap();
+        let n1 = Name::new("alice@EXAMPLE.COM", Some(&OTHER)).unwrap();
+        let n2 = Name::new("bob@EXAMPLE.COM", Some(&OTHER)).unwrap();
+        assert_eq!(n0, n1);
+        assert_eq!(hash(&n0), hash(&n1));
+        assert_ne!(n0, n2);
+    }
+
+    #[test]
+    fn unkeyed_names() {
+        let n0 = Name::wrap(ptr::null_mut());
+        let n1 = Name::wrap(ptr::null_mut());
+        assert_eq!(n0.key(), &NameKey::Unkeyed);
+        assert_eq!(n0, n0);
+        assert_ne!(n0, n1);
+        assert_eq!(hash(&n0), hash(&n1));
+        let set = HashSet::from([n0, n1]);
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn map_lookup_before_and_after_canonicalization() {
+        let mut perms = HashMap::new();
+        let alice = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_USER_NAME)).unwrap();
+        let alice_mn = alice.canonicalize(Some(&GSS_MECH_KRB5)).unwrap();
+        perms.insert(alice, "admin");
+        assert_eq!(perms.get(&alice_mn), Some(&"admin"));
+        let mut perms = HashMap::new();
+        let alice = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_USER_NAME)).unwrap();
+        perms.insert(alice_mn, "admin");
+        assert_eq!(perms.get(&alice), Some(&"admin"));
+        let bob = Name::new("bob@EXAMPLE.COM", Some(&GSS_NT_USER_NAME)).unwrap();
+        assert_eq!(perms.get(&bob), None);
+    }
+
+    #[cfg(feature = "localname")]
+    #[test]
+    fn localname_keeps_the_minor_status() {
+        use crate::error::{KRB5_LNAME_NOTRANS, KRB5_NO_LOCALNAME};
+        let n = Name::new("alice@UNMAPPED.INVALID", Some(&GSS_NT_KRB5_PRINCIPAL))
+            .unwrap();
+        let e = n.localname(None).unwrap_err();
+        let minor = e.minor == KRB5_NO_LOCALNAME || e.minor == KRB5_LNAME_NOTRANS;
+        assert_eq!(e.is_no_local_mapping(), minor);
+    }
+
+    #[test]
+    fn display_matches_display_name() {
+        let names = [
+            Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap(),
+            Name::new("HTTP@www.example.com", Some(&GSS_NT_HOSTBASED_SERVICE)).unwrap(),
+            Name::new("a\\/b/c\\@d@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap(),
+            Name::new("bob", None).unwrap(),
+        ];
+        for name in &names {
+            let raw = name.display_name().unwrap();
+            assert_eq!(name.to_string().as_bytes(), &*raw);
+        }
+        assert_eq!(names[2].to_string(), r"a\/b/c\@d@EXAMPLE.COM");
+        let name = Name::wrap(ptr::null_mut());
+        assert!(name.display_name().is_err());
+        assert_eq!(name.to_string(), "<unprintable gss name>");
+    }
+
+    #[test]
+    fn embedded_nul_rejected() {
+        let bad_name = |r: Result<Name, Error>| {
+            assert_eq!(r.unwrap_err().kind(), Some(WellKnownError::BadName));
+        };
+        for s in ["alice\0@EXAMPLE.COM", "alice@EXAMPLE.COM\0", "\0"] {
+            bad_name(Name::new(s, None));
+            bad_name(Name::new(s, Some(&GSS_NT_KRB5_PRINCIPAL)));
+            bad_name(Name::new(s, Some(&GSS_NT_HOSTBASED_SERVICE)));
+            bad_name(Name::try_from(s));
+            bad_name(Name::try_from(s.as_bytes()));
+            bad_name(s.parse());
+        }
+        let name = Name::try_from("alice@EXAMPLE.COM").unwrap();
+        assert_eq!(name.to_string(), "alice@EXAMPLE.COM");
+        // exported names are binary, the NULs in the length field are
+        // fine
+        let n = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+        let exported = n.export_for(&GSS_MECH_KRB5).unwrap();
+        assert!(exported.contains(&0));
+        assert!(Name::from_exported(&exported).unwrap().equal(&n).unwrap());
+    }
+
+    #[test]
+    fn raw_round_trip_releases_once() {
+        let released = || RELEASED.with(|n| n.get());
+        let start = released();
+        let name = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+        let raw = name.into_raw();
+        assert!(!raw.is_null());
+        assert_eq!(released(), start);
+        let name = unsafe { Name::from_raw(raw) };
+        assert_eq!(name.as_raw(), raw);
+        assert_eq!(name.to_string(), "alice@EXAMPLE.COM");
+        drop(name);
+        assert_eq!(released(), start + 1);
+        // released by the caller, not by us
+        let mut raw = Name::new("bob", None).unwrap().into_raw();
+        let mut minor = GSS_S_COMPLETE;
+        let major = unsafe { gss_release_name(&mut minor, &mut raw) };
+        assert_eq!(major, GSS_S_COMPLETE);
+        assert!(raw.is_null());
+        assert_eq!(released(), start + 1);
+        drop(Name::wrap(ptr::null_mut()));
+        assert_eq!(released(), start + 1);
+    }
+
+    #[test]
+    fn high_bit_principal() {
+        // latin-1 e acute, and a quoted slash in the same component
+        let raw: &[u8] = b"caf\xe9\\/bar@EXAMPLE.COM";
+        let name = Name::new(raw, Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+        assert_eq!(&*name.display_name().unwrap(), raw);
+        match name.to_utf8_string() {
+            Err(NameDisplayError::Utf8(e)) => assert_eq!(e.into_bytes(), raw),
+            r => panic!("expected a utf8 error, got {:?}", r),
+        }
+        let lossy = name.to_string_lossy().unwrap();
+        assert_eq!(lossy, "caf\u{fffd}\\/bar@EXAMPLE.COM");
+        // the quoting survives canonicalization, the byte is untouched
+        let canon = name.canonicalize(Some(&GSS_MECH_KRB5)).unwrap();
+        assert_eq!(&*canon.display_name().unwrap(), raw);
+    }
+
+    #[test]
+    fn name_hasher() {
+        let hasher = NameHasher::default();
+        let n0 = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_USER_NAME)).unwrap();
+        let n1 = Name::new("alice@EXAMPLE.COM", Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap();
+        let (mut h0, mut h1) = (DefaultHasher::new(), DefaultHasher::new());
+        hasher.hash(&n0, &mut h0).unwrap();
+        hasher.hash(&n1, &mut h1).unwrap();
+        assert_eq!(h0.finish(), h1.finish());
+        assert_eq!(hasher.canonicalize(&n0).unwrap(), n1);
+        let n2 = Name::new("alice@EXAMPLE.COM", Some(&OTHER)).unwrap();
+        assert!(hasher.canonicalize(&n2).is_err());
+        assert!(hasher.hash(&n2, &mut h0).is_err());
+        // the mechanism needn't be static
+        let hasher = NameHasher::new(&Oid::from_dotted("1.2.840.113554.1.2.2").unwrap());
+        assert_eq!(hasher.canonicalize(&n0).unwrap(), n1);
+    }
+}
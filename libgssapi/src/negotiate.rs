@@ -0,0 +1,158 @@
+//! Helpers for carrying gssapi tokens over HTTP using the `Negotiate`
+//! authentication scheme (rfc4559).
+//!
+//! A client sends `Authorization: Negotiate <token>` and the server
+//! replies with either `401` and `WWW-Authenticate: Negotiate <token>`
+//! when it needs another round trip, or with the real response
+//! (possibly carrying a final token for mutual authentication) when
+//! the context is established.
+use crate::{
+    context::{ClientCtx, SecurityContext, ServerCtx},
+    error::{Error, MajorFlags},
+    name::Name,
+    util::{decode_base64, encode_base64},
+};
+
+#[cfg(feature = "reqwest")]
+pub mod reqwest;
+#[cfg(feature = "tower")]
+pub mod tower;
+
+/// gssapi may block (e.g. talking to the KDC), so run `f` on the
+/// blocking pool
+#[cfg(any(feature = "reqwest", feature = "tower"))]
+async fn blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(t) => t,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// The name of the HTTP authentication scheme. A server that wants
+/// the client to start a handshake replies `401` with this as the
+/// value of the `WWW-Authenticate` header.
+pub const NEGOTIATE: &str = "Negotiate";
+
+fn split_scheme(value: &str) -> Option<&str> {
+    let value = value.trim();
+    let (scheme, rest) = match value.find(char::is_whitespace) {
+        None => (value, ""),
+        Some(i) => (&value[..i], value[i..].trim()),
+    };
+    if scheme.eq_ignore_ascii_case(NEGOTIATE) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+fn decode_token(tok: &str) -> Result<Option<Vec<u8>>, Error> {
+    if tok.is_empty() {
+        Ok(None)
+    } else {
+        decode_base64(tok).map(Some)
+    }
+}
+
+/// Format a token as the value of an `Authorization` or
+/// `WWW-Authenticate` header.
+pub fn format_header(tok: &[u8]) -> String {
+    format!("{} {}", NEGOTIATE, encode_base64(tok))
+}
+
+/// Parse the value of an `Authorization` header. Returns the decoded
+/// token, or `None` if the header is a bare `Negotiate` with no
+/// token. Headers using any other scheme are rejected with
+/// `GSS_S_BAD_MECH`, and invalid tokens with `GSS_S_DEFECTIVE_TOKEN`.
+pub fn parse_header(value: &str) -> Result<Option<Vec<u8>>, Error> {
+    match split_scheme(value) {
+        Some(tok) => decode_token(tok),
+        None => Err(Error {
+            major: MajorFlags::GSS_S_BAD_MECH,
+            minor: 0,
+        }),
+    }
+}
+
+/// Parse the value of a `WWW-Authenticate` header, which may list
+/// several comma separated challenges, and return the token from the
+/// `Negotiate` challenge (if it carries one).
+pub fn parse_challenge(value: &str) -> Result<Option<Vec<u8>>, Error> {
+    match value.split(',').find_map(split_scheme) {
+        Some(tok) => decode_token(tok),
+        None => Err(Error {
+            major: MajorFlags::GSS_S_BAD_MECH,
+            minor: 0,
+        }),
+    }
+}
+
+/// Perform 1 step of the client side of the handshake. Pass `None`
+/// as the challenge to produce the initial `Authorization` header,
+/// then pass the `WWW-Authenticate` header of each `401` response
+/// (and of the final response, if it has one, so mutual
+/// authentication can be verified). Returns the value of the next
+/// `Authorization` header to send, or `None` once the context is
+/// established.
+///
+/// A challenge that isn't `Negotiate` is rejected without touching
+/// the context.
+pub fn client_step(
+    ctx: &mut ClientCtx,
+    challenge: Option<&str>,
+    channel_bindings: Option<&[u8]>,
+) -> Result<Option<String>, Error> {
+    let tok = match challenge {
+        None => None,
+        Some(challenge) => parse_challenge(challenge)?,
+    };
+    let out = ctx.step(tok.as_deref(), channel_bindings)?;
+    Ok(out.map(|tok| format_header(&tok)))
+}
+
+/// The outcome of feeding an `Authorization` header to a server
+/// context.
+#[derive(Debug)]
+pub enum ServerStep {
+    /// The handshake needs another round trip. Reply `401` with this
+    /// as the value of the `WWW-Authenticate` header.
+    Continue(String),
+    /// The client is authenticated as `name`. If `token` is present
+    /// it should be sent to the client in a `WWW-Authenticate` header
+    /// along with the response so it can complete mutual
+    /// authentication.
+    Done { name: Name, token: Option<String> },
+}
+
+/// Perform 1 step of the server side of the handshake, feeding it the
+/// value of the client's `Authorization` header.
+///
+/// A header that isn't `Negotiate`, or that doesn't carry a token, is
+/// rejected without touching the context.
+pub fn server_step(ctx: &mut ServerCtx, authorization: &str) -> Result<ServerStep, Error> {
+    let tok = match parse_header(authorization)? {
+        Some(tok) => tok,
+        None => {
+            return Err(Error {
+                major: MajorFlags::GSS_S_DEFECTIVE_TOKEN,
+                minor: 0,
+            })
+        }
+    };
+    let out = ctx.step(&tok)?;
+    if ctx.is_complete() {
+        Ok(ServerStep::Done {
+            name: ctx.source_name()?,
+            token: out.map(|tok| format_header(&tok)),
+        })
+    } else {
+        match out {
+            Some(tok) => Ok(ServerStep::Continue(format_header(&tok))),
+            None => Ok(ServerStep::Continue(String::from(NEGOTIATE))),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Transparent `Negotiate` authentication for reqwest clients.
+use super::{blocking, client_step, parse_challenge};
+use crate::{
+    context::{ClientCtx, CtxFlags, SecurityContext},
+    credential::{Cred, CredUsage},
+    error::Error,
+    name::Name,
+    oid::GSS_MECH_SPNEGO,
+};
+use ::reqwest::{
+    header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE},
+    Client, Request, Response, StatusCode,
+};
+use std::{error, fmt};
+
+/// Give up if the server is still asking for tokens after this many
+/// round trips.
+const MAX_LEGS: usize = 8;
+
+/// The ways `send` can fail
+#[derive(Debug)]
+pub enum NegotiateError {
+    /// Acquiring credentials or establishing the context failed, or
+    /// the server sent a malformed `Negotiate` challenge
+    Gss(Error),
+    /// Sending a request failed
+    Http(::reqwest::Error),
+}
+
+impl fmt::Display for NegotiateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NegotiateError::Gss(e) => write!(f, "gssapi error: {}", e),
+            NegotiateError::Http(e) => write!(f, "http error: {}", e),
+        }
+    }
+}
+
+impl error::Error for NegotiateError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            NegotiateError::Gss(e) => Some(e),
+            NegotiateError::Http(e) => Some(e),
+        }
+    }
+}
+
+impl From<Error> for NegotiateError {
+    fn from(e: Error) -> Self {
+        NegotiateError::Gss(e)
+    }
+}
+
+impl From<::reqwest::Error> for NegotiateError {
+    fn from(e: ::reqwest::Error) -> Self {
+        NegotiateError::Http(e)
+    }
+}
+
+fn challenge(resp: &Response) -> Option<&str> {
+    resp.headers()
+        .get_all(WWW_AUTHENTICATE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find(|v| parse_challenge(v).is_ok())
+}
+
+/// Send `req` using `client`. If the server replies `401` with a
+/// `Negotiate` challenge then acquire the default initiator
+/// credentials, establish a SPNEGO context with the `HTTP@<host>`
+/// service requesting `flags`, and retry the request with an
+/// `Authorization` header for as many round trips as the server
+/// needs.
+///
+/// Returns the final response, along with the client context if
+/// authentication was attempted. If you requested
+/// `GSS_C_MUTUAL_FLAG`, check that the context `is_complete` and that
+/// its `flags` contain `GSS_C_MUTUAL_FLAG` to be sure the server
+/// really proved its identity.
+///
+/// Only requests that can be cloned (e.g. that don't have a streaming
+/// body) can be retried, the first response to any other request is
+/// returned as is.
+///
+/// Acquiring the credentials and each step of the handshake run on
+/// tokio's blocking pool, since they may wait on the KDC.
+pub async fn send(
+    client: &Client,
+    req: Request,
+    flags: CtxFlags,
+) -> Result<(Response, Option<ClientCtx>), NegotiateError> {
+    let retry = match req.try_clone() {
+        Some(retry) => retry,
+        None => return Ok((client.execute(req).await?, None)),
+    };
+    let mut resp = client.execute(req).await?;
+    if resp.status() != StatusCode::UNAUTHORIZED || challenge(&resp).is_none() {
+        return Ok((resp, None));
+    }
+    let host = match retry.url().host_str() {
+        Some(host) => host.to_owned(),
+        None => return Ok((resp, None)),
+    };
+    let (mut ctx, mut tok) = blocking(move || -> Result<_, Error> {
+        let target = Name::service("HTTP", &host)?;
+        let cred = Cred::acquire_default(CredUsage::Initiate)?;
+        let mut ctx = ClientCtx::new(Some(cred), target, flags, Some(&GSS_MECH_SPNEGO));
+        let tok = client_step(&mut ctx, None, None)?;
+        Ok((ctx, tok))
+    })
+    .await?;
+    for _ in 0..MAX_LEGS {
+        let auth = match tok.take() {
+            None => break,
+            Some(auth) => auth,
+        };
+        let mut req = retry.try_clone().expect("request was cloneable");
+        let auth = HeaderValue::from_str(&auth).expect("base64 is a valid header value");
+        req.headers_mut().insert(AUTHORIZATION, auth);
+        resp = client.execute(req).await?;
+        match challenge(&resp) {
+            Some(c) if !ctx.is_complete() && parse_challenge(c)?.is_some() => {
+                // either another leg, or the server's final token
+                // proving its identity to us
+                let c = c.to_owned();
+                let (next, res) = blocking(move || {
+                    let res = client_step(&mut ctx, Some(&c), None);
+                    (ctx, res)
+                })
+                .await;
+                ctx = next;
+                tok = res?;
+                if resp.status() != StatusCode::UNAUTHORIZED {
+                    break;
+                }
+            }
+            Some(_) | None => break,
+        }
+    }
+    Ok((resp, Some(ctx)))
+}
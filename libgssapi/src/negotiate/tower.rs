@@ -0,0 +1,213 @@
+//! A tower layer that requires clients to authenticate with
+//! `Negotiate` before requests reach the inner service.
+//!
+//! The layer should be applied once per connection (e.g. in your
+//! accept loop), because a multi leg handshake is carried over
+//! successive requests on the same connection and the partially
+//! established context lives in the service.
+//!
+//! Acquiring credentials and stepping the context run on tokio's
+//! blocking pool, since they may wait on the KDC or the replay cache.
+use super::{blocking, server_step, split_scheme, ServerStep, NEGOTIATE};
+use crate::{
+    context::ServerCtx,
+    credential::Cred,
+    error::Error,
+    name::Name,
+};
+use http::{
+    header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE},
+    Request, Response, StatusCode,
+};
+use std::{
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Inserted into the request extensions once the client has
+/// authenticated.
+#[derive(Debug, Clone)]
+pub struct Authenticated {
+    /// The authenticated client
+    pub name: Arc<Name>,
+    /// The credentials the client delegated to us, if any
+    pub delegated_cred: Option<Arc<Cred>>,
+}
+
+type Acquire = dyn Fn() -> Result<Cred, Error> + Send + Sync;
+
+/// Wraps a service so it only sees authenticated requests
+#[derive(Clone)]
+pub struct NegotiateLayer {
+    acquire: Arc<Acquire>,
+}
+
+impl fmt::Debug for NegotiateLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NegotiateLayer").finish_non_exhaustive()
+    }
+}
+
+impl NegotiateLayer {
+    /// `acquire` is called at the start of each handshake to get the
+    /// acceptor credentials, e.g.
+    /// `|| Cred::acquire_default(CredUsage::Accept)` to
+    /// accept any service in the default keytab.
+    pub fn new<F>(acquire: F) -> NegotiateLayer
+    where
+        F: Fn() -> Result<Cred, Error> + Send + Sync + 'static,
+    {
+        NegotiateLayer { acquire: Arc::new(acquire) }
+    }
+}
+
+impl<S> Layer<S> for NegotiateLayer {
+    type Service = NegotiateService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        NegotiateService {
+            inner,
+            acquire: Arc::clone(&self.acquire),
+            ctx: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+/// The service produced by `NegotiateLayer`. Clones share the
+/// handshake in progress, so they must serve the same connection.
+///
+/// Requests without a `Negotiate` `Authorization` header get a `401`
+/// challenge, and requests whose token can't be accepted get a `403`.
+/// Neither reaches the inner service.
+#[derive(Clone)]
+pub struct NegotiateService<S> {
+    inner: S,
+    acquire: Arc<Acquire>,
+    ctx: Arc<Mutex<Option<ServerCtx>>>,
+}
+
+impl<S: fmt::Debug> fmt::Debug for NegotiateService<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NegotiateService")
+            .field("inner", &self.inner)
+            .field("ctx", &self.ctx)
+            .finish_non_exhaustive()
+    }
+}
+
+fn reply<B: Default>(status: StatusCode, challenge: Option<&str>) -> Response<B> {
+    let mut resp = Response::new(B::default());
+    *resp.status_mut() = status;
+    if let Some(challenge) = challenge {
+        let v = HeaderValue::from_str(challenge).expect("base64 is a valid header value");
+        resp.headers_mut().insert(WWW_AUTHENTICATE, v);
+    }
+    resp
+}
+
+enum Outcome {
+    Challenge(String),
+    Forbidden,
+    Authenticated(Authenticated, Option<String>),
+}
+
+/// Feed `authorization` to the handshake in progress, or start a new
+/// one. Returns the context to keep for the next leg along with the
+/// outcome. This blocks, run it on the blocking pool.
+fn authenticate(
+    acquire: &Acquire,
+    ctx: Option<ServerCtx>,
+    authorization: &str,
+) -> (Option<ServerCtx>, Outcome) {
+    let mut ctx = match ctx {
+        Some(ctx) => ctx,
+        None => match acquire() {
+            Ok(cred) => ServerCtx::new(cred),
+            Err(_) => return (None, Outcome::Forbidden),
+        },
+    };
+    match server_step(&mut ctx, authorization) {
+        Err(_) => (None, Outcome::Forbidden),
+        Ok(ServerStep::Continue(challenge)) => {
+            (Some(ctx), Outcome::Challenge(challenge))
+        }
+        Ok(ServerStep::Done { name, token }) => {
+            let auth = Authenticated {
+                name: Arc::new(name),
+                delegated_cred: ctx.take_delegated_cred().map(Arc::new),
+            };
+            (None, Outcome::Authenticated(auth, token))
+        }
+    }
+}
+
+impl<S, B, ResBody> Service<Request<B>> for NegotiateService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+    ResBody: Default + Send + 'static,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        // poll_ready readied this one, leave a clone for the next call
+        let clone = self.inner.clone();
+        let mut inner = mem::replace(&mut self.inner, clone);
+        let acquire = Arc::clone(&self.acquire);
+        let cur = Arc::clone(&self.ctx);
+        let authorization = req
+            .headers()
+            .get(AUTHORIZATION)
+            .map(|v| v.to_str().map(String::from));
+        Box::pin(async move {
+            let outcome = match authorization {
+                None => Outcome::Challenge(String::from(NEGOTIATE)),
+                Some(Err(_)) => Outcome::Forbidden,
+                Some(Ok(authorization)) if split_scheme(&authorization).is_none() => {
+                    // some other scheme, tell the client we want Negotiate
+                    Outcome::Challenge(String::from(NEGOTIATE))
+                }
+                Some(Ok(authorization)) => {
+                    // the lock isn't held during the step. If this
+                    // future is dropped meanwhile the handshake is lost
+                    // and the client's next leg is refused
+                    let ctx = cur.lock().unwrap().take();
+                    let (ctx, outcome) =
+                        blocking(move || authenticate(&*acquire, ctx, &authorization))
+                            .await;
+                    *cur.lock().unwrap() = ctx;
+                    outcome
+                }
+            };
+            match outcome {
+                Outcome::Challenge(challenge) => {
+                    Ok(reply(StatusCode::UNAUTHORIZED, Some(&challenge)))
+                }
+                Outcome::Forbidden => Ok(reply(StatusCode::FORBIDDEN, None)),
+                Outcome::Authenticated(auth, token) => {
+                    req.extensions_mut().insert(auth);
+                    let mut resp = inner.call(req).await?;
+                    if let Some(token) = token {
+                        let v = HeaderValue::from_str(&token)
+                            .expect("base64 is a valid header value");
+                        resp.headers_mut().insert(WWW_AUTHENTICATE, v);
+                    }
+                    Ok(resp)
+                }
+            }
+        })
+    }
+}
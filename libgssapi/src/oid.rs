@@ -2,13 +2,20 @@
 /// horrible. This module is horrible. I'm so pleased to share my
 /// horror with you.
 use crate::error::{Error, MajorFlags};
+use crate::util::Buf;
 use libgssapi_sys::{
     gss_OID, gss_OID_desc, gss_OID_set, gss_OID_set_desc, gss_add_oid_set_member,
-    gss_create_empty_oid_set, gss_release_oid_set, gss_test_oid_set_member, OM_uint32,
+    gss_create_empty_oid_set, gss_display_mech_attr, gss_indicate_mechs_by_attrs,
+    gss_inquire_attrs_for_mech, gss_release_oid_set, gss_test_oid_set_member, OM_uint32,
     GSS_S_COMPLETE,
 };
+#[cfg(feature = "mit_kerberos")]
+use crate::util::BufRef;
+#[cfg(feature = "mit_kerberos")]
+use libgssapi_sys::{gss_oid_to_str, gss_release_oid, gss_str_to_oid};
 use std::{
     self,
+    borrow::Borrow,
     cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd},
     collections::HashMap,
     fmt,
@@ -17,9 +24,9 @@ use std::{
     ops::{Deref, Index},
     ptr, slice,
     os::raw::c_int,
+    str::FromStr,
 };
 
-// CR estokes: do I need the attributes from rfc 5587? There are loads of them.
 pub static GSS_NT_USER_NAME: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x01\x01");
 
@@ -32,7 +39,7 @@ pub static GSS_NT_STRING_UID_NAME: Oid =
 pub static GSS_NT_HOSTBASED_SERVICE: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x01\x04");
 
-pub static GSS_NT_ANONYMOUS: Oid = Oid::from_slice(b"\x2b\x06\01\x05\x06\x03");
+pub static GSS_NT_ANONYMOUS: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x06\x03");
 
 pub static GSS_NT_EXPORT_NAME: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x06\x04");
 
@@ -41,6 +48,18 @@ pub static GSS_NT_COMPOSITE_EXPORT: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x06
 pub static GSS_NT_KRB5_PRINCIPAL: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x01");
 
+pub static GSS_NT_KRB5_ENTERPRISE_NAME: Oid =
+    Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x06");
+
+/// The ticket flags of a krb5 context, for `inquire_by_oid`
+pub static GSS_KRB5_GET_TKT_FLAGS_X: Oid =
+    Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x05\x01");
+
+/// The prefix of the oid that extracts authorization data of a given
+/// type from a krb5 context, the type is appended as another arc
+pub static GSS_KRB5_EXTRACT_AUTHZ_DATA_FROM_SEC_CONTEXT_X: Oid =
+    Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x05\x0a");
+
 pub static GSS_INQ_SSPI_SESSION_KEY: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x05\x05");
 
@@ -53,6 +72,22 @@ pub static GSS_INQ_NEGOEX_VERIFY_KEY: Oid =
 pub static GSS_MA_NEGOEX_AND_SPNEGO: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x05\x12");
 
+// the mechanism attributes from rfc5587 that are useful for choosing
+// a mechanism, see `indicate_mechs_by_attrs`. The rest can be made
+// with `Oid::from_dotted` (they are 1.3.6.1.5.5.13.n), and
+// `inquire_attrs_for_mech` lists the ones a mechanism knows.
+pub static GSS_MA_MECH_CONCRETE: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x01");
+pub static GSS_MA_MECH_NEGO: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x04");
+pub static GSS_MA_AUTH_INIT_ANON: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x0e");
+pub static GSS_MA_AUTH_TARG_ANON: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x0f");
+pub static GSS_MA_DELEG_CRED: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x10");
+pub static GSS_MA_INTEG_PROT: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x11");
+pub static GSS_MA_CONF_PROT: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x12");
+pub static GSS_MA_PROT_READY: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x15");
+pub static GSS_MA_REPLAY_DET: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x16");
+pub static GSS_MA_OOS_DET: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x17");
+pub static GSS_MA_CBINDINGS: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x0d\x18");
+
 pub static GSS_SEC_CONTEXT_SASL_SSF: Oid =
     Oid::from_slice(b"\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x05\x0f");
 
@@ -62,6 +97,9 @@ pub static GSS_MECH_IAKERB: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x02\x05");
 
 pub static GSS_MECH_SPNEGO: Oid = Oid::from_slice(b"\x2b\x06\x01\x05\x05\x02");
 
+pub static GSS_MECH_NTLM: Oid =
+    Oid::from_slice(b"\x2b\x06\x01\x04\x01\x82\x37\x02\x02\x0a");
+
 pub static GSS_KRB5_CRED_NO_CI_FLAGS_X: Oid =
     Oid::from_slice(b"\x2a\x85\x70\x2b\x0d\x1d");
 
@@ -72,26 +110,45 @@ pub(crate) const NO_OID: gss_OID = ptr::null_mut();
 pub(crate) const NO_OID_SET: gss_OID_set = ptr::null_mut();
 
 lazy_static! {
-    static ref OIDS: HashMap<Oid, &'static str> = HashMap::from_iter(
+    static ref OIDS: HashMap<&'static Oid, &'static str> = HashMap::from_iter(
         [
-            (GSS_NT_USER_NAME, "GSS_NT_USER_NAME"),
-            (GSS_NT_MACHINE_UID_NAME, "GSS_NT_MACHINE_UID_NAME"),
-            (GSS_NT_STRING_UID_NAME, "GSS_NT_STRING_UID_NAME"),
-            (GSS_NT_HOSTBASED_SERVICE, "GSS_NT_HOSTBASED_SERVICE"),
-            (GSS_NT_ANONYMOUS, "GSS_NT_ANONYMOUS"),
-            (GSS_NT_EXPORT_NAME, "GSS_NT_EXPORT_NAME"),
-            (GSS_NT_COMPOSITE_EXPORT, "GSS_NT_COMPOSITE_EXPORT"),
-            (GSS_INQ_SSPI_SESSION_KEY, "GSS_INQ_SSPI_SESSION_KEY"),
-            (GSS_INQ_NEGOEX_KEY, "GSS_INQ_NEGOEX_KEY"),
-            (GSS_INQ_NEGOEX_VERIFY_KEY, "GSS_INQ_NEGOEX_VERIFY_KEY"),
-            (GSS_MA_NEGOEX_AND_SPNEGO, "GSS_MA_NEGOEX_AND_SPNEGO"),
-            (GSS_SEC_CONTEXT_SASL_SSF, "GSS_SEC_CONTEXT_SASL_SSF"),
-            (GSS_MECH_KRB5, "GSS_MECH_KRB5"),
-            (GSS_MECH_IAKERB, "GSS_MECH_IAKERB"),
-            (GSS_NT_KRB5_PRINCIPAL, "GSS_KRB5_NT_PRINCIPAL"),
-            (GSS_KRB5_CRED_NO_CI_FLAGS_X, "GSS_KRB5_CRED_NO_CI_FLAGS_X"),
+            (&GSS_NT_USER_NAME, "GSS_NT_USER_NAME"),
+            (&GSS_NT_MACHINE_UID_NAME, "GSS_NT_MACHINE_UID_NAME"),
+            (&GSS_NT_STRING_UID_NAME, "GSS_NT_STRING_UID_NAME"),
+            (&GSS_NT_HOSTBASED_SERVICE, "GSS_NT_HOSTBASED_SERVICE"),
+            (&GSS_NT_ANONYMOUS, "GSS_NT_ANONYMOUS"),
+            (&GSS_NT_EXPORT_NAME, "GSS_NT_EXPORT_NAME"),
+            (&GSS_NT_COMPOSITE_EXPORT, "GSS_NT_COMPOSITE_EXPORT"),
+            (&GSS_KRB5_GET_TKT_FLAGS_X, "GSS_KRB5_GET_TKT_FLAGS_X"),
+            (
+                &GSS_KRB5_EXTRACT_AUTHZ_DATA_FROM_SEC_CONTEXT_X,
+                "GSS_KRB5_EXTRACT_AUTHZ_DATA_FROM_SEC_CONTEXT_X"
+            ),
+            (&GSS_INQ_SSPI_SESSION_KEY, "GSS_INQ_SSPI_SESSION_KEY"),
+            (&GSS_INQ_NEGOEX_KEY, "GSS_INQ_NEGOEX_KEY"),
+            (&GSS_INQ_NEGOEX_VERIFY_KEY, "GSS_INQ_NEGOEX_VERIFY_KEY"),
+            (&GSS_MA_NEGOEX_AND_SPNEGO, "GSS_MA_NEGOEX_AND_SPNEGO"),
+            (&GSS_MA_MECH_CONCRETE, "GSS_MA_MECH_CONCRETE"),
+            (&GSS_MA_MECH_NEGO, "GSS_MA_MECH_NEGO"),
+            (&GSS_MA_AUTH_INIT_ANON, "GSS_MA_AUTH_INIT_ANON"),
+            (&GSS_MA_AUTH_TARG_ANON, "GSS_MA_AUTH_TARG_ANON"),
+            (&GSS_MA_DELEG_CRED, "GSS_MA_DELEG_CRED"),
+            (&GSS_MA_INTEG_PROT, "GSS_MA_INTEG_PROT"),
+            (&GSS_MA_CONF_PROT, "GSS_MA_CONF_PROT"),
+            (&GSS_MA_PROT_READY, "GSS_MA_PROT_READY"),
+            (&GSS_MA_REPLAY_DET, "GSS_MA_REPLAY_DET"),
+            (&GSS_MA_OOS_DET, "GSS_MA_OOS_DET"),
+            (&GSS_MA_CBINDINGS, "GSS_MA_CBINDINGS"),
+            (&GSS_SEC_CONTEXT_SASL_SSF, "GSS_SEC_CONTEXT_SASL_SSF"),
+            (&GSS_MECH_KRB5, "GSS_MECH_KRB5"),
+            (&GSS_MECH_IAKERB, "GSS_MECH_IAKERB"),
+            (&GSS_MECH_SPNEGO, "GSS_MECH_SPNEGO"),
+            (&GSS_MECH_NTLM, "GSS_MECH_NTLM"),
+            (&GSS_NT_KRB5_PRINCIPAL, "GSS_KRB5_NT_PRINCIPAL"),
+            (&GSS_NT_KRB5_ENTERPRISE_NAME, "GSS_KRB5_NT_ENTERPRISE_NAME"),
+            (&GSS_KRB5_CRED_NO_CI_FLAGS_X, "GSS_KRB5_CRED_NO_CI_FLAGS_X"),
             (
-                GSS_KRB5_GET_CRED_IMPERSONATOR,
+                &GSS_KRB5_GET_CRED_IMPERSONATOR,
                 "GSS_KRB5_GET_CRED_IMPERSONATOR"
             )
         ]
@@ -109,8 +166,11 @@ lazy_static! {
  * some other implementation might be. So if that happens I guess file
  * a bug. */
 /// An Oid. Did I mention I hate OIDs.
+///
+/// An `Oid` may borrow memory it doesn't own (the BER encoding of an
+/// `OwnedOid`, or an element of an `OidSet`), so it isn't `Copy` or
+/// `Clone`, use `to_owned` to keep a copy.
 #[repr(transparent)]
-#[derive(Clone, Copy)]
 pub struct Oid(gss_OID_desc);
 
 /* OIDs are defined in the standard as const pointers into static
@@ -199,6 +259,361 @@ impl Oid {
         let elements = ber.as_ptr() as *mut std::ffi::c_void;
         Oid(gss_OID_desc { length, elements })
     }
+
+    /// Parse an oid in dotted decimal form,
+    /// e.g. "1.2.840.113554.1.2.2". The result owns its BER encoding,
+    /// which is freed when it is dropped, and derefs to `Oid`.
+    ///
+    /// ```
+    /// # use libgssapi::oid::{Oid, OidSet, GSS_MECH_KRB5};
+    /// let krb5 = Oid::from_dotted("1.2.840.113554.1.2.2").unwrap();
+    /// assert_eq!(*krb5, GSS_MECH_KRB5);
+    /// let mut set = OidSet::new().unwrap();
+    /// set.add(&krb5).unwrap();
+    /// assert!(set.contains(&GSS_MECH_KRB5).unwrap());
+    /// assert!(Oid::from_dotted("1.2.").is_err());
+    /// ```
+    pub fn from_dotted(s: &str) -> Result<OwnedOid, OidParseError> {
+        match ber_from_dotted(s) {
+            Some(ber) => Ok(OwnedOid::from_ber(ber)),
+            None => Err(OidParseError(())),
+        }
+    }
+
+    /// Format the oid in dotted decimal form, e.g.
+    /// "1.2.840.113554.1.2.2", the inverse of `from_dotted`. Returns
+    /// `None` if the BER encoding is malformed.
+    pub fn to_dotted(&self) -> Option<String> {
+        dotted_from_ber(self)
+    }
+
+    /// Return a pointer to the underlying `gss_OID_desc`, for passing
+    /// to gssapi functions this library doesn't wrap.
+    pub fn as_ptr(&self) -> gss_OID {
+        self as *const Oid as gss_OID
+    }
+
+    /// Format the oid in the gssapi `{ 1 2 840 113554 1 2 2 }`
+    /// notation, the way gssapi aware tools print them. MIT includes
+    /// the terminating NUL in the buffer. This is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn to_gss_str(&self) -> Result<Buf, Error> {
+        let mut out = Buf::empty();
+        let mut minor = GSS_S_COMPLETE;
+        let major = unsafe {
+            gss_oid_to_str(&mut minor as *mut OM_uint32, self.to_c(), out.to_c())
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(out)
+        } else {
+            Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            })
+        }
+    }
+
+    /// Parse an oid in the notation produced by `to_gss_str` (MIT
+    /// also accepts dotted decimal). The result is allocated by the
+    /// library and released when it is dropped, see `OwnedOid`. This
+    /// is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn from_gss_str(s: &[u8]) -> Result<OwnedOid, Error> {
+        OwnedOid::from_gss_str(s)
+    }
+}
+
+impl AsRef<Oid> for Oid {
+    fn as_ref(&self) -> &Oid {
+        self
+    }
+}
+
+/// The error returned by `Oid::from_dotted` for a string that isn't
+/// an oid in dotted decimal form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OidParseError(());
+
+impl fmt::Display for OidParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "invalid dotted decimal oid")
+    }
+}
+
+impl std::error::Error for OidParseError {}
+
+enum OwnedOidInner {
+    // oid points into ber, which doesn't move when the oid does
+    Ber { oid: Oid, _ber: Box<[u8]> },
+    #[cfg(feature = "mit_kerberos")]
+    Gss(gss_OID),
+}
+
+/// An oid that owns its BER encoding, either parsed by
+/// `Oid::from_dotted`, or allocated by the gssapi library and
+/// released with `gss_release_oid` when it is dropped. It derefs to
+/// `Oid`, so it can be passed anywhere an `&Oid` is expected, and as
+/// `Oid` can't be copied out of the reference nothing can use the
+/// encoding after the `OwnedOid` is dropped. Oids
+/// gssapi returns from e.g. `gss_display_name` or
+/// `gss_inquire_context` belong to the library and are static, and
+/// are returned as `&'static Oid`.
+///
+/// ```compile_fail
+/// # use libgssapi::oid::Oid;
+/// let owned = Oid::from_dotted("1.2.840.113554.1.2.2").unwrap();
+/// let oid: Oid = *owned;
+/// drop(owned);
+/// ```
+pub struct OwnedOid(OwnedOidInner);
+
+unsafe impl Send for OwnedOid {}
+unsafe impl Sync for OwnedOid {}
+
+#[cfg(feature = "mit_kerberos")]
+impl Drop for OwnedOid {
+    fn drop(&mut self) {
+        if let OwnedOidInner::Gss(oid) = &mut self.0 {
+            let mut minor = GSS_S_COMPLETE;
+            let _major = unsafe {
+                gss_release_oid(&mut minor as *mut OM_uint32, oid as *mut gss_OID)
+            };
+        }
+    }
+}
+
+impl Deref for OwnedOid {
+    type Target = Oid;
+
+    fn deref(&self) -> &Oid {
+        match &self.0 {
+            OwnedOidInner::Ber { oid, .. } => oid,
+            #[cfg(feature = "mit_kerberos")]
+            OwnedOidInner::Gss(oid) => unsafe { Oid::from_c(*oid) },
+        }
+    }
+}
+
+impl Borrow<Oid> for OwnedOid {
+    fn borrow(&self) -> &Oid {
+        self
+    }
+}
+
+/// Copies the BER encoding
+impl ToOwned for Oid {
+    type Owned = OwnedOid;
+
+    fn to_owned(&self) -> OwnedOid {
+        OwnedOid::from(self)
+    }
+}
+
+impl AsRef<Oid> for OwnedOid {
+    fn as_ref(&self) -> &Oid {
+        self
+    }
+}
+
+impl fmt::Debug for OwnedOid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// Copies the BER encoding, so the clone of an oid allocated by
+/// gssapi is not itself allocated by gssapi, and is freed by Rust
+/// rather than `gss_release_oid`
+impl Clone for OwnedOid {
+    fn clone(&self) -> Self {
+        OwnedOid::from(&**self)
+    }
+}
+
+impl PartialEq for OwnedOid {
+    fn eq(&self, other: &OwnedOid) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for OwnedOid {}
+
+impl Hash for OwnedOid {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+/// Copies the BER encoding
+impl From<&Oid> for OwnedOid {
+    fn from(oid: &Oid) -> Self {
+        OwnedOid::from_ber(Vec::from(&**oid))
+    }
+}
+
+/// The same as `Oid::from_dotted`
+impl FromStr for OwnedOid {
+    type Err = OidParseError;
+
+    fn from_str(s: &str) -> Result<Self, OidParseError> {
+        Oid::from_dotted(s)
+    }
+}
+
+impl OwnedOid {
+    fn from_ber(ber: Vec<u8>) -> OwnedOid {
+        let ber = ber.into_boxed_slice();
+        let oid = Oid(gss_OID_desc {
+            length: ber.len() as OM_uint32,
+            elements: ber.as_ptr() as *mut std::ffi::c_void,
+        });
+        OwnedOid(OwnedOidInner::Ber { oid, _ber: ber })
+    }
+
+    /// Take ownership of `oid`, which will be released with
+    /// `gss_release_oid`.
+    ///
+    /// # Safety
+    ///
+    /// `oid` must not be null, must have been allocated by the gssapi
+    /// library, and must not be released by anyone else.
+    #[cfg(feature = "mit_kerberos")]
+    pub unsafe fn from_c(oid: gss_OID) -> OwnedOid {
+        OwnedOid(OwnedOidInner::Gss(oid))
+    }
+
+    /// Parse an oid in the notation produced by `Oid::to_gss_str`
+    /// (MIT also accepts dotted decimal). This is an MIT extension.
+    #[cfg(feature = "mit_kerberos")]
+    pub fn from_gss_str(s: &[u8]) -> Result<OwnedOid, Error> {
+        let mut buf = BufRef::from(s);
+        let mut oid = ptr::null_mut::<gss_OID_desc>();
+        let mut minor = GSS_S_COMPLETE;
+        let major = unsafe {
+            gss_str_to_oid(
+                &mut minor as *mut OM_uint32,
+                buf.to_c(),
+                &mut oid as *mut gss_OID,
+            )
+        };
+        if major != GSS_S_COMPLETE || oid.is_null() {
+            return Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            });
+        }
+        Ok(OwnedOid(OwnedOidInner::Gss(oid)))
+    }
+}
+
+fn push_base128(ber: &mut Vec<u8>, mut v: u64) {
+    let mut tmp = [0u8; 10];
+    let mut i = tmp.len();
+    let mut more = 0;
+    loop {
+        i -= 1;
+        tmp[i] = (v & 0x7f) as u8 | more;
+        more = 0x80;
+        v >>= 7;
+        if v == 0 {
+            break;
+        }
+    }
+    ber.extend_from_slice(&tmp[i..]);
+}
+
+fn ber_from_dotted(s: &str) -> Option<Vec<u8>> {
+    let mut arcs = s.split('.').map(|arc| {
+        if arc.is_empty() || !arc.bytes().all(|c| c.is_ascii_digit()) {
+            None
+        } else {
+            arc.parse::<u64>().ok()
+        }
+    });
+    let first = arcs.next()??;
+    let second = arcs.next()??;
+    if first > 2 || (first < 2 && second >= 40) {
+        return None;
+    }
+    let mut ber = Vec::new();
+    push_base128(&mut ber, second.checked_add(first * 40)?);
+    for arc in arcs {
+        push_base128(&mut ber, arc?);
+    }
+    Some(ber)
+}
+
+fn dotted_from_ber(ber: &[u8]) -> Option<String> {
+    let mut arcs = Vec::new();
+    let mut v: u64 = 0;
+    let mut pending = false;
+    for c in ber {
+        // leading zero septets and arcs wider than 64 bits are invalid
+        if (!pending && *c == 0x80) || v > u64::MAX >> 7 {
+            return None;
+        }
+        v = v << 7 | (c & 0x7f) as u64;
+        pending = c & 0x80 != 0;
+        if !pending {
+            arcs.push(v);
+            v = 0;
+        }
+    }
+    if pending || arcs.is_empty() {
+        return None;
+    }
+    let (first, second) = match arcs[0] {
+        v if v < 80 => (v / 40, v % 40),
+        v => (2, v - 80),
+    };
+    let mut s = format!("{}.{}", first, second);
+    for arc in &arcs[1..] {
+        s.push_str(&format!(".{}", arc));
+    }
+    Some(s)
+}
+
+/// A gssapi mechanism. The well known mechanisms resolve to the oids
+/// defined in this module rather than to symbols exported by the
+/// gssapi implementation, so they are available on every platform.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Mechanism {
+    Krb5,
+    Spnego,
+    Ntlm,
+    Other(&'static Oid),
+}
+
+impl Mechanism {
+    /// The oid of this mechanism
+    pub fn oid(&self) -> &Oid {
+        match self {
+            Mechanism::Krb5 => &GSS_MECH_KRB5,
+            Mechanism::Spnego => &GSS_MECH_SPNEGO,
+            Mechanism::Ntlm => &GSS_MECH_NTLM,
+            Mechanism::Other(oid) => oid,
+        }
+    }
+}
+
+impl From<&'static Oid> for Mechanism {
+    fn from(oid: &'static Oid) -> Self {
+        if *oid == GSS_MECH_KRB5 {
+            Mechanism::Krb5
+        } else if *oid == GSS_MECH_SPNEGO {
+            Mechanism::Spnego
+        } else if *oid == GSS_MECH_NTLM {
+            Mechanism::Ntlm
+        } else {
+            Mechanism::Other(oid)
+        }
+    }
+}
+
+impl<'a> From<&'a Mechanism> for gss_OID {
+    fn from(mech: &'a Mechanism) -> Self {
+        unsafe { mech.oid().to_c() }
+    }
 }
 
 pub struct OidSetIter<'a> {
@@ -210,7 +625,7 @@ impl<'a> Iterator for OidSetIter<'a> {
     type Item = &'a Oid;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.current < self.len() {
+        if self.current < self.set.len() {
             let res = Some(&self.set[self.current]);
             self.current += 1;
             res
@@ -318,13 +733,14 @@ impl OidSet {
         unsafe { (*self.0).count as usize }
     }
 
-    /// Add an OID to the set.
-    pub fn add(&mut self, id: &Oid) -> Result<(), Error> {
+    /// Add an OID to the set, either an `&Oid` or an `OwnedOid`. The
+    /// set keeps its own copy.
+    pub fn add(&mut self, id: impl AsRef<Oid>) -> Result<(), Error> {
         let mut minor = GSS_S_COMPLETE;
         let major = unsafe {
             gss_add_oid_set_member(
                 &mut minor as *mut OM_uint32,
-                id.to_c(),
+                id.as_ref().to_c(),
                 &mut self.0 as *mut gss_OID_set,
             )
         };
@@ -338,6 +754,18 @@ impl OidSet {
         }
     }
 
+    /// Return a new set containing only the oids that are present in
+    /// both this set and `other`.
+    pub fn intersection(&self, other: &OidSet) -> Result<OidSet, Error> {
+        let mut res = OidSet::new()?;
+        for id in self {
+            if other.contains(id)? {
+                res.add(id)?;
+            }
+        }
+        Ok(res)
+    }
+
     /// Ask gssapi whether it thinks the specified oid is in the
     /// specified set.
     pub fn contains(&self, id: &Oid) -> Result<bool, Error> {
@@ -361,3 +789,316 @@ impl OidSet {
         }
     }
 }
+
+/// Return the name and short description of the mechanism attribute
+/// `attr` (rfc5587), e.g. "GSS_C_MA_MECH_CONCRETE" and
+/// "concrete-mech".
+pub fn display_mech_attr(attr: &Oid) -> Result<(Buf, Buf), Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut name = Buf::empty();
+    let mut short_desc = Buf::empty();
+    let mut long_desc = Buf::empty();
+    let major = unsafe {
+        gss_display_mech_attr(
+            &mut minor as *mut OM_uint32,
+            attr.to_c(),
+            name.to_c(),
+            short_desc.to_c(),
+            long_desc.to_c(),
+        )
+    };
+    if major == GSS_S_COMPLETE {
+        Ok((name, short_desc))
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
+/// Return the attributes of the mechanism `mech` (rfc5587), and all
+/// the attributes known to the library, as `(mech_attrs,
+/// known_mech_attrs)`.
+pub fn inquire_attrs_for_mech(mech: &Oid) -> Result<(OidSet, OidSet), Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut mech_attrs = ptr::null_mut::<gss_OID_set_desc>();
+    let mut known_mech_attrs = ptr::null_mut::<gss_OID_set_desc>();
+    let major = unsafe {
+        gss_inquire_attrs_for_mech(
+            &mut minor as *mut OM_uint32,
+            mech.to_c(),
+            &mut mech_attrs as *mut gss_OID_set,
+            &mut known_mech_attrs as *mut gss_OID_set,
+        )
+    };
+    let (mech_attrs, known_mech_attrs) =
+        unsafe { (OidSet::from_c(mech_attrs), OidSet::from_c(known_mech_attrs)) };
+    if major == GSS_S_COMPLETE {
+        Ok((mech_attrs, known_mech_attrs))
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
+/// Return the mechanisms that have all the attributes in `desired`
+/// (rfc5587), none of the ones in `except`, and know about all the
+/// ones in `critical`, e.g. every concrete mechanism that supports
+/// anonymous acceptors with `GSS_MA_MECH_CONCRETE` and
+/// `GSS_MA_AUTH_TARG_ANON` in `desired`.
+pub fn indicate_mechs_by_attrs(
+    desired: &OidSet,
+    except: &OidSet,
+    critical: &OidSet,
+) -> Result<OidSet, Error> {
+    let mut minor = GSS_S_COMPLETE;
+    let mut mechs = ptr::null_mut::<gss_OID_set_desc>();
+    let major = unsafe {
+        gss_indicate_mechs_by_attrs(
+            &mut minor as *mut OM_uint32,
+            desired.to_c(),
+            except.to_c(),
+            critical.to_c(),
+            &mut mechs as *mut gss_OID_set,
+        )
+    };
+    let mechs = unsafe { OidSet::from_c(mechs) };
+    if major == GSS_S_COMPLETE {
+        Ok(mechs)
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
+/// Parse a DER length, returning it and the rest of `b`
+pub(crate) fn der_len(b: &[u8]) -> Option<(usize, &[u8])> {
+    let first = *b.first()?;
+    if first < 0x80 {
+        Some((first as usize, &b[1..]))
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let len = b
+            .get(1..1 + n)?
+            .iter()
+            .fold(0, |len, c| len << 8 | *c as usize);
+        Some((len, &b[1 + n..]))
+    }
+}
+
+/// Split an initial context token with the rfc2743 3.1 framing (the
+/// application tag, length, and mechanism oid) into the BER of the
+/// mechanism oid and the mechanism specific token
+pub(crate) fn split_initial_token(tok: &[u8]) -> Option<(&[u8], &[u8])> {
+    if tok.first() != Some(&0x60) {
+        return None;
+    }
+    let (len, body) = der_len(&tok[1..])?;
+    if body.len() != len || body.first() != Some(&0x06) {
+        return None;
+    }
+    let (oid_len, rest) = der_len(&body[1..])?;
+    if oid_len == 0 || oid_len > rest.len() {
+        return None;
+    }
+    Some(rest.split_at(oid_len))
+}
+
+/// NTLMSSP tokens aren't framed, they start with this signature
+const NTLMSSP_SIGNATURE: &[u8] = b"NTLMSSP\0";
+
+/// Work out the mechanism of an initial context token from the
+/// client, without calling gssapi, e.g. to pick the acceptor
+/// credential before calling `ServerCtx::step`. krb5, IAKERB, SPNEGO,
+/// and raw NTLMSSP tokens are recognized. Malformed tokens fail with
+/// `GSS_S_DEFECTIVE_TOKEN`, and tokens for any other mechanism with
+/// `GSS_S_BAD_MECH`.
+///
+/// ```
+/// # use libgssapi::oid::{mech_for_token, GSS_MECH_KRB5, GSS_MECH_NTLM};
+/// # use libgssapi::oid::GSS_MECH_SPNEGO;
+/// // the start of a SPNEGO NegTokenInit
+/// let tok = b"\x60\x0a\x06\x06\x2b\x06\x01\x05\x05\x02\xa0\x00";
+/// assert_eq!(mech_for_token(tok).unwrap(), &GSS_MECH_SPNEGO);
+/// // the start of a krb5 AP-REQ
+/// let tok = b"\x60\x0d\x06\x09\x2a\x86\x48\x86\xf7\x12\x01\x02\x02\x01\x00";
+/// assert_eq!(mech_for_token(tok).unwrap(), &GSS_MECH_KRB5);
+/// // an NTLM NEGOTIATE_MESSAGE
+/// let tok = b"NTLMSSP\0\x01\x00\x00\x00";
+/// assert_eq!(mech_for_token(tok).unwrap(), &GSS_MECH_NTLM);
+/// // truncated
+/// assert!(mech_for_token(b"\x60\x82\x01").is_err());
+/// ```
+pub fn mech_for_token(tok: &[u8]) -> Result<&'static Oid, Error> {
+    if tok.starts_with(NTLMSSP_SIGNATURE) {
+        return Ok(&GSS_MECH_NTLM);
+    }
+    let (oid, _) = split_initial_token(tok).ok_or(Error {
+        major: MajorFlags::GSS_S_DEFECTIVE_TOKEN,
+        minor: 0,
+    })?;
+    [&GSS_MECH_KRB5, &GSS_MECH_IAKERB, &GSS_MECH_SPNEGO, &GSS_MECH_NTLM]
+        .iter()
+        .copied()
+        .find(|mech| ***mech == *oid)
+        .ok_or(Error {
+            major: MajorFlags::GSS_S_BAD_MECH,
+            minor: 0,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::WellKnownError::{self, BadMech, DefectiveToken};
+    use std::{collections::HashSet, iter};
+
+    #[test]
+    fn dotted_round_trip() {
+        let oid = Oid::from_dotted("1.2.840.113554.1.2.2").unwrap();
+        assert_eq!(*oid, GSS_MECH_KRB5);
+        assert_eq!(oid.to_dotted().as_deref(), Some("1.2.840.113554.1.2.2"));
+        let big = "2.999.18446744073709551615";
+        assert_eq!(Oid::from_dotted(big).unwrap().to_dotted().as_deref(), Some(big));
+        assert_eq!("1.2.840.113554.1.2.2".parse::<OwnedOid>().unwrap(), oid);
+        assert_eq!(oid.clone(), oid);
+    }
+
+    #[test]
+    fn dotted_errors() {
+        let bad = ["", "1", "1.", "1..2", "3.1", "1.40", "1.2.x", "1.2.-3"];
+        for s in bad.iter().chain(&["1.2.99999999999999999999"]) {
+            assert_eq!(Oid::from_dotted(s), Err(OidParseError(())), "{}", s);
+        }
+    }
+
+    #[test]
+    fn add_owned() {
+        let mut set = OidSet::new().unwrap();
+        set.add(Oid::from_dotted("1.2.840.113554.1.2.2").unwrap()).unwrap();
+        let spnego = Oid::from_dotted("1.3.6.1.5.5.2").unwrap();
+        set.add(&spnego).unwrap();
+        set.add(&GSS_MECH_NTLM).unwrap();
+        drop(spnego);
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&GSS_MECH_KRB5).unwrap());
+        assert!(set.contains(&GSS_MECH_SPNEGO).unwrap());
+        assert!(set.contains(&GSS_MECH_NTLM).unwrap());
+    }
+
+    #[test]
+    fn to_owned_outlives_the_original() {
+        let owned = {
+            let parsed = Oid::from_dotted("1.2.840.113554.1.2.2").unwrap();
+            (*parsed).to_owned()
+        };
+        assert_eq!(*owned, GSS_MECH_KRB5);
+        let set: HashSet<OwnedOid> = iter::once(GSS_MECH_KRB5.to_owned()).collect();
+        assert!(set.contains(&GSS_MECH_KRB5));
+        assert!(!set.contains(&GSS_MECH_SPNEGO));
+    }
+
+    // the first bytes of initial tokens captured from MIT's
+    // gss_init_sec_context and a Windows client
+    const KRB5_TOKEN: &[u8] = &[
+        0x60, 0x82, 0x02, 0x13, 0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x12,
+        0x01, 0x02, 0x02, 0x01, 0x00, 0x6e, 0x82, 0x02, 0x02, 0x30, 0x82, 0x01,
+        0xfe, 0xa0, 0x03, 0x02,
+    ];
+    const SPNEGO_TOKEN: &[u8] = &[
+        0x60, 0x82, 0x02, 0x3e, 0x06, 0x06, 0x2b, 0x06, 0x01, 0x05, 0x05, 0x02,
+        0xa0, 0x82, 0x02, 0x32, 0x30, 0x82, 0x02, 0x2e, 0xa0, 0x0d, 0x30, 0x0b,
+        0x06, 0x09, 0x2a, 0x86,
+    ];
+    const NTLMSSP_TOKEN: &[u8] = &[
+        0x4e, 0x54, 0x4c, 0x4d, 0x53, 0x53, 0x50, 0x00, 0x01, 0x00, 0x00, 0x00,
+        0x97, 0x82, 0x08, 0xe2, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0a, 0x00, 0x61, 0x4a,
+        0x00, 0x00, 0x00, 0x0f,
+    ];
+
+    // pad a captured prefix out to the length in its framing
+    fn captured(prefix: &[u8]) -> Vec<u8> {
+        let (len, body) = der_len(&prefix[1..]).unwrap();
+        let mut tok = prefix.to_vec();
+        tok.resize(prefix.len() - body.len() + len, 0);
+        tok
+    }
+
+    fn kind(tok: &[u8]) -> Option<WellKnownError> {
+        mech_for_token(tok).unwrap_err().kind()
+    }
+
+    #[test]
+    fn captured_tokens() {
+        assert_eq!(mech_for_token(&captured(KRB5_TOKEN)).unwrap(), &GSS_MECH_KRB5);
+        assert_eq!(mech_for_token(&captured(SPNEGO_TOKEN)).unwrap(), &GSS_MECH_SPNEGO);
+        assert_eq!(mech_for_token(NTLMSSP_TOKEN).unwrap(), &GSS_MECH_NTLM);
+    }
+
+    #[test]
+    fn truncated_tokens() {
+        for tok in &[captured(KRB5_TOKEN), captured(SPNEGO_TOKEN)] {
+            for len in 0..tok.len() {
+                assert_eq!(kind(&tok[..len]), Some(DefectiveToken), "{}", len);
+            }
+        }
+        for len in 0..NTLMSSP_SIGNATURE.len() {
+            assert_eq!(kind(&NTLMSSP_TOKEN[..len]), Some(DefectiveToken));
+        }
+    }
+
+    #[test]
+    fn malformed_tokens() {
+        let defective: &[&[u8]] = &[
+            // indefinite, missing and 5 byte lengths
+            b"\x60\x80\x06\x01\x2a\x00\x00",
+            b"\x60\x81",
+            b"\x60\x85\xff\xff\xff\xff\xff\x06",
+            // the wrong tag, and a length past the end
+            b"\x30\x03\x06\x01\x2a",
+            b"\x60\x84\xff\xff\xff\xff\x06\x01\x2a",
+            // not an oid, an empty oid, and an oid longer than the token
+            b"\x60\x03\x04\x01\x2a",
+            b"\x60\x02\x06\x00",
+            b"\x60\x03\x06\x05\x2a",
+        ];
+        for tok in defective {
+            assert_eq!(kind(tok), Some(DefectiveToken), "{:x?}", tok);
+        }
+        let mut tok = captured(KRB5_TOKEN);
+        tok.push(0);
+        assert_eq!(kind(&tok), Some(DefectiveToken));
+        // well formed, but not a mechanism we know
+        assert_eq!(kind(b"\x60\x04\x06\x02\x2a\x03"), Some(BadMech));
+        // any corruption of the framing must be an error, not a panic
+        for tok in &[captured(KRB5_TOKEN), captured(SPNEGO_TOKEN)] {
+            for i in 0..16 {
+                for b in 0..=255 {
+                    let mut tok = tok.clone();
+                    tok[i] = b;
+                    let _ = mech_for_token(&tok);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "mit_kerberos")]
+    #[test]
+    fn gss_str_round_trip() {
+        let s = GSS_MECH_KRB5.to_gss_str().unwrap();
+        assert_eq!(&*s, b"{ 1 2 840 113554 1 2 2 }\0");
+        let oid = Oid::from_gss_str(&s).unwrap();
+        assert_eq!(*oid, GSS_MECH_KRB5);
+        assert_eq!(oid.clone(), oid);
+        assert!(Oid::from_gss_str(b"{ not an oid }").is_err());
+    }
+}
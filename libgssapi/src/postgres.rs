@@ -0,0 +1,257 @@
+//! Support for PostgreSQL's GSSAPI transport encryption
+//! (`gssencmode`), as implemented by libpq.
+//!
+//! The client sends a `GSSENCRequest` instead of (well, before) the
+//! startup packet. If the server replies `G` the client performs the
+//! GSSAPI handshake, sending each token as a 4 byte big endian length
+//! followed by the token, and the server replies the same way until
+//! the context is established. From then on everything, starting
+//! with the startup packet, is sent in wrap tokens framed the same
+//! way, and no token may be bigger than 16kB. That's exactly the
+//! framing `GssStream` uses, so the encrypted connection is just a
+//! `GssStream` with `max_token_size(MAX_TOKEN_SIZE)`.
+//!
+//! If the server rejects the client during the handshake (e.g. no
+//! `pg_hba.conf` entry matches) it sends an `ErrorResponse` instead
+//! of a token, which is reported as a `ConnectionRefused` error
+//! carrying the server's message.
+use crate::{
+    context::{ClientCtx, CtxFlags, SecurityContext},
+    name::Name,
+    stream::GssStream,
+};
+use std::io::{self, Read, Write};
+
+/// The request code of a `GSSENCRequest` packet
+pub const GSSENC_REQUEST_CODE: u32 = 80877104;
+
+/// The service name libpq uses unless `krbsrvname` is set
+pub const DEFAULT_SERVICE: &str = "postgres";
+
+/// The largest packet either side will send, including the 4 byte
+/// length
+pub const MAX_PACKET_SIZE: usize = 16384;
+
+/// The largest wrap token either side will send
+pub const MAX_TOKEN_SIZE: usize = MAX_PACKET_SIZE - 4;
+
+/// The largest handshake token we'll accept from the server
+const MAX_AUTH_TOKEN_SIZE: usize = 65536;
+
+/// The type byte of an `ErrorResponse` message
+const ERROR_RESPONSE: u8 = b'E';
+
+/// The flags libpq requests when it initiates the context
+pub const FLAGS: CtxFlags = CtxFlags::GSS_C_MUTUAL_FLAG
+    .union(CtxFlags::GSS_C_REPLAY_FLAG)
+    .union(CtxFlags::GSS_C_SEQUENCE_FLAG)
+    .union(CtxFlags::GSS_C_CONF_FLAG)
+    .union(CtxFlags::GSS_C_INTEG_FLAG);
+
+/// The `GSSENCRequest` packet, the first thing the client sends
+pub fn gssenc_request() -> [u8; 8] {
+    let mut pkt = [0u8; 8];
+    pkt[..4].copy_from_slice(&8u32.to_be_bytes());
+    pkt[4..].copy_from_slice(&GSSENC_REQUEST_CODE.to_be_bytes());
+    pkt
+}
+
+/// The result of asking the server for GSSAPI encryption
+#[derive(Debug)]
+pub enum Negotiated<S> {
+    /// The server agreed, and the handshake completed. Send the
+    /// startup packet over this stream.
+    Encrypted(GssStream<ClientCtx, S>),
+    /// The server replied `N`. The connection can be used without
+    /// encryption (e.g. to try TLS or plain text).
+    Refused(S),
+}
+
+fn check_response(resp: u8) -> io::Result<bool> {
+    match resp {
+        b'G' => Ok(true),
+        b'N' => Ok(false),
+        b'E' => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            "server sent an error in response to GSSENCRequest",
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unexpected response to GSSENCRequest",
+        )),
+    }
+}
+
+fn auth_token_len(hdr: [u8; 4]) -> io::Result<usize> {
+    let len = u32::from_be_bytes(hdr) as usize;
+    if len > MAX_AUTH_TOKEN_SIZE {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "handshake token too large",
+        ))
+    } else {
+        Ok(len)
+    }
+}
+
+/// The length of the body of an `ErrorResponse` read where a token
+/// was expected. `hdr` is what was read as the token length, the
+/// type byte and the first 3 bytes of the message length, `last` is
+/// the final byte of the message length. No token length starts with
+/// `ERROR_RESPONSE`, they are too big.
+fn error_len(hdr: [u8; 4], last: u8) -> io::Result<usize> {
+    let len = u32::from_be_bytes([hdr[1], hdr[2], hdr[3], last]) as usize;
+    match len.checked_sub(4) {
+        Some(len) if len <= MAX_PACKET_SIZE => Ok(len),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "bad ErrorResponse length",
+        )),
+    }
+}
+
+/// Turn the fields of an `ErrorResponse` into an error, using the
+/// severity (`S`) and message (`M`) fields
+fn server_error(body: &[u8]) -> io::Error {
+    let mut severity = None;
+    let mut msg = None;
+    for field in body.split(|b| *b == 0) {
+        match field.split_first() {
+            Some((b'S', v)) => severity = Some(String::from_utf8_lossy(v)),
+            Some((b'M', v)) => msg = Some(String::from_utf8_lossy(v)),
+            _ => (),
+        }
+    }
+    io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        format!(
+            "{}: {}",
+            severity.as_deref().unwrap_or("ERROR"),
+            msg.as_deref().unwrap_or("the server sent no message")
+        ),
+    )
+}
+
+fn frame(tok: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(4 + tok.len());
+    record.extend_from_slice(&(tok.len() as u32).to_be_bytes());
+    record.extend_from_slice(tok);
+    record
+}
+
+fn client_ctx(host: &str, service: Option<&str>) -> io::Result<ClientCtx> {
+    let service = service.unwrap_or(DEFAULT_SERVICE);
+    let target = Name::service(service, host).map_err(io::Error::other)?;
+    Ok(ClientCtx::new(None, target, FLAGS, None))
+}
+
+fn check_ctx(ctx: &mut ClientCtx) -> io::Result<()> {
+    let flags = ctx.flags().map_err(io::Error::other)?;
+    if flags.contains(CtxFlags::GSS_C_CONF_FLAG) {
+        Ok(())
+    } else {
+        Err(io::Error::other("the context doesn't support encryption"))
+    }
+}
+
+/// Ask the server at the other end of `stream` for GSSAPI encryption
+/// and, if it agrees, perform the handshake using the default
+/// credentials and the `<service>@<host>` service (`service` defaults
+/// to `DEFAULT_SERVICE`).
+pub fn connect<S: Read + Write>(
+    mut stream: S,
+    host: &str,
+    service: Option<&str>,
+) -> io::Result<Negotiated<S>> {
+    stream.write_all(&gssenc_request())?;
+    let mut resp = [0u8; 1];
+    stream.read_exact(&mut resp)?;
+    if !check_response(resp[0])? {
+        return Ok(Negotiated::Refused(stream));
+    }
+    let mut ctx = client_ctx(host, service)?;
+    let mut input: Option<Vec<u8>> = None;
+    loop {
+        if let Some(tok) = ctx.step(input.as_deref(), None).map_err(io::Error::other)? {
+            stream.write_all(&frame(&tok))?;
+        }
+        if ctx.is_complete() {
+            break;
+        }
+        let mut hdr = [0u8; 4];
+        stream.read_exact(&mut hdr)?;
+        if hdr[0] == ERROR_RESPONSE {
+            let mut last = [0u8; 1];
+            stream.read_exact(&mut last)?;
+            let mut body = vec![0u8; error_len(hdr, last[0])?];
+            stream.read_exact(&mut body)?;
+            return Err(server_error(&body));
+        }
+        let mut tok = vec![0u8; auth_token_len(hdr)?];
+        stream.read_exact(&mut tok)?;
+        input = Some(tok);
+    }
+    check_ctx(&mut ctx)?;
+    let stream = GssStream::new(ctx, stream, true).max_token_size(MAX_TOKEN_SIZE);
+    Ok(Negotiated::Encrypted(stream))
+}
+
+#[cfg(feature = "tokio")]
+mod async_tokio {
+    use super::*;
+    use crate::stream::AsyncGssStream;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    /// The result of asking the server for GSSAPI encryption
+    #[derive(Debug)]
+    pub enum AsyncNegotiated<S> {
+        /// The server agreed, and the handshake completed. Send the
+        /// startup packet over this stream.
+        Encrypted(Box<AsyncGssStream<ClientCtx, S>>),
+        /// The server replied `N`
+        Refused(S),
+    }
+
+    /// The same as `connect`, but over a tokio transport
+    pub async fn connect_async<S: AsyncRead + AsyncWrite + Unpin>(
+        mut stream: S,
+        host: &str,
+        service: Option<&str>,
+    ) -> io::Result<AsyncNegotiated<S>> {
+        stream.write_all(&gssenc_request()).await?;
+        if !check_response(stream.read_u8().await?)? {
+            return Ok(AsyncNegotiated::Refused(stream));
+        }
+        let mut ctx = client_ctx(host, service)?;
+        let mut input: Option<Vec<u8>> = None;
+        loop {
+            let out = ctx
+                .step_async(input.as_deref(), None)
+                .await
+                .map_err(io::Error::other)?;
+            if let Some(tok) = out {
+                stream.write_all(&frame(&tok)).await?;
+            }
+            if ctx.is_complete() {
+                break;
+            }
+            let mut hdr = [0u8; 4];
+            stream.read_exact(&mut hdr).await?;
+            if hdr[0] == ERROR_RESPONSE {
+                let len = error_len(hdr, stream.read_u8().await?)?;
+                let mut body = vec![0u8; len];
+                stream.read_exact(&mut body).await?;
+                return Err(server_error(&body));
+            }
+            let mut tok = vec![0u8; auth_token_len(hdr)?];
+            stream.read_exact(&mut tok).await?;
+            input = Some(tok);
+        }
+        check_ctx(&mut ctx)?;
+        let stream =
+            AsyncGssStream::new(ctx, stream, true).max_token_size(MAX_TOKEN_SIZE);
+        Ok(AsyncNegotiated::Encrypted(Box::new(stream)))
+    }
+}
+#[cfg(feature = "tokio")]
+pub use async_tokio::*;
@@ -0,0 +1,227 @@
+//! A client context that is transparently re-established when it
+//! expires, for long lived connections whose kerberos tickets expire
+//! underneath them.
+//!
+//! Messages are never retried under the new context. Anything the
+//! peer hadn't processed when the old context expired is lost, so
+//! each time a context is replaced the caller is told, and must
+//! resynchronize at the application layer (e.g. resend unacknowledged
+//! requests, wrapped again).
+use crate::{
+    context::{ClientCtx, CtxFlags, SecurityContext},
+    credential::Cred,
+    error::{Error, MajorFlags},
+    name::Name,
+    oid::Oid,
+    util::Buf,
+};
+use std::{fmt, time::Duration};
+
+/// The result of wrapping a message with `ReestablishingClient::wrap`
+#[derive(Debug)]
+pub enum Wrapped {
+    /// The message was wrapped under the same context as the
+    /// previous one
+    Current(Buf),
+    /// The old context expired (or was about to), and a new one was
+    /// established before wrapping the message. This is the resync
+    /// point, the peer can't unwrap anything still in flight under
+    /// the old context, and tokens it sent under the old context
+    /// can't be unwrapped anymore.
+    Reestablished(Buf),
+}
+
+impl Wrapped {
+    /// Return the wrapped token
+    pub fn into_token(self) -> Buf {
+        match self {
+            Wrapped::Current(tok) | Wrapped::Reestablished(tok) => tok,
+        }
+    }
+
+    /// Return true if a new context was established
+    pub fn is_reestablished(&self) -> bool {
+        match self {
+            Wrapped::Current(_) => false,
+            Wrapped::Reestablished(_) => true,
+        }
+    }
+}
+
+/// A client context that does the handshake again whenever it has
+/// expired, or is about to. `exchange` is called with each token to
+/// send to the server, and a flag that is true if the handshake is
+/// complete on our side (so the server won't reply). It returns the
+/// server's reply token, if any. Transport errors should be reported
+/// as `GSS_S_FAILURE`.
+pub struct ReestablishingClient<F> {
+    ctx: ClientCtx,
+    exchange: F,
+    channel_bindings: Option<Vec<u8>>,
+    renew_before: Duration,
+    generation: u64,
+    expired: bool,
+}
+
+impl<F> fmt::Debug for ReestablishingClient<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReestablishingClient")
+            .field("ctx", &self.ctx)
+            .field("renew_before", &self.renew_before)
+            .field("generation", &self.generation)
+            .field("expired", &self.expired)
+            .finish()
+    }
+}
+
+impl<F> ReestablishingClient<F>
+where
+    F: FnMut(&[u8], bool) -> Result<Option<Vec<u8>>, Error>,
+{
+    /// Create a client that establishes contexts with `target` using
+    /// `cred` (or the default credentials), `flags`, and `mech`, as
+    /// `ClientCtx::new` does. No handshake is done until the first
+    /// message is wrapped, or `context` is called.
+    ///
+    /// Each new context uses the same credentials, so they must be
+    /// renewed too, e.g. by a process that refreshes the ccache.
+    pub fn new(
+        cred: Option<Cred>,
+        target: Name,
+        flags: CtxFlags,
+        mech: Option<&'static Oid>,
+        exchange: F,
+    ) -> Self {
+        ReestablishingClient {
+            ctx: ClientCtx::new(cred, target, flags, mech),
+            exchange,
+            channel_bindings: None,
+            renew_before: Duration::from_secs(0),
+            generation: 0,
+            expired: false,
+        }
+    }
+
+    /// Pass `channel_bindings` to every handshake
+    #[must_use]
+    pub fn with_channel_bindings(mut self, channel_bindings: &[u8]) -> Self {
+        self.channel_bindings = Some(Vec::from(channel_bindings));
+        self
+    }
+
+    /// Establish a new context when the current one has less than
+    /// `threshold` left, rather than waiting for it to expire. The
+    /// default is to wait.
+    #[must_use]
+    pub fn renew_before(mut self, threshold: Duration) -> Self {
+        self.renew_before = threshold;
+        self
+    }
+
+    /// The number of contexts established so far, which changes at
+    /// each resync point
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn needs_renewal(&mut self) -> bool {
+        if self.expired || !self.ctx.is_complete() {
+            return true;
+        }
+        match self.ctx.lifetime() {
+            Ok(lifetime) => {
+                lifetime == Duration::from_secs(0) || lifetime < self.renew_before
+            }
+            Err(_) => true,
+        }
+    }
+
+    fn establish(&mut self) -> Result<(), Error> {
+        self.ctx.restart();
+        let mut input: Option<Vec<u8>> = None;
+        loop {
+            let tok = self
+                .ctx
+                .step(input.as_deref(), self.channel_bindings.as_deref())?;
+            let complete = self.ctx.is_complete();
+            let reply = match tok {
+                Some(tok) => (self.exchange)(&tok, complete)?,
+                None => None,
+            };
+            if complete {
+                break;
+            }
+            input = Some(reply.ok_or(Error {
+                major: MajorFlags::GSS_S_DEFECTIVE_TOKEN,
+                minor: 0,
+            })?);
+        }
+        self.expired = false;
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Make sure there is a live context, establishing a new one if
+    /// necessary. Returns true if a new context was established.
+    pub fn refresh(&mut self) -> Result<bool, Error> {
+        if self.needs_renewal() {
+            self.establish()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Return the current context, establishing a new one if
+    /// necessary. Check `generation` to see if it was replaced.
+    pub fn context(&mut self) -> Result<&mut ClientCtx, Error> {
+        self.refresh()?;
+        Ok(&mut self.ctx)
+    }
+
+    fn check_expired<T>(&mut self, r: Result<T, Error>) -> Result<T, Error> {
+        if let Err(e) = &r {
+            if e.is_context_expired() {
+                self.expired = true;
+            }
+        }
+        r
+    }
+
+    /// Wrap a message, first establishing a new context if the
+    /// current one has expired. If the context expires during the
+    /// call the error is returned, and the next call establishes a
+    /// new one.
+    pub fn wrap(&mut self, encrypt: bool, msg: &[u8]) -> Result<Wrapped, Error> {
+        let renewed = self.refresh()?;
+        let tok = self.ctx.wrap(encrypt, msg);
+        let tok = self.check_expired(tok)?;
+        Ok(if renewed && self.generation > 1 {
+            Wrapped::Reestablished(tok)
+        } else {
+            Wrapped::Current(tok)
+        })
+    }
+
+    /// Unwrap a message from the server using the current context.
+    /// This never establishes a new context, since the message was
+    /// wrapped under the current one. If it fails with
+    /// `GSS_S_CONTEXT_EXPIRED` (or `GSS_S_NO_CONTEXT` if there isn't
+    /// one yet) the message is lost, and the next `wrap` establishes
+    /// a new context.
+    pub fn unwrap(&mut self, msg: &[u8]) -> Result<Buf, Error> {
+        if !self.ctx.is_complete() {
+            return Err(Error {
+                major: MajorFlags::GSS_S_NO_CONTEXT,
+                minor: 0,
+            });
+        }
+        let msg = self.ctx.unwrap(msg);
+        self.check_expired(msg)
+    }
+
+    /// Return the underlying context, which may not be established
+    pub fn into_context(self) -> ClientCtx {
+        self.ctx
+    }
+}
@@ -0,0 +1,334 @@
+//! The SASL GSSAPI mechanism (rfc4752), as used by LDAP, memcached,
+//! and many other protocols.
+//!
+//! After the gssapi context is established the server sends a
+//! wrapped message listing the security layers it offers and the
+//! largest message it will accept, and the client replies with a
+//! wrapped message choosing one of them, the largest message it will
+//! accept, and optionally an authorization identity.
+//!
+//! Once the exchange is complete, if a security layer was chosen,
+//! each SASL message must be wrapped (with encryption if the layer is
+//! `CONFIDENTIALITY`) using the context, which you can get back from
+//! `SaslClient::context_mut` or `SaslServer::context_mut`.
+//!
+//! The GS2 mechanisms (`GS2-KRB5` etc) are in [`gs2`].
+use crate::{
+    context::{ClientCtx, CtxFlags, SecurityContext, ServerCtx},
+    error::{Error, MajorFlags},
+};
+use std::str;
+
+pub mod gs2;
+
+bitflags! {
+    /// The SASL security layers
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct SecurityLayers: u8 {
+        /// No security layer, messages are sent in the clear after
+        /// authentication
+        const NONE = 1;
+        /// Messages are integrity protected
+        const INTEGRITY = 2;
+        /// Messages are encrypted and integrity protected
+        const CONFIDENTIALITY = 4;
+    }
+}
+
+/// The largest value of the 3 byte max message size field
+pub const MAX_SIZE: u32 = 0xffffff;
+
+fn defective() -> Error {
+    Error {
+        major: MajorFlags::GSS_S_DEFECTIVE_TOKEN,
+        minor: 0,
+    }
+}
+
+fn bad_layer() -> Error {
+    Error {
+        major: MajorFlags::GSS_S_BAD_QOP,
+        minor: 0,
+    }
+}
+
+/// Remove the layers the context can't actually provide
+fn supported(layers: SecurityLayers, flags: CtxFlags) -> SecurityLayers {
+    let mut layers = layers;
+    if !flags.contains(CtxFlags::GSS_C_INTEG_FLAG) {
+        layers.remove(SecurityLayers::INTEGRITY | SecurityLayers::CONFIDENTIALITY);
+    }
+    if !flags.contains(CtxFlags::GSS_C_CONF_FLAG) {
+        layers.remove(SecurityLayers::CONFIDENTIALITY);
+    }
+    layers
+}
+
+fn encode(layer: SecurityLayers, max_size: u32, authzid: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(4 + authzid.len());
+    msg.push(layer.bits());
+    msg.extend_from_slice(&max_size.min(MAX_SIZE).to_be_bytes()[1..]);
+    msg.extend_from_slice(authzid);
+    msg
+}
+
+fn decode(msg: &[u8]) -> Result<(SecurityLayers, u32, &[u8]), Error> {
+    if msg.len() < 4 {
+        return Err(defective());
+    }
+    let layers = SecurityLayers::from_bits_truncate(msg[0]);
+    let max_size = u32::from_be_bytes([0, msg[1], msg[2], msg[3]]);
+    Ok((layers, max_size, &msg[4..]))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ClientState {
+    Handshake,
+    Negotiate,
+    Done,
+}
+
+/// The client side of the SASL GSSAPI mechanism
+#[derive(Debug)]
+pub struct SaslClient {
+    ctx: ClientCtx,
+    state: ClientState,
+    acceptable: SecurityLayers,
+    max_size: u32,
+    authzid: Option<String>,
+    layer: Option<SecurityLayers>,
+    server_max_size: Option<u32>,
+}
+
+impl SaslClient {
+    /// Create a new SASL client driving `ctx`, which should request
+    /// `GSS_C_MUTUAL_FLAG` and `GSS_C_SEQUENCE_FLAG`, as well as
+    /// `GSS_C_INTEG_FLAG` and `GSS_C_CONF_FLAG` if you want those
+    /// layers. The strongest layer in `acceptable` that the server
+    /// offers is chosen. `max_size` is the largest message we will
+    /// accept, and `authzid` is the identity to act as, if it's
+    /// different from the authenticated one.
+    pub fn new(
+        ctx: ClientCtx,
+        acceptable: SecurityLayers,
+        max_size: u32,
+        authzid: Option<&str>,
+    ) -> SaslClient {
+        SaslClient {
+            ctx,
+            state: ClientState::Handshake,
+            acceptable,
+            max_size,
+            authzid: authzid.map(String::from),
+            layer: None,
+            server_max_size: None,
+        }
+    }
+
+    /// Perform 1 step of the exchange, feeding it the server's
+    /// challenge (`None` to produce the initial response). Returns
+    /// the response to send to the server (which may be empty), or
+    /// `None` once the exchange is complete.
+    pub fn step(&mut self, challenge: Option<&[u8]>) -> Result<Option<Vec<u8>>, Error> {
+        match self.state {
+            ClientState::Done => Ok(None),
+            ClientState::Handshake => {
+                let tok = self.ctx.step(challenge, None)?;
+                if self.ctx.is_complete() {
+                    self.state = ClientState::Negotiate;
+                }
+                Ok(Some(tok.map(|tok| tok.to_vec()).unwrap_or_default()))
+            }
+            ClientState::Negotiate => {
+                let challenge = challenge.ok_or_else(defective)?;
+                let msg = self.ctx.unwrap(challenge)?;
+                let (offered, server_max_size, _) = decode(&msg)?;
+                if msg.len() != 4 {
+                    return Err(defective());
+                }
+                let flags = self.ctx.flags()?;
+                let usable = offered & supported(self.acceptable, flags);
+                let layer = [
+                    SecurityLayers::CONFIDENTIALITY,
+                    SecurityLayers::INTEGRITY,
+                    SecurityLayers::NONE,
+                ]
+                .iter()
+                .copied()
+                .find(|l| usable.contains(*l))
+                .ok_or_else(bad_layer)?;
+                let max_size = if layer == SecurityLayers::NONE {
+                    0
+                } else {
+                    self.max_size
+                };
+                let authzid = self.authzid.as_deref().unwrap_or("");
+                let reply = encode(layer, max_size, authzid.as_bytes());
+                let tok = self.ctx.wrap(false, &reply)?;
+                self.layer = Some(layer);
+                self.server_max_size = Some(server_max_size);
+                self.state = ClientState::Done;
+                Ok(Some(tok.to_vec()))
+            }
+        }
+    }
+
+    /// Return true if the exchange is complete
+    pub fn is_complete(&self) -> bool {
+        match self.state {
+            ClientState::Done => true,
+            ClientState::Handshake | ClientState::Negotiate => false,
+        }
+    }
+
+    /// The security layer chosen, once the exchange is complete
+    pub fn layer(&self) -> Option<SecurityLayers> {
+        self.layer
+    }
+
+    /// The largest message the server will accept, once the exchange
+    /// is complete
+    pub fn server_max_size(&self) -> Option<u32> {
+        self.server_max_size
+    }
+
+    /// Get a mutable reference to the security context
+    pub fn context_mut(&mut self) -> &mut ClientCtx {
+        &mut self.ctx
+    }
+
+    /// Return the security context
+    pub fn into_context(self) -> ClientCtx {
+        self.ctx
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ServerState {
+    Handshake,
+    AwaitEmpty,
+    AwaitChoice,
+    Done,
+}
+
+/// The server side of the SASL GSSAPI mechanism
+#[derive(Debug)]
+pub struct SaslServer {
+    ctx: ServerCtx,
+    state: ServerState,
+    offered: SecurityLayers,
+    max_size: u32,
+    layer: Option<SecurityLayers>,
+    client_max_size: Option<u32>,
+    authzid: Option<String>,
+}
+
+impl SaslServer {
+    /// Create a new SASL server driving `ctx`, offering the security
+    /// layers in `offered` (less any the context turns out not to
+    /// support), and accepting messages of up to `max_size` bytes.
+    pub fn new(ctx: ServerCtx, offered: SecurityLayers, max_size: u32) -> SaslServer {
+        SaslServer {
+            ctx,
+            state: ServerState::Handshake,
+            offered,
+            max_size,
+            layer: None,
+            client_max_size: None,
+            authzid: None,
+        }
+    }
+
+    fn offer(&mut self) -> Result<Vec<u8>, Error> {
+        let offered = supported(self.offered, self.ctx.flags()?);
+        if offered.is_empty() {
+            return Err(bad_layer());
+        }
+        self.offered = offered;
+        self.state = ServerState::AwaitChoice;
+        Ok(self
+            .ctx
+            .wrap(false, &encode(offered, self.max_size, &[]))?
+            .to_vec())
+    }
+
+    /// Perform 1 step of the exchange, feeding it the client's
+    /// response. Returns the challenge to send to the client, or
+    /// `None` once the client is authenticated.
+    ///
+    /// A client that chooses a layer we didn't offer is rejected with
+    /// `GSS_S_BAD_QOP`.
+    pub fn step(&mut self, response: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.state {
+            ServerState::Done => Ok(None),
+            ServerState::Handshake => {
+                let tok = self.ctx.step(response)?;
+                match tok {
+                    Some(tok) if self.ctx.is_complete() => {
+                        // the client must see our last token before we
+                        // can start negotiating the layer
+                        self.state = ServerState::AwaitEmpty;
+                        Ok(Some(tok.to_vec()))
+                    }
+                    None if self.ctx.is_complete() => self.offer().map(Some),
+                    tok => Ok(Some(tok.map(|tok| tok.to_vec()).unwrap_or_default())),
+                }
+            }
+            ServerState::AwaitEmpty => self.offer().map(Some),
+            ServerState::AwaitChoice => {
+                let msg = self.ctx.unwrap(response)?;
+                let (layer, client_max_size, authzid) = decode(&msg)?;
+                if layer.bits().count_ones() != 1 || !self.offered.contains(layer) {
+                    return Err(bad_layer());
+                }
+                let authzid = str::from_utf8(authzid).map_err(|_| defective())?;
+                self.layer = Some(layer);
+                self.client_max_size = Some(client_max_size);
+                if !authzid.is_empty() {
+                    self.authzid = Some(String::from(authzid));
+                }
+                self.state = ServerState::Done;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Return true if the client is authenticated
+    pub fn is_complete(&self) -> bool {
+        match self.state {
+            ServerState::Done => true,
+            ServerState::Handshake
+            | ServerState::AwaitEmpty
+            | ServerState::AwaitChoice => false,
+        }
+    }
+
+    /// The security layer the client chose, once the exchange is
+    /// complete
+    pub fn layer(&self) -> Option<SecurityLayers> {
+        self.layer
+    }
+
+    /// The largest message the client will accept, once the exchange
+    /// is complete
+    pub fn client_max_size(&self) -> Option<u32> {
+        self.client_max_size
+    }
+
+    /// The identity the client asked to act as, if it's different
+    /// from the authenticated one (`context_mut().source_name()`).
+    /// It's up to you to decide if the client is allowed to do that.
+    pub fn authzid(&self) -> Option<&str> {
+        self.authzid.as_deref()
+    }
+
+    /// Get a mutable reference to the security context
+    pub fn context_mut(&mut self) -> &mut ServerCtx {
+        &mut self.ctx
+    }
+
+    /// Return the security context
+    pub fn into_context(self) -> ServerCtx {
+        self.ctx
+    }
+}
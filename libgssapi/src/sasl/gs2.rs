@@ -0,0 +1,424 @@
+//! The GS2 family of SASL mechanisms (rfc5801), e.g. `GS2-KRB5` and
+//! `GS2-KRB5-PLUS`, which modern SASL stacks prefer over the legacy
+//! `GSSAPI` mechanism.
+//!
+//! Unlike `GSSAPI` there are no security layers to negotiate, the
+//! exchange is just the gssapi context establishment with a GS2
+//! header on the first message. The header carries the optional
+//! authorization identity and says whether the client is using
+//! channel binding (the `-PLUS` variants), and it is itself bound
+//! into the context via the gssapi channel bindings, so it can't be
+//! tampered with.
+use crate::{
+    context::{ClientCtx, SecurityContext, ServerCtx},
+    error::{Error, MajorFlags},
+    oid::{split_initial_token, Oid, OwnedOid},
+    util::{Buf, BufRef},
+};
+use libgssapi_sys::{
+    gss_OID, gss_OID_desc, gss_inquire_mech_for_saslname, gss_inquire_saslname_for_mech,
+    OM_uint32, GSS_S_COMPLETE,
+};
+use std::{ptr, str};
+
+/// The suffix of the channel binding variant of a mechanism name
+pub const PLUS: &str = "-PLUS";
+
+fn defective() -> Error {
+    Error {
+        major: MajorFlags::GSS_S_DEFECTIVE_TOKEN,
+        minor: 0,
+    }
+}
+
+fn bad_bindings() -> Error {
+    Error {
+        major: MajorFlags::GSS_S_BAD_BINDINGS,
+        minor: 0,
+    }
+}
+
+/// Return the SASL mechanism name of `mech`, e.g. `GS2-KRB5` for
+/// `GSS_MECH_KRB5`. Append `PLUS` to get the name of the channel
+/// binding variant.
+pub fn saslname_for_mech(mech: &Oid) -> Result<String, Error> {
+    let mut sasl_name = Buf::empty();
+    let mut mech_name = Buf::empty();
+    let mut mech_description = Buf::empty();
+    let mut minor = GSS_S_COMPLETE;
+    let major = unsafe {
+        gss_inquire_saslname_for_mech(
+            &mut minor as *mut OM_uint32,
+            mech.to_c(),
+            sasl_name.to_c(),
+            mech_name.to_c(),
+            mech_description.to_c(),
+        )
+    };
+    if major == GSS_S_COMPLETE {
+        Ok(String::from_utf8_lossy(&sasl_name).into_owned())
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
+/// Return the mechanism of the SASL mechanism name `name`, and
+/// whether `name` is the `-PLUS` (channel binding) variant.
+pub fn mech_for_saslname(name: &str) -> Result<(&'static Oid, bool), Error> {
+    let (name, plus) = match name.strip_suffix(PLUS) {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let mut buf = BufRef::from(name.as_bytes());
+    let mut mech = ptr::null_mut::<gss_OID_desc>();
+    let mut minor = GSS_S_COMPLETE;
+    let major = unsafe {
+        gss_inquire_mech_for_saslname(
+            &mut minor as *mut OM_uint32,
+            buf.to_c(),
+            &mut mech as *mut gss_OID,
+        )
+    };
+    if major == GSS_S_COMPLETE && !mech.is_null() {
+        Ok((unsafe { Oid::from_c(mech) }, plus))
+    } else {
+        Err(Error {
+            major: MajorFlags::from_bits_retain(major),
+            minor,
+        })
+    }
+}
+
+/// The gs2-cb-flag, how the client is using channel binding
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CbFlag {
+    /// `n`, the client doesn't support channel binding
+    NotSupported,
+    /// `y`, the client supports channel binding, but thinks the
+    /// server doesn't
+    NotUsed,
+    /// `p=<cb-name>`, the client is using the named channel binding
+    /// type, e.g. `tls-exporter` or `tls-server-end-point`
+    Used(String),
+}
+
+/// The GS2 header sent at the start of the client's first message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gs2Header {
+    /// The initial token doesn't have the standard rfc2743 framing
+    pub nonstd: bool,
+    pub cb_flag: CbFlag,
+    /// The identity the client wants to act as, if it's different
+    /// from the authenticated one
+    pub authzid: Option<String>,
+}
+
+impl Gs2Header {
+    fn encode_bound(&self, out: &mut Vec<u8>) {
+        match &self.cb_flag {
+            CbFlag::NotSupported => out.push(b'n'),
+            CbFlag::NotUsed => out.push(b'y'),
+            CbFlag::Used(name) => {
+                out.extend_from_slice(b"p=");
+                out.extend_from_slice(name.as_bytes());
+            }
+        }
+        out.push(b',');
+        if let Some(authzid) = &self.authzid {
+            out.extend_from_slice(b"a=");
+            for c in authzid.bytes() {
+                match c {
+                    b',' => out.extend_from_slice(b"=2C"),
+                    b'=' => out.extend_from_slice(b"=3D"),
+                    c => out.push(c),
+                }
+            }
+        }
+        out.push(b',');
+    }
+
+    /// Encode the header
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        if self.nonstd {
+            out.extend_from_slice(b"F,");
+        }
+        self.encode_bound(&mut out);
+        out
+    }
+
+    /// The application data both sides must use as the gssapi channel
+    /// bindings, the header (less the nonstd flag) followed by the
+    /// channel binding data if it's being used.
+    pub fn channel_bindings(&self, cb_data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_bound(&mut out);
+        if let CbFlag::Used(_) = self.cb_flag {
+            out.extend_from_slice(cb_data);
+        }
+        out
+    }
+
+    /// Parse the header at the start of `msg`, returning it and the
+    /// rest of the message.
+    pub fn parse(msg: &[u8]) -> Result<(Gs2Header, &[u8]), Error> {
+        fn field(msg: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+            let i = msg.iter().position(|c| *c == b',').ok_or_else(defective)?;
+            Ok((&msg[..i], &msg[i + 1..]))
+        }
+        let (nonstd, msg) = match msg.strip_prefix(b"F,") {
+            Some(msg) => (true, msg),
+            None => (false, msg),
+        };
+        let (cb_flag, msg) = field(msg)?;
+        let cb_flag = match cb_flag {
+            b"n" => CbFlag::NotSupported,
+            b"y" => CbFlag::NotUsed,
+            flag => match flag.strip_prefix(b"p=") {
+                Some(name)
+                    if !name.is_empty()
+                        && name.iter().all(|c| {
+                            c.is_ascii_alphanumeric() || *c == b'.' || *c == b'-'
+                        }) =>
+                {
+                    CbFlag::Used(String::from_utf8_lossy(name).into_owned())
+                }
+                _ => return Err(defective()),
+            },
+        };
+        let (authzid, msg) = field(msg)?;
+        let authzid = if authzid.is_empty() {
+            None
+        } else {
+            let escaped = authzid.strip_prefix(b"a=").ok_or_else(defective)?;
+            let escaped = str::from_utf8(escaped).map_err(|_| defective())?;
+            let mut authzid = String::with_capacity(escaped.len());
+            let mut parts = escaped.split('=');
+            authzid.push_str(parts.next().unwrap_or(""));
+            for part in parts {
+                if let Some(rest) = part.strip_prefix("2C") {
+                    authzid.push(',');
+                    authzid.push_str(rest);
+                } else if let Some(rest) = part.strip_prefix("3D") {
+                    authzid.push('=');
+                    authzid.push_str(rest);
+                } else {
+                    return Err(defective());
+                }
+            }
+            Some(authzid)
+        };
+        Ok((
+            Gs2Header {
+                nonstd,
+                cb_flag,
+                authzid,
+            },
+            msg,
+        ))
+    }
+}
+
+fn push_der_len(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8)
+    } else {
+        let bytes = (len as u32).to_be_bytes();
+        let skip = bytes.iter().take_while(|c| **c == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+/// Strip the rfc2743 3.1 framing (the application tag, length, and
+/// mechanism oid) from an initial context token
+fn unframe(tok: &[u8]) -> Option<&[u8]> {
+    split_initial_token(tok).map(|(_, inner)| inner)
+}
+
+/// Put the framing `unframe` removed back
+fn frame(mech: &Oid, inner: &[u8]) -> Vec<u8> {
+    let oid: &[u8] = mech;
+    let mut oid_len = Vec::new();
+    push_der_len(&mut oid_len, oid.len());
+    let mut tok = Vec::with_capacity(inner.len() + oid.len() + 16);
+    tok.push(0x60);
+    push_der_len(&mut tok, 1 + oid_len.len() + oid.len() + inner.len());
+    tok.push(0x06);
+    tok.extend_from_slice(&oid_len);
+    tok.extend_from_slice(oid);
+    tok.extend_from_slice(inner);
+    tok
+}
+
+/// The client side of a GS2 mechanism
+#[derive(Debug)]
+pub struct Gs2Client {
+    ctx: ClientCtx,
+    header: Gs2Header,
+    bindings: Vec<u8>,
+    started: bool,
+}
+
+impl Gs2Client {
+    /// Create a new GS2 client driving `ctx`, which should request
+    /// `GSS_C_MUTUAL_FLAG`. `cb_data` is the channel binding data
+    /// (e.g. from the TLS connection) and is only used if `cb_flag`
+    /// is `CbFlag::Used`, which is what the `-PLUS` variants require.
+    pub fn new(
+        ctx: ClientCtx,
+        authzid: Option<&str>,
+        cb_flag: CbFlag,
+        cb_data: &[u8],
+    ) -> Gs2Client {
+        let header = Gs2Header {
+            nonstd: false,
+            cb_flag,
+            authzid: authzid.map(String::from),
+        };
+        let bindings = header.channel_bindings(cb_data);
+        Gs2Client {
+            ctx,
+            header,
+            bindings,
+            started: false,
+        }
+    }
+
+    /// Perform 1 step of the exchange, feeding it the server's
+    /// challenge (`None` to produce the initial response). Returns
+    /// the response to send to the server, or `None` once the
+    /// context is established.
+    pub fn step(&mut self, challenge: Option<&[u8]>) -> Result<Option<Vec<u8>>, Error> {
+        if self.started {
+            let tok = self.ctx.step(challenge, Some(&self.bindings))?;
+            return Ok(tok.map(|tok| tok.to_vec()));
+        }
+        let tok = self
+            .ctx
+            .step(None, Some(&self.bindings))?
+            .ok_or_else(defective)?;
+        self.started = true;
+        let inner = unframe(&tok);
+        self.header.nonstd = inner.is_none();
+        let mut msg = self.header.encode();
+        msg.extend_from_slice(inner.unwrap_or(&tok));
+        Ok(Some(msg))
+    }
+
+    /// Return true if the context is established
+    pub fn is_complete(&self) -> bool {
+        self.ctx.is_complete()
+    }
+
+    /// Get a mutable reference to the security context
+    pub fn context_mut(&mut self) -> &mut ClientCtx {
+        &mut self.ctx
+    }
+
+    /// Return the security context
+    pub fn into_context(self) -> ClientCtx {
+        self.ctx
+    }
+}
+
+/// The server side of a GS2 mechanism
+#[derive(Debug)]
+pub struct Gs2Server {
+    ctx: ServerCtx,
+    mech: OwnedOid,
+    channel_binding: Option<(String, Vec<u8>)>,
+    header: Option<Gs2Header>,
+    bindings: Vec<u8>,
+}
+
+impl Gs2Server {
+    /// Create a new GS2 server driving `ctx` for `mech` (which you can
+    /// get from `mech_for_saslname`). If the server offered the
+    /// `-PLUS` variant then `channel_binding` is the channel binding
+    /// type it supports (e.g. `tls-exporter`) and the data for this
+    /// connection.
+    ///
+    /// A client using a different channel binding type, or saying it
+    /// supports channel binding but thinks we don't (a sign that the
+    /// `-PLUS` mechanism was stripped from our advertisement), is
+    /// rejected with `GSS_S_BAD_BINDINGS`, as is a client whose
+    /// channel binding data doesn't match ours.
+    pub fn new(
+        ctx: ServerCtx,
+        mech: &Oid,
+        channel_binding: Option<(&str, &[u8])>,
+    ) -> Gs2Server {
+        Gs2Server {
+            ctx,
+            mech: OwnedOid::from(mech),
+            channel_binding: channel_binding
+                .map(|(name, data)| (String::from(name), Vec::from(data))),
+            header: None,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Perform 1 step of the exchange, feeding it the client's
+    /// response. Returns the challenge to send to the client, or
+    /// `None` once the context is established.
+    pub fn step(&mut self, response: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if self.header.is_some() {
+            let tok = self
+                .ctx
+                .step_with_channel_bindings(response, Some(&self.bindings))?;
+            return Ok(tok.map(|tok| tok.to_vec()));
+        }
+        let (header, inner) = Gs2Header::parse(response)?;
+        let cb_data = match (&header.cb_flag, &self.channel_binding) {
+            (CbFlag::NotSupported, _) | (CbFlag::NotUsed, None) => &[][..],
+            (CbFlag::NotUsed, Some(_)) => return Err(bad_bindings()),
+            (CbFlag::Used(name), Some((ours, data))) if name == ours => &data[..],
+            (CbFlag::Used(_), _) => return Err(bad_bindings()),
+        };
+        self.bindings = header.channel_bindings(cb_data);
+        let tok = if header.nonstd {
+            Vec::from(inner)
+        } else {
+            frame(&self.mech, inner)
+        };
+        self.header = Some(header);
+        let tok = self
+            .ctx
+            .step_with_channel_bindings(&tok, Some(&self.bindings))?;
+        Ok(tok.map(|tok| tok.to_vec()))
+    }
+
+    /// Return true if the context is established
+    pub fn is_complete(&self) -> bool {
+        self.ctx.is_complete()
+    }
+
+    /// The channel binding type the client used, if any
+    pub fn channel_binding(&self) -> Option<&str> {
+        match self.header.as_ref().map(|h| &h.cb_flag) {
+            Some(CbFlag::Used(name)) => Some(name),
+            Some(CbFlag::NotSupported) | Some(CbFlag::NotUsed) | None => None,
+        }
+    }
+
+    /// The identity the client asked to act as, if it's different
+    /// from the authenticated one (`context_mut().source_name()`).
+    /// It's up to you to decide if the client is allowed to do that.
+    pub fn authzid(&self) -> Option<&str> {
+        self.header.as_ref().and_then(|h| h.authzid.as_deref())
+    }
+
+    /// Get a mutable reference to the security context
+    pub fn context_mut(&mut self) -> &mut ServerCtx {
+        &mut self.ctx
+    }
+
+    /// Return the security context
+    pub fn into_context(self) -> ServerCtx {
+        self.ctx
+    }
+}
@@ -0,0 +1,1024 @@
+//! A blocking stream that protects everything passing through it
+//! with an established security context, a drop in "encrypted
+//! TcpStream".
+//!
+//! Each write is wrapped and sent as a record consisting of the
+//! length of the wrap token as a 4 byte big endian integer followed
+//! by the token. Both sides must use `GssStream` (or speak the same
+//! framing).
+//!
+//! `GssToken` applies the same framing to the context establishment
+//! tokens exchanged before the stream is set up.
+use crate::{context::SecurityContext, error::Error, util::Buf};
+use std::{
+    cmp::{max, min},
+    convert::TryFrom,
+    io::{self, Read, Write},
+    ops::Deref,
+};
+
+/// The largest wrap token we will send. Writes are split so that no
+/// record exceeds this.
+const MAX_TOKEN: usize = 0x10000;
+
+/// Refuse to read records bigger than this, so a hostile peer can't
+/// make us allocate an arbitrary amount of memory.
+const MAX_RECORD: usize = 0x1000000;
+
+fn to_io(e: Error) -> io::Error {
+    io::Error::other(e)
+}
+
+/// The framing settings shared by the streams and the codec
+#[derive(Debug, Clone, Copy)]
+struct Framing {
+    encrypt: bool,
+    max_send: usize,
+    max_recv: usize,
+    limit: Option<usize>,
+}
+
+impl Framing {
+    fn new(encrypt: bool) -> Self {
+        Framing {
+            encrypt,
+            max_send: MAX_TOKEN,
+            max_recv: MAX_RECORD,
+            limit: None,
+        }
+    }
+
+    fn set_max_token_size(&mut self, size: usize) {
+        self.max_send = size;
+        self.max_recv = size;
+        self.limit = None;
+    }
+
+    /// Decode a record header, returning the length of the token
+    /// that follows.
+    fn record_len(&self, hdr: [u8; 4]) -> io::Result<usize> {
+        let len = u32::from_be_bytes(hdr) as usize;
+        if len > self.max_recv {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "record too large",
+            ))
+        } else {
+            Ok(len)
+        }
+    }
+
+    /// Return the largest message that fits in one record, asking
+    /// the context the first time.
+    fn max_input<C: SecurityContext>(&mut self, ctx: &mut C) -> io::Result<usize> {
+        match self.limit {
+            Some(max_input) => Ok(max_input),
+            None => {
+                let max_input = ctx
+                    .wrap_size_limit(self.encrypt, self.max_send)
+                    .map_err(to_io)?;
+                let max_input = max(max_input, 1);
+                self.limit = Some(max_input);
+                Ok(max_input)
+            }
+        }
+    }
+
+    /// Wrap as much of `buf` as fits in one record, returning the
+    /// number of bytes consumed and the record to send.
+    fn wrap_record<C: SecurityContext>(
+        &mut self,
+        ctx: &mut C,
+        buf: &[u8],
+    ) -> io::Result<(usize, Vec<u8>)> {
+        let n = min(buf.len(), self.max_input(ctx)?);
+        let tok = ctx.wrap(self.encrypt, &buf[..n]).map_err(to_io)?;
+        let mut record = Vec::with_capacity(4 + tok.len());
+        record.extend_from_slice(&(tok.len() as u32).to_be_bytes());
+        record.extend_from_slice(&tok);
+        Ok((n, record))
+    }
+}
+
+/// A stream over `S` that wraps everything written and unwraps
+/// everything read using the security context `C`. The context must
+/// be complete before it is used.
+///
+/// This is meant for blocking transports, if the transport returns
+/// `WouldBlock` in the middle of a record the stream is corrupted.
+#[derive(Debug)]
+pub struct GssStream<C, S> {
+    ctx: C,
+    inner: S,
+    framing: Framing,
+    rbuf: Buf,
+    pos: usize,
+}
+
+impl<C: SecurityContext, S> GssStream<C, S> {
+    /// Create a new stream over `inner`. If `encrypt` is true then
+    /// records are encrypted as well as integrity protected.
+    pub fn new(ctx: C, inner: S, encrypt: bool) -> Self {
+        GssStream {
+            ctx,
+            inner,
+            framing: Framing::new(encrypt),
+            rbuf: Buf::empty(),
+            pos: 0,
+        }
+    }
+
+    /// Limit wrap tokens to `size` bytes in both directions,
+    /// larger records from the peer are an error. The default is
+    /// to send tokens of up to 64KiB and accept up to 16MiB.
+    #[must_use]
+    pub fn max_token_size(mut self, size: usize) -> Self {
+        self.framing.set_max_token_size(size);
+        self
+    }
+
+    /// Get a reference to the security context
+    pub fn context(&self) -> &C {
+        &self.ctx
+    }
+
+    /// Get a mutable reference to the security context
+    pub fn context_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+
+    /// Get a reference to the underlying transport
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying transport. Reading
+    /// or writing it directly will corrupt the stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Return the security context and the transport. Any data that
+    /// was read and unwrapped but not yet consumed is lost.
+    pub fn into_inner(self) -> (C, S) {
+        (self.ctx, self.inner)
+    }
+}
+
+impl<C: SecurityContext, S: Read> GssStream<C, S> {
+    /// Read and unwrap the next record, returning `None` on a clean
+    /// end of stream.
+    fn read_record(&mut self) -> io::Result<Option<Buf>> {
+        let mut hdr = [0u8; 4];
+        let mut got = 0;
+        while got < hdr.len() {
+            match self.inner.read(&mut hdr[got..]) {
+                Ok(0) if got == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => got += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        let mut tok = vec![0u8; self.framing.record_len(hdr)?];
+        self.inner.read_exact(&mut tok)?;
+        self.ctx.unwrap(&tok).map(Some).map_err(to_io)
+    }
+}
+
+impl<C: SecurityContext, S: Read> Read for GssStream<C, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pos >= self.rbuf.len() {
+            match self.read_record()? {
+                None => return Ok(0),
+                Some(rbuf) => {
+                    self.rbuf = rbuf;
+                    self.pos = 0;
+                }
+            }
+        }
+        let n = min(buf.len(), self.rbuf.len() - self.pos);
+        buf[..n].copy_from_slice(&self.rbuf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl<C: SecurityContext, S: Write> Write for GssStream<C, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let (n, record) = self.framing.wrap_record(&mut self.ctx, buf)?;
+        self.inner.write_all(&record)?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A writer over `W` that wraps everything written using the
+/// security context `C`, framed the same way as `GssStream`. Unlike
+/// `GssStream` each write consumes the whole buffer, splitting it
+/// into as many records as it takes, each carrying as much data as
+/// `wrap_size_limit` allows for the maximum token size. Read it back
+/// with a `GssStream` on the other side.
+#[derive(Debug)]
+pub struct WrapWriter<C, W> {
+    ctx: C,
+    inner: W,
+    framing: Framing,
+}
+
+impl<C: SecurityContext, W> WrapWriter<C, W> {
+    /// Create a new writer over `inner`. If `encrypt` is true then
+    /// records are encrypted as well as integrity protected.
+    pub fn new(ctx: C, inner: W, encrypt: bool) -> Self {
+        WrapWriter {
+            ctx,
+            inner,
+            framing: Framing::new(encrypt),
+        }
+    }
+
+    /// Limit wrap tokens to `size` bytes. The default is 64KiB.
+    #[must_use]
+    pub fn max_token_size(mut self, size: usize) -> Self {
+        self.framing.set_max_token_size(size);
+        self
+    }
+
+    /// Get a reference to the security context
+    pub fn context(&self) -> &C {
+        &self.ctx
+    }
+
+    /// Get a mutable reference to the security context
+    pub fn context_mut(&mut self) -> &mut C {
+        &mut self.ctx
+    }
+
+    /// Get a reference to the underlying writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying writer. Writing to
+    /// it directly will corrupt the stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Return the security context and the writer
+    pub fn into_inner(self) -> (C, W) {
+        (self.ctx, self.inner)
+    }
+}
+
+impl<C: SecurityContext, W: Write> Write for WrapWriter<C, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let (n, record) = self.framing.wrap_record(&mut self.ctx, rest)?;
+            self.inner.write_all(&record)?;
+            rest = &rest[n..];
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An opaque token, such as the output of `step`, framed on the wire
+/// as its length as a 4 byte big endian integer followed by the
+/// token.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GssToken(pub Vec<u8>);
+
+impl Deref for GssToken {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for GssToken {
+    fn from(tok: Vec<u8>) -> Self {
+        GssToken(tok)
+    }
+}
+
+impl From<&[u8]> for GssToken {
+    fn from(tok: &[u8]) -> Self {
+        GssToken(Vec::from(tok))
+    }
+}
+
+impl From<Buf> for GssToken {
+    fn from(tok: Buf) -> Self {
+        GssToken(tok.to_vec())
+    }
+}
+
+impl GssToken {
+    /// Write the length of the token followed by the token to `w`.
+    /// Tokens longer than `u32::MAX` can't be framed and fail with
+    /// `InvalidInput`.
+    pub fn encode_to(&self, w: &mut impl Write) -> io::Result<()> {
+        let len = u32::try_from(self.0.len()).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "token too large")
+        })?;
+        w.write_all(&len.to_be_bytes())?;
+        w.write_all(&self.0)
+    }
+
+    /// Read a token written by `encode_to` from `r`. Tokens larger
+    /// than 16MiB are refused with `InvalidData` before anything is
+    /// allocated for them.
+    pub fn decode_from(r: &mut impl Read) -> io::Result<GssToken> {
+        let mut hdr = [0u8; 4];
+        r.read_exact(&mut hdr)?;
+        let mut tok = vec![0u8; Framing::new(false).record_len(hdr)?];
+        r.read_exact(&mut tok)?;
+        Ok(GssToken(tok))
+    }
+
+    /// Return the token bytes
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+mod records {
+    use super::*;
+    use std::{
+        mem,
+        task::{ready, Poll},
+    };
+
+    /// The record layer shared by the async streams. The transport is
+    /// passed in as closures so the same state machine works with any
+    /// flavor of async io trait.
+    ///
+    /// A record that is only partly read or written when the
+    /// transport returns `Pending` is kept and resumed on the next
+    /// poll.
+    #[derive(Debug)]
+    pub(super) struct Records<C> {
+        pub(super) ctx: C,
+        pub(super) framing: Framing,
+        hdr: [u8; 4],
+        hdr_read: usize,
+        tok: Vec<u8>,
+        tok_read: usize,
+        rbuf: Buf,
+        pos: usize,
+        wbuf: Vec<u8>,
+        wpos: usize,
+    }
+
+    impl<C: SecurityContext> Records<C> {
+        pub(super) fn new(ctx: C, encrypt: bool) -> Self {
+            Records {
+                ctx,
+                framing: Framing::new(encrypt),
+                hdr: [0; 4],
+                hdr_read: 0,
+                tok: Vec::new(),
+                tok_read: 0,
+                rbuf: Buf::empty(),
+                pos: 0,
+                wbuf: Vec::new(),
+                wpos: 0,
+            }
+        }
+
+        /// Read and unwrap the next record, returning `None` on a
+        /// clean end of stream.
+        fn poll_record<R>(&mut self, read: &mut R) -> Poll<io::Result<Option<Buf>>>
+        where
+            R: FnMut(&mut [u8]) -> Poll<io::Result<usize>>,
+        {
+            while self.hdr_read < self.hdr.len() {
+                let n = ready!(read(&mut self.hdr[self.hdr_read..]))?;
+                if n == 0 && self.hdr_read == 0 {
+                    return Poll::Ready(Ok(None));
+                } else if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                self.hdr_read += n;
+                if self.hdr_read == self.hdr.len() {
+                    self.tok = vec![0u8; self.framing.record_len(self.hdr)?];
+                    self.tok_read = 0;
+                }
+            }
+            while self.tok_read < self.tok.len() {
+                let n = ready!(read(&mut self.tok[self.tok_read..]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                self.tok_read += n;
+            }
+            self.hdr_read = 0;
+            let tok = mem::take(&mut self.tok);
+            Poll::Ready(self.ctx.unwrap(&tok).map(Some).map_err(to_io))
+        }
+
+        /// Fill `buf` with unwrapped data, reading another record
+        /// from the transport if there is none left.
+        pub(super) fn poll_read<R>(
+            &mut self,
+            mut read: R,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>>
+        where
+            R: FnMut(&mut [u8]) -> Poll<io::Result<usize>>,
+        {
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            while self.pos >= self.rbuf.len() {
+                match ready!(self.poll_record(&mut read))? {
+                    None => return Poll::Ready(Ok(0)),
+                    Some(rbuf) => {
+                        self.rbuf = rbuf;
+                        self.pos = 0;
+                    }
+                }
+            }
+            let n = min(buf.len(), self.rbuf.len() - self.pos);
+            buf[..n].copy_from_slice(&self.rbuf[self.pos..self.pos + n]);
+            self.pos += n;
+            Poll::Ready(Ok(n))
+        }
+
+        /// Finish sending the current record, if any
+        pub(super) fn poll_drain<W>(&mut self, mut write: W) -> Poll<io::Result<()>>
+        where
+            W: FnMut(&[u8]) -> Poll<io::Result<usize>>,
+        {
+            while self.wpos < self.wbuf.len() {
+                let n = ready!(write(&self.wbuf[self.wpos..]))?;
+                if n == 0 {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                self.wpos += n;
+            }
+            self.wbuf.clear();
+            self.wpos = 0;
+            Poll::Ready(Ok(()))
+        }
+
+        /// Wrap as much of `buf` as fits in one record and start
+        /// sending it. The rest of the record is sent by the next
+        /// write or drain.
+        pub(super) fn poll_write<W>(
+            &mut self,
+            mut write: W,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>>
+        where
+            W: FnMut(&[u8]) -> Poll<io::Result<usize>>,
+        {
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            ready!(self.poll_drain(&mut write))?;
+            let (n, record) = self.framing.wrap_record(&mut self.ctx, buf)?;
+            self.wbuf = record;
+            // the data is ours now, if the transport isn't ready the
+            // rest of the record goes out with the next poll
+            if let Poll::Ready(Err(e)) = self.poll_drain(&mut write) {
+                return Poll::Ready(Err(e));
+            }
+            Poll::Ready(Ok(n))
+        }
+
+        /// Wrap all of `buf`, as many records as it takes, and start
+        /// sending them. The rest is sent by the next write or drain.
+        #[cfg(feature = "tokio")]
+        pub(super) fn poll_write_all<W>(
+            &mut self,
+            mut write: W,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>>
+        where
+            W: FnMut(&[u8]) -> Poll<io::Result<usize>>,
+        {
+            ready!(self.poll_drain(&mut write))?;
+            let mut rest = buf;
+            while !rest.is_empty() {
+                let (n, record) = self.framing.wrap_record(&mut self.ctx, rest)?;
+                self.wbuf.extend_from_slice(&record);
+                rest = &rest[n..];
+            }
+            if let Poll::Ready(Err(e)) = self.poll_drain(&mut write) {
+                return Poll::Ready(Err(e));
+            }
+            Poll::Ready(Ok(buf.len()))
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod async_tokio {
+    use super::{records::Records, *};
+    #[cfg(feature = "iov")]
+    use crate::util::{GssIov, GssIovType};
+    use bytes::{Buf as _, Bytes, BytesMut};
+    use std::{
+        convert::TryInto,
+        pin::Pin,
+        task::{ready, Context, Poll},
+    };
+    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    /// The same as `GssStream`, but over a tokio transport.
+    ///
+    /// A wrapped record that hasn't been completely sent yet is
+    /// finished by the next write, flush or shutdown.
+    ///
+    /// No context deletion token is sent on shutdown, modern
+    /// mechanisms (e.g. krb5) don't produce one and peers don't
+    /// expect it. Shutdown sends the rest of the last record and
+    /// shuts down `inner`, the peer reads that as the end of the
+    /// stream. An end of stream in the middle of a record is an
+    /// `UnexpectedEof` error, but one between records can't be told
+    /// apart from a shutdown, so a protocol that must detect
+    /// truncation has to mark its own end. The context is deleted
+    /// when it is dropped.
+    #[derive(Debug)]
+    pub struct AsyncGssStream<C, S> {
+        records: Records<C>,
+        inner: S,
+    }
+
+    impl<C: SecurityContext, S> AsyncGssStream<C, S> {
+        /// Create a new stream over `inner`. If `encrypt` is true then
+        /// records are encrypted as well as integrity protected.
+        pub fn new(ctx: C, inner: S, encrypt: bool) -> Self {
+            AsyncGssStream {
+                records: Records::new(ctx, encrypt),
+                inner,
+            }
+        }
+
+        /// Limit wrap tokens to `size` bytes in both directions,
+        /// larger records from the peer are an error. The default is
+        /// to send tokens of up to 64KiB and accept up to 16MiB.
+        #[must_use]
+        pub fn max_token_size(mut self, size: usize) -> Self {
+            self.records.framing.set_max_token_size(size);
+            self
+        }
+
+        /// Get a reference to the security context
+        pub fn context(&self) -> &C {
+            &self.records.ctx
+        }
+
+        /// Get a mutable reference to the security context
+        pub fn context_mut(&mut self) -> &mut C {
+            &mut self.records.ctx
+        }
+
+        /// Get a reference to the underlying transport
+        pub fn get_ref(&self) -> &S {
+            &self.inner
+        }
+
+        /// Get a mutable reference to the underlying transport. Reading
+        /// or writing it directly will corrupt the stream.
+        pub fn get_mut(&mut self) -> &mut S {
+            &mut self.inner
+        }
+
+        /// Return the security context and the transport. Any data
+        /// that is buffered in either direction is lost.
+        pub fn into_inner(self) -> (C, S) {
+            (self.records.ctx, self.inner)
+        }
+    }
+
+    impl<C: SecurityContext + Unpin, S: AsyncRead + Unpin> AsyncRead
+        for AsyncGssStream<C, S>
+    {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let AsyncGssStream { records, inner } = self.get_mut();
+            let read = |b: &mut [u8]| {
+                let mut b = ReadBuf::new(b);
+                ready!(Pin::new(&mut *inner).poll_read(cx, &mut b))?;
+                Poll::Ready(Ok(b.filled().len()))
+            };
+            let n = ready!(records.poll_read(read, buf.initialize_unfilled()))?;
+            buf.advance(n);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<C: SecurityContext + Unpin, S: AsyncWrite + Unpin> AsyncWrite
+        for AsyncGssStream<C, S>
+    {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let AsyncGssStream { records, inner } = self.get_mut();
+            records.poll_write(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b), buf)
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            let AsyncGssStream { records, inner } = self.get_mut();
+            ready!(
+                records.poll_drain(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b))
+            )?;
+            Pin::new(inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            let AsyncGssStream { records, inner } = self.get_mut();
+            ready!(
+                records.poll_drain(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b))
+            )?;
+            Pin::new(inner).poll_shutdown(cx)
+        }
+    }
+
+    /// The same as `WrapWriter`, but over a tokio writer. Each write
+    /// wraps the whole buffer and holds on to the records until the
+    /// writer takes them, they are finished by the next write, flush
+    /// or shutdown.
+    #[derive(Debug)]
+    pub struct AsyncWrapWriter<C, W> {
+        records: Records<C>,
+        inner: W,
+    }
+
+    impl<C: SecurityContext, W> AsyncWrapWriter<C, W> {
+        /// Create a new writer over `inner`. If `encrypt` is true then
+        /// records are encrypted as well as integrity protected.
+        pub fn new(ctx: C, inner: W, encrypt: bool) -> Self {
+            AsyncWrapWriter {
+                records: Records::new(ctx, encrypt),
+                inner,
+            }
+        }
+
+        /// Limit wrap tokens to `size` bytes. The default is 64KiB.
+        #[must_use]
+        pub fn max_token_size(mut self, size: usize) -> Self {
+            self.records.framing.set_max_token_size(size);
+            self
+        }
+
+        /// Get a reference to the security context
+        pub fn context(&self) -> &C {
+            &self.records.ctx
+        }
+
+        /// Get a mutable reference to the security context
+        pub fn context_mut(&mut self) -> &mut C {
+            &mut self.records.ctx
+        }
+
+        /// Get a reference to the underlying writer
+        pub fn get_ref(&self) -> &W {
+            &self.inner
+        }
+
+        /// Get a mutable reference to the underlying writer. Writing
+        /// to it directly will corrupt the stream.
+        pub fn get_mut(&mut self) -> &mut W {
+            &mut self.inner
+        }
+
+        /// Return the security context and the writer. Any records
+        /// not yet sent are lost.
+        pub fn into_inner(self) -> (C, W) {
+            (self.records.ctx, self.inner)
+        }
+    }
+
+    impl<C: SecurityContext + Unpin, W: AsyncWrite + Unpin> AsyncWrite
+        for AsyncWrapWriter<C, W>
+    {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let AsyncWrapWriter { records, inner } = self.get_mut();
+            records.poll_write_all(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b), buf)
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            let AsyncWrapWriter { records, inner } = self.get_mut();
+            ready!(
+                records.poll_drain(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b))
+            )?;
+            Pin::new(inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            let AsyncWrapWriter { records, inner } = self.get_mut();
+            ready!(
+                records.poll_drain(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b))
+            )?;
+            Pin::new(inner).poll_shutdown(cx)
+        }
+    }
+
+    /// A tokio-util codec that frames messages the same way as
+    /// `GssStream`, for message oriented protocols. The encoder wraps
+    /// each message into a record, and the decoder unwraps each
+    /// record back into a message.
+    #[derive(Debug)]
+    pub struct GssCodec<C> {
+        ctx: C,
+        framing: Framing,
+        strict: bool,
+        split: bool,
+    }
+
+    impl<C: SecurityContext> GssCodec<C> {
+        /// Create a new codec using `ctx`. If `encrypt` is true then
+        /// records are encrypted as well as integrity protected.
+        pub fn new(ctx: C, encrypt: bool) -> Self {
+            GssCodec {
+                ctx,
+                framing: Framing::new(encrypt),
+                strict: true,
+                split: false,
+            }
+        }
+
+        /// If `strict` is true (the default) then a record that the
+        /// mechanism reports as duplicated, old, out of order, or
+        /// following a gap is a decode error. Otherwise such records
+        /// are returned like any other.
+        #[must_use]
+        pub fn strict(mut self, strict: bool) -> Self {
+            self.strict = strict;
+            self
+        }
+
+        /// A message too big to wrap into a single record is an
+        /// encode error unless `split` is true, in which case it is
+        /// sent as several records. The peer will decode each of
+        /// those as a separate message.
+        #[must_use]
+        pub fn split(mut self, split: bool) -> Self {
+            self.split = split;
+            self
+        }
+
+        /// Limit wrap tokens to `size` bytes in both directions,
+        /// larger records from the peer are an error. The default is
+        /// to send tokens of up to 64KiB and accept up to 16MiB.
+        #[must_use]
+        pub fn max_token_size(mut self, size: usize) -> Self {
+            self.framing.set_max_token_size(size);
+            self
+        }
+
+        /// Get a reference to the security context
+        pub fn context(&self) -> &C {
+            &self.ctx
+        }
+
+        /// Get a mutable reference to the security context
+        pub fn context_mut(&mut self) -> &mut C {
+            &mut self.ctx
+        }
+
+        /// Return the security context
+        pub fn into_inner(self) -> C {
+            self.ctx
+        }
+    }
+
+    impl<C: SecurityContext> Decoder for GssCodec<C> {
+        type Item = Bytes;
+        type Error = io::Error;
+
+        fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Bytes>> {
+            if src.len() < 4 {
+                return Ok(None);
+            }
+            let len = self.framing.record_len(src[..4].try_into().unwrap())?;
+            if src.len() < 4 + len {
+                src.reserve(4 + len - src.len());
+                return Ok(None);
+            }
+            src.advance(4);
+            let tok = src.split_to(len);
+            let msg = if self.strict {
+                unwrap_in_place(&mut self.ctx, tok)
+            } else {
+                self.ctx.unwrap_with_status(&tok).map(|(msg, _)| Bytes::from(msg))
+            };
+            Ok(Some(msg.map_err(to_io)?))
+        }
+    }
+
+    /// Decrypt the record where it is, and split the message off it
+    #[cfg(feature = "iov")]
+    fn unwrap_in_place<C: SecurityContext>(
+        ctx: &mut C,
+        mut tok: BytesMut,
+    ) -> Result<Bytes, Error> {
+        let (header, len) = {
+            let mut iov = [
+                GssIov::new(GssIovType::Stream, &mut tok),
+                GssIov::new(GssIovType::Data, &mut []),
+            ];
+            ctx.unwrap_iov(&mut iov)?;
+            (iov[0].header_length(&iov[1]).unwrap(), iov[1].len())
+        };
+        tok.advance(header);
+        tok.truncate(len);
+        Ok(tok.freeze())
+    }
+
+    // gssapi allocates the message, Bytes takes ownership of it
+    #[cfg(not(feature = "iov"))]
+    fn unwrap_in_place<C: SecurityContext>(
+        ctx: &mut C,
+        tok: BytesMut,
+    ) -> Result<Bytes, Error> {
+        ctx.unwrap(&tok).map(Bytes::from)
+    }
+
+    impl<C: SecurityContext> Encoder<Bytes> for GssCodec<C> {
+        type Error = io::Error;
+
+        fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> io::Result<()> {
+            let max_input = self.framing.max_input(&mut self.ctx)?;
+            if !self.split && item.len() > max_input {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "message is bigger than the wrap size limit",
+                ));
+            }
+            let mut msg = &item[..];
+            loop {
+                let (n, record) = self.framing.wrap_record(&mut self.ctx, msg)?;
+                dst.extend_from_slice(&record);
+                msg = &msg[n..];
+                if msg.is_empty() {
+                    break Ok(());
+                }
+            }
+        }
+    }
+}
+#[cfg(feature = "tokio")]
+pub use async_tokio::*;
+
+#[cfg(feature = "futures-io")]
+mod async_futures {
+    use super::{records::Records, *};
+    use futures_io::{AsyncRead, AsyncWrite};
+    use std::{
+        pin::Pin,
+        task::{ready, Context, Poll},
+    };
+
+    /// The same as `GssStream`, but over a `futures-io` transport,
+    /// for executors other than tokio (e.g. smol, async-std).
+    ///
+    /// A wrapped record that hasn't been completely sent yet is
+    /// finished by the next write, flush or close.
+    ///
+    /// Like `AsyncGssStream` no context deletion token is sent on
+    /// close, the peer sees the end of the stream.
+    #[derive(Debug)]
+    pub struct FuturesGssStream<C, S> {
+        records: Records<C>,
+        inner: S,
+    }
+
+    impl<C: SecurityContext, S> FuturesGssStream<C, S> {
+        /// Create a new stream over `inner`. If `encrypt` is true then
+        /// records are encrypted as well as integrity protected.
+        pub fn new(ctx: C, inner: S, encrypt: bool) -> Self {
+            FuturesGssStream {
+                records: Records::new(ctx, encrypt),
+                inner,
+            }
+        }
+
+        /// Limit wrap tokens to `size` bytes in both directions,
+        /// larger records from the peer are an error. The default is
+        /// to send tokens of up to 64KiB and accept up to 16MiB.
+        #[must_use]
+        pub fn max_token_size(mut self, size: usize) -> Self {
+            self.records.framing.set_max_token_size(size);
+            self
+        }
+
+        /// Get a reference to the security context
+        pub fn context(&self) -> &C {
+            &self.records.ctx
+        }
+
+        /// Get a mutable reference to the security context
+        pub fn context_mut(&mut self) -> &mut C {
+            &mut self.records.ctx
+        }
+
+        /// Get a reference to the underlying transport
+        pub fn get_ref(&self) -> &S {
+            &self.inner
+        }
+
+        /// Get a mutable reference to the underlying transport. Reading
+        /// or writing it directly will corrupt the stream.
+        pub fn get_mut(&mut self) -> &mut S {
+            &mut self.inner
+        }
+
+        /// Return the security context and the transport. Any data
+        /// that is buffered in either direction is lost.
+        pub fn into_inner(self) -> (C, S) {
+            (self.records.ctx, self.inner)
+        }
+    }
+
+    impl<C: SecurityContext + Unpin, S: AsyncRead + Unpin> AsyncRead
+        for FuturesGssStream<C, S>
+    {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let FuturesGssStream { records, inner } = self.get_mut();
+            records.poll_read(|b: &mut [u8]| Pin::new(&mut *inner).poll_read(cx, b), buf)
+        }
+    }
+
+    impl<C: SecurityContext + Unpin, S: AsyncWrite + Unpin> AsyncWrite
+        for FuturesGssStream<C, S>
+    {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let FuturesGssStream { records, inner } = self.get_mut();
+            records.poll_write(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b), buf)
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            let FuturesGssStream { records, inner } = self.get_mut();
+            ready!(
+                records.poll_drain(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b))
+            )?;
+            Pin::new(inner).poll_flush(cx)
+        }
+
+        fn poll_close(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<io::Result<()>> {
+            let FuturesGssStream { records, inner } = self.get_mut();
+            ready!(
+                records.poll_drain(|b: &[u8]| Pin::new(&mut *inner).poll_write(cx, b))
+            )?;
+            Pin::new(inner).poll_close(cx)
+        }
+    }
+}
+#[cfg(feature = "futures-io")]
+pub use async_futures::*;
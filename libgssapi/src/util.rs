@@ -1,12 +1,11 @@
-use bytes;
+use crate::error::{Error, MajorFlags};
 use libgssapi_sys::{
-    gss_buffer_desc, gss_buffer_desc_struct, gss_buffer_t, gss_release_buffer, OM_uint32,
-    GSS_S_COMPLETE,
+    gss_add_buffer_set_member, gss_buffer_desc, gss_buffer_desc_struct, gss_buffer_set_t,
+    gss_buffer_t, gss_create_empty_buffer_set, gss_release_buffer, gss_release_buffer_set,
+    OM_uint32, GSS_S_COMPLETE,
 };
-#[cfg(feature = "s4u")]
-use libgssapi_sys::{gss_buffer_set_t, gss_release_buffer_set};
 use std::{
-    ffi,
+    ffi, fmt, mem,
     marker::PhantomData,
     ops::{Deref, DerefMut, Drop},
     ptr, slice,
@@ -83,6 +82,16 @@ mod iov {
             GssIovFake(gss_iov)
         }
 
+        /// Create a fake Iov with a length, used to tell
+        /// wrap_iov_length how much data you will wrap (the padding
+        /// needed may depend on it). It still doesn't contain any
+        /// memory.
+        pub fn with_len(typ: GssIovType, len: usize) -> GssIovFake {
+            let mut iov = GssIovFake::new(typ);
+            iov.0.buffer.length = len as _;
+            iov
+        }
+
         pub fn len(&self) -> usize {
             self.0.buffer.length as usize
         }
@@ -216,6 +225,12 @@ impl<'a> From<&'a [u8]> for BufRef<'a> {
     }
 }
 
+impl<'a> From<&'a str> for BufRef<'a> {
+    fn from(s: &'a str) -> Self {
+        BufRef::from(s.as_bytes())
+    }
+}
+
 impl<'a> BufRef<'a> {
     pub(crate) unsafe fn to_c(&mut self) -> gss_buffer_t {
         &mut self.0 as gss_buffer_t
@@ -226,23 +241,48 @@ impl<'a> BufRef<'a> {
 /// deallocated via the library routine when it is dropped.
 #[repr(transparent)]
 #[allow(dead_code)]
-#[derive(Debug)]
 pub struct Buf(gss_buffer_desc);
 
 unsafe impl Send for Buf {}
 unsafe impl Sync for Buf {}
 
+impl fmt::Debug for Buf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Buf([")?;
+        for b in self.iter() {
+            write!(f, "{:02x}", b)?;
+        }
+        write!(f, "], len={})", self.len())
+    }
+}
+
 impl Deref for Buf {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        unsafe { slice::from_raw_parts(self.0.value.cast(), self.0.length as usize) }
+        if self.0.value.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.0.value.cast(), self.0.length as usize) }
+        }
+    }
+}
+
+impl AsRef<[u8]> for Buf {
+    fn as_ref(&self) -> &[u8] {
+        self
     }
 }
 
 impl DerefMut for Buf {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { slice::from_raw_parts_mut(self.0.value.cast(), self.0.length as usize) }
+        if self.0.value.is_null() {
+            &mut []
+        } else {
+            unsafe {
+                slice::from_raw_parts_mut(self.0.value.cast(), self.0.length as usize)
+            }
+        }
     }
 }
 
@@ -271,108 +311,269 @@ impl Buf {
     pub(crate) unsafe fn to_c(&mut self) -> gss_buffer_t {
         &mut self.0 as gss_buffer_t
     }
+}
+
+#[cfg(feature = "bytes")]
+mod gss_bytes {
+    use super::*;
 
-    /// Wrap this bytes in a structure that implements `bytes::Buf`
-    pub fn to_bytes(self) -> GssBytes {
-        GssBytes { pos: 0, buf: self }
+    impl Buf {
+        /// Wrap this bytes in a structure that implements `bytes::Buf`
+        pub fn to_bytes(self) -> GssBytes {
+            GssBytes { pos: 0, buf: self }
+        }
     }
-}
 
-#[derive(Debug)]
-pub struct GssBytes {
-    pos: usize,
-    buf: Buf,
-}
+    /// Convert the buffer to `Bytes` without copying it. The gssapi
+    /// buffer is released when the last clone of the `Bytes` is
+    /// dropped.
+    impl From<Buf> for bytes::Bytes {
+        fn from(buf: Buf) -> Self {
+            bytes::Bytes::from_owner(buf)
+        }
+    }
 
-impl bytes::Buf for GssBytes {
-    fn remaining(&self) -> usize {
-        self.buf.0.length as usize - self.pos
+    #[derive(Debug)]
+    pub struct GssBytes {
+        pos: usize,
+        buf: Buf,
     }
 
-    fn chunk(&self) -> &[u8] {
-        &((*self.buf)[self.pos..])
+    impl bytes::Buf for GssBytes {
+        fn remaining(&self) -> usize {
+            self.buf.0.length as usize - self.pos
+        }
+
+        fn chunk(&self) -> &[u8] {
+            &((*self.buf)[self.pos..])
+        }
+
+        fn advance(&mut self, cnt: usize) {
+            let rem = self.remaining();
+            if cnt > rem {
+                panic!(
+                    "advancing {} would overrun the remaining buffer {}",
+                    cnt, rem
+                );
+            } else {
+                self.pos += cnt;
+            }
+        }
     }
 
-    fn advance(&mut self, cnt: usize) {
-        let rem = self.remaining();
-        if cnt > rem {
-            panic!(
-                "advancing {} would overrun the remaining buffer {}",
-                cnt, rem
-            );
-        } else {
-            self.pos += cnt;
+    impl GssBytes {
+        /// Consume the GssBytes and return the inner buffer
+        pub fn into_inner(self) -> Buf {
+            self.buf
         }
     }
 }
 
-impl GssBytes {
-    /// Consume the GssBytes and return the inner buffer
-    pub fn into_inner(self) -> Buf {
-        self.buf
+#[cfg(feature = "bytes")]
+pub use gss_bytes::*;
+
+#[cfg(feature = "base64")]
+mod b64 {
+    use super::*;
+    use crate::error::{Error, MajorFlags};
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    /// Encode a token as standard base64, the form used to carry
+    /// tokens in HTTP `Negotiate` headers.
+    pub fn encode_base64(tok: &[u8]) -> String {
+        STANDARD.encode(tok)
+    }
+
+    /// Decode a standard base64 encoded token. Invalid input is
+    /// reported as `GSS_S_DEFECTIVE_TOKEN` rather than causing a
+    /// panic.
+    pub fn decode_base64(s: &str) -> Result<Vec<u8>, Error> {
+        STANDARD.decode(s).map_err(|_| Error {
+            major: MajorFlags::GSS_S_DEFECTIVE_TOKEN,
+            minor: 0,
+        })
+    }
+
+    impl Buf {
+        /// Encode the contents of this buffer as standard base64
+        pub fn to_base64(&self) -> String {
+            encode_base64(self)
+        }
     }
 }
 
-#[cfg(feature = "s4u")]
-mod s4u {
+#[cfg(feature = "base64")]
+pub use b64::*;
+
+#[cfg(feature = "zeroize")]
+mod zero {
     use super::*;
+    use zeroize::Zeroize;
 
-    /// This represents an owned buffer set we got from gssapi, it will be
-    /// deallocated via the library routine when it is dropped.
-    #[repr(transparent)]
-    #[allow(dead_code)]
-    #[derive(Debug)]
-    pub(crate) struct BufSet<'a>(gss_buffer_set_t, PhantomData<&'a [BufRef<'a>]>);
+    /// A `Buf` holding secrets, e.g. key material, that is zeroed
+    /// before it is released
+    pub struct SafeBuf(Buf);
 
-    unsafe impl Send for BufSet<'_> {}
-    unsafe impl Sync for BufSet<'_> {}
+    impl From<Buf> for SafeBuf {
+        fn from(buf: Buf) -> Self {
+            SafeBuf(buf)
+        }
+    }
 
-    impl<'a> Deref for BufSet<'a> {
-        type Target = [BufRef<'a>];
+    impl fmt::Debug for SafeBuf {
+        fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+            write!(f, "SafeBuf(len={})", self.0.len())
+        }
+    }
 
-        fn deref(&self) -> &'a Self::Target {
-            if self.0.is_null() {
-                &[]
-            } else {
-                unsafe { slice::from_raw_parts((*self.0).elements.cast(), (*self.0).count as usize) }
-            }
+    impl Deref for SafeBuf {
+        type Target = [u8];
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
         }
     }
 
-    impl<'a> DerefMut for BufSet<'a> {
-        fn deref_mut(&mut self) -> &'a mut Self::Target {
-            if self.0.is_null() {
-                &mut []
-            } else {
-                unsafe { slice::from_raw_parts_mut((*self.0).elements.cast(), (*self.0).count as usize) }
-            }
+    impl AsRef<[u8]> for SafeBuf {
+        fn as_ref(&self) -> &[u8] {
+            &self.0
         }
     }
 
-    impl Drop for BufSet<'_> {
-        fn drop(&mut self) {
-            if !self.0.is_null() {
-                let mut minor = GSS_S_COMPLETE;
-                let _major = unsafe {
-                    gss_release_buffer_set(
-                        &mut minor as *mut OM_uint32,
-                        &mut self.0,
-                    )
-                };
-            }
+    impl Zeroize for SafeBuf {
+        fn zeroize(&mut self) {
+            self.0.zeroize()
         }
     }
 
-    impl BufSet<'_> {
-        pub(crate) fn empty() -> Self {
-            Self(ptr::null_mut(), PhantomData)
+    impl Drop for SafeBuf {
+        fn drop(&mut self) {
+            // the inner Buf is released after this
+            self.zeroize()
         }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+pub use zero::*;
+
+/// A set of buffers, which the gssapi extensions that deal in more
+/// than one buffer take or return, e.g. `gss_inquire_sec_context_by_oid`
+/// or `gss_get_name_attribute`. It is allocated by gssapi and released
+/// when it is dropped.
+pub struct BufSet(gss_buffer_set_t);
+
+unsafe impl Send for BufSet {}
+unsafe impl Sync for BufSet {}
 
-        pub(crate) unsafe fn to_c(&mut self) -> &mut gss_buffer_set_t {
-            &mut self.0
+impl Drop for BufSet {
+    fn drop(&mut self) {
+        if !self.0.is_null() {
+            let mut minor = GSS_S_COMPLETE;
+            let _major = unsafe {
+                gss_release_buffer_set(&mut minor as *mut OM_uint32, &mut self.0)
+            };
         }
     }
 }
 
-#[cfg(feature = "s4u")]
-pub(crate) use s4u::*;
+impl fmt::Debug for BufSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl BufSet {
+    /// Create a new empty buffer set
+    pub fn new() -> Result<BufSet, Error> {
+        let mut set = BufSet::empty();
+        let mut minor = GSS_S_COMPLETE;
+        let major = unsafe {
+            gss_create_empty_buffer_set(&mut minor as *mut OM_uint32, &mut set.0)
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(set)
+        } else {
+            Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            })
+        }
+    }
+
+    pub(crate) fn empty() -> Self {
+        BufSet(ptr::null_mut())
+    }
+
+    pub(crate) unsafe fn to_c(&mut self) -> &mut gss_buffer_set_t {
+        &mut self.0
+    }
+
+    /// Copy `buf` to the end of the set
+    pub fn add(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut buf = BufRef::from(buf);
+        let mut minor = GSS_S_COMPLETE;
+        let major = unsafe {
+            gss_add_buffer_set_member(&mut minor as *mut OM_uint32, buf.to_c(), &mut self.0)
+        };
+        if major == GSS_S_COMPLETE {
+            Ok(())
+        } else {
+            Err(Error {
+                major: MajorFlags::from_bits_retain(major),
+                minor,
+            })
+        }
+    }
+
+    /// Return the number of buffers in the set
+    pub fn len(&self) -> usize {
+        if self.0.is_null() {
+            0
+        } else {
+            unsafe { (*self.0).count }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the buffer at `idx`, or `None` if it's out of bounds
+    pub fn get(&self, idx: usize) -> Option<&[u8]> {
+        if idx >= self.len() {
+            None
+        } else {
+            unsafe {
+                let buf = &*(*self.0).elements.add(idx);
+                if buf.value.is_null() {
+                    Some(&[])
+                } else {
+                    Some(slice::from_raw_parts(buf.value.cast(), buf.length))
+                }
+            }
+        }
+    }
+
+    /// Iterate over the buffers in the set
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+
+    /// Move the buffers out of the set without copying them
+    pub fn into_bufs(self) -> Vec<Buf> {
+        (0..self.len())
+            .map(|i| unsafe {
+                // leave an empty buffer behind for gss_release_buffer_set
+                let elt = &mut *(*self.0).elements.add(i);
+                Buf(mem::replace(
+                    elt,
+                    gss_buffer_desc {
+                        length: 0,
+                        value: ptr::null_mut(),
+                    },
+                ))
+            })
+            .collect()
+    }
+}
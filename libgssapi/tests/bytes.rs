@@ -0,0 +1,35 @@
+#![cfg(feature = "bytes")]
+mod common;
+
+use bytes::{Buf as _, Bytes};
+use common::established;
+use libgssapi::context::SecurityContext;
+
+#[test]
+fn bytes_outlives_buf() {
+    let (mut client, mut server) = established();
+    let tok = client.wrap(true, b"super secret message").unwrap();
+    let expected = tok.to_vec();
+    let bytes = Bytes::from(tok);
+    let clone = bytes.clone();
+    // releases nothing, the clone still holds the gssapi buffer
+    drop(bytes);
+    assert_eq!(&clone[..], &expected[..]);
+    let slice = clone.slice(4..);
+    drop(clone);
+    assert_eq!(&slice[..], &expected[4..]);
+    let clone = Bytes::from(client.wrap(true, b"another message").unwrap());
+    assert_eq!(&*server.unwrap(&clone).unwrap(), b"another message");
+}
+
+#[test]
+fn gss_bytes() {
+    let (mut client, mut server) = established();
+    let tok = client.wrap(true, b"super secret message").unwrap();
+    let expected = tok.to_vec();
+    let mut b = tok.to_bytes();
+    assert_eq!(b.remaining(), expected.len());
+    b.advance(4);
+    assert_eq!(b.chunk(), &expected[4..]);
+    assert_eq!(&*server.unwrap(&b.into_inner()).unwrap(), b"super secret message");
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "tls-server-end-point")]
+//! The certificates in `tests/data/certs` are self signed, made with
+//! `openssl req -x509`, and the expected digests with `openssl dgst`.
+use libgssapi::channel_binding::{tls_server_end_point, CertDigest, TLS_SERVER_END_POINT};
+
+fn cert(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/data/certs/{}.der", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read(path).unwrap()
+}
+
+fn hex(b: &[u8]) -> String {
+    b.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn check(name: &str, digest: CertDigest, expected: &str) {
+    let cert = cert(name);
+    assert_eq!(CertDigest::for_cert(&cert).unwrap(), digest);
+    let cb = tls_server_end_point(&cert).unwrap();
+    assert_eq!(&cb[..TLS_SERVER_END_POINT.len()], TLS_SERVER_END_POINT);
+    assert_eq!(hex(&cb[TLS_SERVER_END_POINT.len()..]), expected);
+}
+
+#[test]
+fn rsa_sha1() {
+    // SHA-1 is upgraded to SHA-256
+    check(
+        "rsa-sha1",
+        CertDigest::Sha256,
+        "7b7b6b6b88a24e6dd90aa51f46f3852d2692ba7bc7df0ac8bf4dd37a31de65ab",
+    );
+}
+
+#[test]
+fn rsa_sha256() {
+    check(
+        "rsa-sha256",
+        CertDigest::Sha256,
+        "40ad5108be56a9fe82942472e1d8fdadcd53fea7cb288bdabb5535eb8f8a9299",
+    );
+}
+
+#[test]
+fn rsa_sha384() {
+    check(
+        "rsa-sha384",
+        CertDigest::Sha384,
+        "fed8ac0fe6ef5e12355b0f3f122e02d88cd15711cef19810974ac6d875f355de\
+         1ad70e309bf83437199558aebc1a8871",
+    );
+}
+
+#[test]
+fn ecdsa_sha256() {
+    check(
+        "ecdsa-sha256",
+        CertDigest::Sha256,
+        "0bafa1da811592ccefcc55232aac7a947a673d63c55109e77668a272134475d6",
+    );
+}
+
+#[test]
+fn ecdsa_sha384() {
+    check(
+        "ecdsa-sha384",
+        CertDigest::Sha384,
+        "9dbae71a480290800ab74814593824002ae9cf377358a99728547914ca6f5be4\
+         7166ccb6cf70189d1bd88e031dca7c4d",
+    );
+}
+
+#[test]
+fn malformed() {
+    let mut cert = cert("rsa-sha256");
+    cert.truncate(100);
+    assert!(tls_server_end_point(&cert).is_err());
+}
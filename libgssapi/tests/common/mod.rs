@@ -0,0 +1,74 @@
+//! A krb5 environment for tests that needs no KDC, a keytab for
+//! `HTTP/host.example.com@EXAMPLE.COM` and a ccache for
+//! `user@EXAMPLE.COM` that already holds a ticket for it, see
+//! `tests/data/gen_krb5.py`, and a krb5.conf that makes EXAMPLE.COM
+//! the default realm and the realm of hosts in example.com. This only
+//! works with MIT.
+#![allow(dead_code)]
+use libgssapi::{
+    context::{ClientCtx, CtxFlags, ServerCtx, ServerCtxBuilder},
+    credential::{Cred, CredUsage},
+    error::Error,
+    name::Name,
+    oid::{GSS_MECH_KRB5, GSS_NT_KRB5_PRINCIPAL},
+};
+use std::{env, sync::Once};
+
+pub const SERVICE: &[u8] = b"HTTP/host.example.com@EXAMPLE.COM";
+
+/// Point the kerberos library at the test keytab and ccache, call
+/// this before touching gssapi.
+pub fn krb5_env() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let data = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data");
+        env::set_var("KRB5_CONFIG", format!("{}/krb5.conf", data));
+        env::set_var("KRB5CCNAME", format!("FILE:{}/krb5cc", data));
+        env::set_var("KRB5_KTNAME", format!("FILE:{}/krb5.keytab", data));
+        env::set_var("KRB5RCACHETYPE", "none");
+    })
+}
+
+pub fn service_name() -> Name {
+    krb5_env();
+    Name::new(SERVICE, Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap()
+}
+
+pub fn server_builder() -> ServerCtxBuilder {
+    let name = service_name();
+    let cred = Cred::acquire(Some(&name), None, CredUsage::Accept, None).unwrap();
+    ServerCtxBuilder::new(Some(cred))
+}
+
+pub fn client() -> ClientCtx {
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG;
+    ClientCtx::new(None, service_name(), flags, Some(&GSS_MECH_KRB5))
+}
+
+/// Step both sides until the context is established, the client
+/// passes `channel_bindings`.
+pub fn handshake_with(
+    client: &mut ClientCtx,
+    server: &mut ServerCtx,
+    channel_bindings: Option<&[u8]>,
+) -> Result<(), Error> {
+    let mut server_tok: Option<Vec<u8>> = None;
+    loop {
+        let tok = match client.step(server_tok.as_deref(), channel_bindings)? {
+            None => return Ok(()),
+            Some(tok) => tok,
+        };
+        match server.step(&tok)? {
+            None => return Ok(()),
+            Some(tok) => server_tok = Some(tok.to_vec()),
+        }
+    }
+}
+
+/// An established pair of contexts
+pub fn established() -> (ClientCtx, ServerCtx) {
+    let mut client = client();
+    let mut server = server_builder().build();
+    handshake_with(&mut client, &mut server, None).unwrap();
+    (client, server)
+}
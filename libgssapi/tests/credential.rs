@@ -0,0 +1,122 @@
+mod common;
+
+use libgssapi::{
+    credential::{Cred, CredUsage},
+    oid::{OidSet, GSS_MECH_KRB5, GSS_MECH_SPNEGO},
+};
+
+fn krb5_cred() -> Cred {
+    let name = common::service_name();
+    let mut mechs = OidSet::new().unwrap();
+    mechs.add(&GSS_MECH_KRB5).unwrap();
+    let cred =
+        Cred::acquire(Some(&name), None, CredUsage::Accept, Some(&mechs)).unwrap();
+    let mechs = cred.mechanisms().unwrap();
+    assert!(mechs.contains(&GSS_MECH_KRB5).unwrap());
+    assert!(!mechs.contains(&GSS_MECH_SPNEGO).unwrap());
+    cred
+}
+
+fn assert_both_mechs(cred: &Cred) {
+    let mechs = cred.info().unwrap().mechanisms;
+    assert!(mechs.contains(&GSS_MECH_KRB5).unwrap());
+    assert!(mechs.contains(&GSS_MECH_SPNEGO).unwrap());
+}
+
+#[test]
+fn add() {
+    let cred = krb5_cred();
+    let name = common::service_name();
+    let (added, lifetimes) = cred
+        .add(Some(&name), &GSS_MECH_SPNEGO, CredUsage::Accept, None, None)
+        .unwrap();
+    assert_both_mechs(&added);
+    assert_eq!(lifetimes.accept, None);
+    // the original is left alone
+    assert!(!cred.mechanisms().unwrap().contains(&GSS_MECH_SPNEGO).unwrap());
+}
+
+#[test]
+fn add_in_place() {
+    let mut cred = krb5_cred();
+    let name = common::service_name();
+    cred.add_in_place(Some(&name), &GSS_MECH_SPNEGO, CredUsage::Accept, None, None)
+        .unwrap();
+    assert_both_mechs(&cred);
+}
+
+/// MIT ignores `time_req` for tickets that are already in the ccache,
+/// it only limits the ones it gets from a KDC, so a short `time_req`
+/// can't make the credential expire here. This uses a ccache whose
+/// ticket has already expired instead.
+#[cfg(feature = "mit_kerberos")]
+#[test]
+fn expired() {
+    use libgssapi::{
+        context::{ClientCtx, CtxFlags},
+        credential::CredStore,
+        error::WellKnownError,
+    };
+    use std::time::Duration;
+    common::krb5_env();
+    let short = Some(Duration::from_secs(1));
+    let cred = Cred::acquire(None, short, CredUsage::Initiate, None).unwrap();
+    assert!(cred.remaining_lifetime().unwrap() > short);
+    assert!(!cred.is_expired().unwrap());
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/krb5cc_expired");
+    let store = CredStore::ccache(&format!("FILE:{}", path));
+    let cred = Cred::acquire_from(None, &store, CredUsage::Initiate).unwrap();
+    let e = cred.remaining_lifetime().unwrap_err();
+    assert!(e.is_credentials_expired() && !e.is_context_expired());
+    assert_eq!(e.kind(), Some(WellKnownError::CredentialsExpired));
+    assert!(cred.is_expired().unwrap());
+    // and it can't be used, without a KDC to get a new ticket from
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG;
+    let mut ctx = ClientCtx::new(Some(cred), common::service_name(), flags, None);
+    assert!(ctx.step(None, None).is_err());
+}
+
+/// The copy is a working credential of its own, but the options set
+/// on the original don't come with it
+#[cfg(feature = "mit_kerberos")]
+#[test]
+fn deep_clone() {
+    use common::{handshake_with, server_builder, service_name};
+    use libgssapi::{
+        context::{ClientCtx, CtxFlags, SecurityContext},
+        oid::GSS_KRB5_CRED_NO_CI_FLAGS_X,
+    };
+    common::krb5_env();
+    let mut cred = Cred::acquire(None, None, CredUsage::Initiate, None).unwrap();
+    cred.set_option(&GSS_KRB5_CRED_NO_CI_FLAGS_X, None).unwrap();
+    let copy = cred.deep_clone().unwrap();
+    assert!(copy.name().unwrap().equal(&cred.name().unwrap()).unwrap());
+    let integ = |cred: Cred| {
+        let flags = CtxFlags::GSS_C_MUTUAL_FLAG;
+        let mech = Some(&GSS_MECH_KRB5);
+        let mut client = ClientCtx::new(Some(cred), service_name(), flags, mech);
+        let mut server = server_builder().build();
+        handshake_with(&mut client, &mut server, None).unwrap();
+        client.flags().unwrap().contains(CtxFlags::GSS_C_INTEG_FLAG)
+    };
+    assert!(integ(copy));
+    assert!(!integ(cred));
+}
+
+/// The guard puts back the previous ccache name byte for byte, even
+/// when it isn't utf8
+#[cfg(feature = "mit_kerberos")]
+#[test]
+fn ccache_guard_restores_raw_name() {
+    use libgssapi::credential::{set_ccache_name, GssCcacheGuard};
+    use std::ffi::CStr;
+    let name = CStr::from_bytes_with_nul(b"FILE:/tmp/krb5cc_caf\xe9\0").unwrap();
+    set_ccache_name(Some(name)).unwrap();
+    {
+        let _guard = GssCcacheGuard::new("MEMORY:guarded").unwrap();
+        let current = set_ccache_name(Some(name)).unwrap().unwrap();
+        assert_eq!(current.as_bytes(), b"MEMORY:guarded");
+        set_ccache_name(Some(&current)).unwrap();
+    }
+    assert_eq!(set_ccache_name(None).unwrap().as_deref(), Some(name));
+}
@@ -0,0 +1,171 @@
+# Generates krb5.keytab and krb5cc, a keytab for HTTP/host.example.com
+# and a ccache holding a ticket for it, so tests can run a krb5
+# handshake without a KDC. The ticket expires at the end of 2037.
+# krb5cc_expired holds the same ticket but it expired the day after
+# it was issued, for testing the expired credential paths, and
+# krb5cc_anonymous holds an anonymous ticket for it, as anonymous
+# PKINIT would have issued, and krb5cc_pac a ticket carrying an MS-PAC,
+# as Active Directory would have issued.
+#
+#   python3 gen_krb5.py tests/data
+import datetime, hashlib, hmac, struct, sys
+from cryptography.hazmat.primitives.ciphers import Cipher, algorithms, modes
+
+REALM=b"EXAMPLE.COM"
+SVC=[b"HTTP", b"host.example.com"]
+CLI=[b"user"]
+SKEY=bytes(range(0x20, 0x40))      # service long term key, aes256
+SESS=bytes(range(0x40, 0x60))      # session key
+ETYPE=18
+
+def aes_ecb(k, b):
+    e=Cipher(algorithms.AES(k), modes.ECB()).encryptor()
+    return e.update(b)+e.finalize()
+def aes_cbc(k, b):
+    e=Cipher(algorithms.AES(k), modes.CBC(bytes(16))).encryptor()
+    return e.update(b)+e.finalize()
+
+def nfold(inp, outlen):
+    # RFC 3961 n-fold, outlen in bytes
+    def rot13(b, n):
+        bits = len(b) * 8
+        v = int.from_bytes(b, 'big')
+        n %= bits
+        v = ((v >> n) | (v << (bits - n))) & ((1 << bits) - 1)
+        return v.to_bytes(len(b), 'big')
+    from math import gcd
+    l = len(inp)
+    lcm = outlen * l // gcd(outlen, l)
+    buf = b''.join(rot13(inp, 13 * i) for i in range(lcm // l))
+    # ones' complement addition of the outlen sized chunks
+    mod = (1 << (outlen * 8)) - 1
+    acc = 0
+    for i in range(0, lcm, outlen):
+        acc += int.from_bytes(buf[i:i + outlen], 'big')
+        acc = (acc & mod) + (acc >> (outlen * 8))
+    return acc.to_bytes(outlen, 'big')
+
+def dk(key, const):
+    c=nfold(const, 16); out=b''
+    while len(out)<len(key):
+        c=aes_ecb(key, c); out+=c
+    return out[:len(key)]
+
+def cts(k, p):
+    if len(p)==16: return aes_ecb(k,p)
+    pad=(-len(p))%16
+    c=aes_cbc(k, p+bytes(pad))
+    blocks=[c[i:i+16] for i in range(0,len(c),16)]
+    last=len(p)-16*(len(blocks)-1)
+    blocks[-1], blocks[-2] = blocks[-2], blocks[-1][:16]
+    return b''.join(blocks[:-2])+blocks[-2]+blocks[-1][:last]
+
+def encrypt(key, usage, plain, conf=bytes(range(16))):
+    ke=dk(key, struct.pack('>IB', usage, 0xAA))
+    ki=dk(key, struct.pack('>IB', usage, 0x55))
+    p=conf+plain
+    return cts(ke,p)+hmac.new(ki,p,hashlib.sha1).digest()[:12]
+
+def der_len(n):
+    if n<0x80: return bytes([n])
+    b=n.to_bytes((n.bit_length()+7)//8,'big'); return bytes([0x80|len(b)])+b
+def tlv(t,v): return bytes([t])+der_len(len(v))+v
+def seq(*x): return tlv(0x30,b''.join(x))
+def ctx(n,v): return tlv(0xa0+n,v)
+def app(n,v): return tlv(0x60+n,v)
+def integer(i):
+    b=i.to_bytes((i.bit_length()+8)//8 or 1,'big',signed=True); return tlv(2,b)
+def gstr(s): return tlv(0x1b,s)
+def octs(s): return tlv(4,s)
+def gtime(s): return tlv(0x18,s)
+def bits(v): return tlv(3,b'\x00'+struct.pack('>I',v))
+def pname(t,comps): return seq(ctx(0,integer(t)), ctx(1,seq(*[gstr(c) for c in comps])))
+
+AUTH=1704067200   # 2024-01-01
+END=2145830400    # 2037-12-31
+EXPIRED=AUTH+86400
+FLAGS=0x00400000|0x00200000  # initial, pre-authent
+ANONYMOUS=0x00020000
+def kt(t):
+    t=datetime.datetime.fromtimestamp(t, datetime.timezone.utc)
+    return t.strftime('%Y%m%d%H%M%SZ').encode()
+
+def cc_princ(t,comps,realm=REALM):
+    b=struct.pack('>II',t,len(comps))+struct.pack('>I',len(realm))+realm
+    for c in comps: b+=struct.pack('>I',len(c))+c
+    return b
+
+def checksum(key, usage, data):
+    kc=dk(key, struct.pack('>IB', usage, 0x99))
+    return hmac.new(kc,data,hashlib.sha1).digest()[:12]
+
+def ad(t,v): return seq(ctx(0,integer(t)), ctx(1,octs(v)))
+
+# an MS-PAC with just the client info and the signatures, signed with
+# the service key (and a made up KDC key, the server can't check it)
+KDCKEY=bytes(range(0x60, 0x80))
+def pac():
+    filetime=(AUTH+11644473600)*10000000
+    name=b''.join(CLI).decode().encode('utf-16-le')
+    sig=struct.pack('<I',16)+bytes(12)   # hmac-sha1-96-aes256
+    bufs=[(10,struct.pack('<QH',filetime,len(name))+name),(6,sig),(7,sig)]
+    off=8+16*len(bufs)
+    infos=b''; data=b''; sigs={}
+    for t,b in bufs:
+        infos+=struct.pack('<IIQ',t,len(b),off)
+        sigs[t]=off+4
+        b+=bytes(-len(b)%8)
+        data+=b; off+=len(b)
+    p=bytearray(struct.pack('<II',len(bufs),0)+infos+data)
+    srv=checksum(SKEY,17,bytes(p))
+    p[sigs[6]:sigs[6]+12]=srv
+    p[sigs[7]:sigs[7]+12]=checksum(KDCKEY,17,srv)
+    return bytes(p)
+
+def ccache(end, client=(1,CLI,REALM), flags=FLAGS, authdata=None):
+    t,comps,realm=client
+    enc_tkt=app(3, seq(
+        ctx(0,bits(flags)),
+        ctx(1,seq(ctx(0,integer(ETYPE)), ctx(1,octs(SESS)))),
+        ctx(2,gstr(realm)),
+        ctx(3,pname(t,comps)),
+        ctx(4,seq(ctx(0,integer(0)), ctx(1,octs(b'')))),
+        ctx(5,gtime(kt(AUTH))),
+        ctx(6,gtime(kt(AUTH))),
+        ctx(7,gtime(kt(end))),
+        *([ctx(10,authdata)] if authdata else []),
+    ))
+    ticket=app(1, seq(
+        ctx(0,integer(5)),
+        ctx(1,gstr(REALM)),
+        ctx(2,pname(2,SVC)),
+        ctx(3,seq(
+            ctx(0,integer(ETYPE)),
+            ctx(1,integer(1)),
+            ctx(2,octs(encrypt(SKEY,2,enc_tkt))),
+        )),
+    ))
+    cc=struct.pack('>HH',0x0504,0)+cc_princ(t,comps,realm)
+    cc+=cc_princ(t,comps,realm)+cc_princ(2,SVC)
+    cc+=struct.pack('>HI',ETYPE,len(SESS))+SESS
+    cc+=struct.pack('>IIII',AUTH,AUTH,end,0)
+    cc+=struct.pack('>BI',0,flags)
+    cc+=struct.pack('>II',0,0)
+    cc+=struct.pack('>I',len(ticket))+ticket+struct.pack('>I',0)
+    return cc
+
+def kt_princ(comps):
+    b=struct.pack('>H',len(comps))+struct.pack('>H',len(REALM))+REALM
+    for c in comps: b+=struct.pack('>H',len(c))+c
+    return b
+ent=kt_princ(SVC)+struct.pack('>IIB',1,AUTH,1)+struct.pack('>HH',ETYPE,len(SKEY))+SKEY
+keytab=struct.pack('>H',0x0502)+struct.pack('>i',len(ent))+ent
+
+out=sys.argv[1]
+open(out+'/krb5.keytab','wb').write(keytab)
+open(out+'/krb5cc','wb').write(ccache(END))
+open(out+'/krb5cc_expired','wb').write(ccache(EXPIRED))
+anon=(11,[b"WELLKNOWN",b"ANONYMOUS"],b"WELLKNOWN:ANONYMOUS")
+open(out+'/krb5cc_anonymous','wb').write(ccache(END,anon,FLAGS|ANONYMOUS))
+if_relevant=seq(ad(1,seq(ad(128,pac()))))
+open(out+'/krb5cc_pac','wb').write(ccache(END,authdata=if_relevant))
@@ -0,0 +1,87 @@
+#![cfg(feature = "sasl")]
+mod common;
+
+use common::{client, server_builder};
+use libgssapi::{
+    context::SecurityContext,
+    error::{Error, WellKnownError},
+    oid::GSS_MECH_KRB5,
+    sasl::gs2::{self, CbFlag, Gs2Client, Gs2Server, PLUS},
+};
+
+const CB_TYPE: &str = "tls-exporter";
+
+/// Run a GS2 exchange over `mech_name` with each side using its own
+/// channel binding data, as if they had read it from the same (or a
+/// different) TLS connection.
+fn exchange(
+    mech_name: &str,
+    authzid: Option<&str>,
+    client_cb: &[u8],
+    server_cb: &[u8],
+) -> Result<Gs2Server, Error> {
+    common::krb5_env();
+    let (mech, plus) = gs2::mech_for_saslname(mech_name)?;
+    assert_eq!(mech, &GSS_MECH_KRB5);
+    let (cb_flag, server_binding) = if plus {
+        (CbFlag::Used(String::from(CB_TYPE)), Some((CB_TYPE, server_cb)))
+    } else {
+        (CbFlag::NotSupported, None)
+    };
+    let mut client = Gs2Client::new(client(), authzid, cb_flag, client_cb);
+    let mut server = Gs2Server::new(server_builder().build(), mech, server_binding);
+    let mut challenge: Option<Vec<u8>> = None;
+    while let Some(response) = client.step(challenge.as_deref())? {
+        challenge = match server.step(&response)? {
+            None => break,
+            Some(tok) => Some(tok),
+        };
+    }
+    assert!(client.is_complete() && server.is_complete());
+    Ok(server)
+}
+
+fn plus() -> String {
+    format!("{}{}", gs2::saslname_for_mech(&GSS_MECH_KRB5).unwrap(), PLUS)
+}
+
+#[test]
+fn saslnames() {
+    common::krb5_env();
+    assert_eq!(gs2::saslname_for_mech(&GSS_MECH_KRB5).unwrap(), "GS2-KRB5");
+    assert_eq!(gs2::mech_for_saslname("GS2-KRB5-PLUS").unwrap(), (&GSS_MECH_KRB5, true));
+}
+
+#[test]
+fn gs2_krb5() {
+    let mut server = exchange("GS2-KRB5", Some("admin,ops=1"), b"", b"").unwrap();
+    assert_eq!(server.authzid(), Some("admin,ops=1"));
+    assert_eq!(server.channel_binding(), None);
+    let source = server.context_mut().source_name().unwrap();
+    assert_eq!(source.to_string(), "user@EXAMPLE.COM");
+}
+
+#[test]
+fn gs2_krb5_plus_matching_bindings() {
+    let server = exchange(&plus(), None, b"connection", b"connection").unwrap();
+    assert_eq!(server.authzid(), None);
+    assert_eq!(server.channel_binding(), Some(CB_TYPE));
+}
+
+#[test]
+fn gs2_krb5_plus_mismatched_bindings() {
+    let e = exchange(&plus(), None, b"connection", b"another").unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::BadBindings));
+}
+
+#[test]
+fn plus_stripped_from_advertisement() {
+    // the client thinks the server can't bind but it offered -PLUS
+    common::krb5_env();
+    let mut client = Gs2Client::new(client(), None, CbFlag::NotUsed, b"");
+    let binding = Some((CB_TYPE, &b"connection"[..]));
+    let mut server = Gs2Server::new(server_builder().build(), &GSS_MECH_KRB5, binding);
+    let response = client.step(None).unwrap().unwrap();
+    let e = server.step(&response).unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::BadBindings));
+}
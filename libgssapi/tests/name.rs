@@ -0,0 +1,355 @@
+mod common;
+
+use libgssapi::{
+    error::WellKnownError,
+    name::{Krb5Principal, Name, NameType},
+    oid::{
+        GSS_MECH_KRB5, GSS_NT_ANONYMOUS, GSS_NT_HOSTBASED_SERVICE, GSS_NT_KRB5_PRINCIPAL,
+        GSS_NT_USER_NAME,
+    },
+};
+
+fn principal(s: &str) -> Name {
+    common::krb5_env();
+    Name::new(s.as_bytes(), Some(&GSS_NT_KRB5_PRINCIPAL)).unwrap()
+}
+
+fn canonical(name: &Name) -> String {
+    name.canonicalize(Some(&GSS_MECH_KRB5)).unwrap().to_string()
+}
+
+#[test]
+fn hostbased_canonicalization() {
+    common::krb5_env();
+    let name = Name::new("nfs@host.example.com", Some(&GSS_NT_HOSTBASED_SERVICE));
+    let name = name.unwrap();
+    assert_eq!(name.to_string(), "nfs@host.example.com");
+    assert_eq!(canonical(&name), "nfs/host.example.com@EXAMPLE.COM");
+    // the same text as a krb5 principal is a user in the default realm
+    assert_eq!(canonical(&principal("nfs@host.example.com")), "nfs@host.example.com");
+}
+
+#[test]
+fn export_for_is_independent_of_the_import() {
+    let krb5 = principal("nfs/host.example.com@EXAMPLE.COM");
+    let service = Name::service("nfs", "host.example.com").unwrap();
+    let exported = service.export_for(&GSS_MECH_KRB5).unwrap();
+    assert_eq!(*exported, *krb5.export_for(&GSS_MECH_KRB5).unwrap());
+    // an imported name isn't a mechanism name
+    let e = service.export().unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::NameNotMn));
+    let other = principal("nfs/other.example.com@EXAMPLE.COM");
+    assert_ne!(*exported, *other.export_for(&GSS_MECH_KRB5).unwrap());
+}
+
+#[test]
+fn from_exported_round_trip() {
+    let name = principal("nfs/host.example.com@EXAMPLE.COM");
+    let exported = name.export_for(&GSS_MECH_KRB5).unwrap();
+    let imported = Name::from_exported(&exported).unwrap();
+    assert!(imported.equal(&name).unwrap());
+    assert!(!imported.equal(&principal("user@EXAMPLE.COM")).unwrap());
+    // already a mechanism name, so it exports without canonicalizing
+    assert_eq!(*imported.export().unwrap(), *exported);
+}
+
+#[test]
+fn corrupt_exported() {
+    let exported = principal("user@EXAMPLE.COM").export_for(&GSS_MECH_KRB5).unwrap();
+    // the wrong token id, a truncated name, and a truncated header
+    let mut bad_id = exported.to_vec();
+    bad_id[0] = 0x05;
+    let corrupt = [
+        bad_id,
+        exported[..exported.len() - 1].to_vec(),
+        exported[..3].to_vec(),
+    ];
+    for tok in corrupt {
+        let e = Name::from_exported(&tok).unwrap_err();
+        assert_eq!(e.kind(), Some(WellKnownError::BadName), "{:x?}", tok);
+    }
+}
+
+#[test]
+fn name_type() {
+    common::krb5_env();
+    let service = Name::new("nfs@host.example.com", Some(&GSS_NT_HOSTBASED_SERVICE));
+    let service = service.unwrap();
+    let user = Name::new("nfs@host.example.com", Some(&GSS_NT_USER_NAME)).unwrap();
+    assert_eq!(service.name_type().unwrap(), Some(&GSS_NT_HOSTBASED_SERVICE));
+    assert_eq!(user.name_type().unwrap(), Some(&GSS_NT_USER_NAME));
+    // the same text, but they aren't the same principal
+    let service = service.canonicalize(Some(&GSS_MECH_KRB5)).unwrap();
+    let user = user.canonicalize(Some(&GSS_MECH_KRB5)).unwrap();
+    assert_eq!(service.name_type().unwrap(), Some(&GSS_NT_KRB5_PRINCIPAL));
+    assert_eq!(user.name_type().unwrap(), Some(&GSS_NT_KRB5_PRINCIPAL));
+    assert_eq!(service.to_string(), "nfs/host.example.com@EXAMPLE.COM");
+    assert_eq!(user.to_string(), "nfs@host.example.com");
+    assert_eq!(Name::new("nfs", None).unwrap().name_type().unwrap(), None);
+}
+
+/// With SPNEGO, names are canonicalized for the mechanism it
+/// negotiated
+#[test]
+fn canonicalize_for_negotiated_mech() {
+    use common::{handshake_with, server_builder, service_name};
+    use libgssapi::{
+        context::{ClientCtx, CtxFlags, SecurityContext},
+        oid::GSS_MECH_SPNEGO,
+    };
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG;
+    let mut client = ClientCtx::new(None, service_name(), flags, Some(&GSS_MECH_SPNEGO));
+    let mut server = server_builder().build();
+    handshake_with(&mut client, &mut server, None).unwrap();
+    let mech = server.mechanism().unwrap();
+    assert_eq!(mech, &GSS_MECH_KRB5);
+    let user = Name::new("user@EXAMPLE.COM", Some(&GSS_NT_USER_NAME)).unwrap();
+    let user = user.canonicalize(Some(mech)).unwrap();
+    // MIT exports the source name with SPNEGO's oid, canonicalizing
+    // it for the negotiated mechanism gives the krb5 form
+    let source = server.source_name().unwrap();
+    assert_ne!(*user.export().unwrap(), *source.export().unwrap());
+    assert_eq!(*user.export().unwrap(), *source.export_for(mech).unwrap());
+    let service = Name::service("HTTP", "host.example.com").unwrap();
+    let mech = client.mechanism().unwrap();
+    let target = client.target_name().unwrap().export_for(mech).unwrap();
+    assert_eq!(*service.export_for(mech).unwrap(), *target);
+}
+
+// what an acceptor might do with each kind of name
+fn describe(name: &Name) -> String {
+    match name.kind().unwrap() {
+        NameType::Anonymous => String::from("anonymous"),
+        NameType::HostbasedService => format!("service {}", name),
+        NameType::Krb5Principal => format!("principal {}", name),
+        kind => format!("{:?} {}", kind, name),
+    }
+}
+
+#[test]
+fn kind() {
+    common::krb5_env();
+    let service = Name::service("nfs", "host.example.com").unwrap();
+    let names = [
+        (service, "service nfs@host.example.com"),
+        (principal("user@EXAMPLE.COM"), "principal user@EXAMPLE.COM"),
+        (Name::new("", Some(&GSS_NT_ANONYMOUS)).unwrap(), "anonymous"),
+        (Name::new("user", Some(&GSS_NT_USER_NAME)).unwrap(), "UserName user"),
+        (Name::new("user", None).unwrap(), "Untyped user"),
+    ];
+    for (name, expected) in &names {
+        assert_eq!(describe(name), *expected);
+    }
+    // canonical names are krb5 principals, except the anonymous name
+    let canonical: Vec<NameType> = names
+        .iter()
+        .map(|(name, _)| name.canonicalize(None).unwrap().kind().unwrap())
+        .collect();
+    let expected = [
+        NameType::Krb5Principal,
+        NameType::Krb5Principal,
+        NameType::Anonymous,
+        NameType::Krb5Principal,
+        NameType::Krb5Principal,
+    ];
+    assert_eq!(canonical, expected);
+}
+
+#[test]
+fn service() {
+    common::krb5_env();
+    let name = Name::service("nfs", "host.example.com").unwrap();
+    assert_eq!(name.to_string(), "nfs@host.example.com");
+    assert_eq!(canonical(&name), "nfs/host.example.com@EXAMPLE.COM");
+    // the name keeps the host as given, krb5 folds the case and drops
+    // the trailing dot of a fully qualified host
+    for host in ["host.example.com.", "HOST.Example.COM", "HOST.EXAMPLE.COM."] {
+        let name = Name::service("nfs", host).unwrap();
+        assert_eq!(name.to_string(), format!("nfs@{}", host));
+        assert_eq!(canonical(&name), "nfs/host.example.com@EXAMPLE.COM", "{}", host);
+    }
+}
+
+#[test]
+fn bad_service() {
+    for (service, host) in [
+        ("", "host.example.com"),
+        ("nfs", ""),
+        ("nfs@host.example.com", "host.example.com"),
+        ("nfs", "host.example.com@EXAMPLE.COM"),
+        ("nfs\0", "host.example.com"),
+        ("nfs", "host.example.com\0.evil"),
+    ] {
+        let e = Name::service(service, host).unwrap_err();
+        assert_eq!(e.kind(), Some(WellKnownError::BadName), "{:?}@{:?}", service, host);
+    }
+}
+
+#[test]
+fn krb5_principal_round_trip() {
+    common::krb5_env();
+    let principals = [
+        Krb5Principal::new(&["user"], Some("EXAMPLE.COM")),
+        Krb5Principal::new(&["a/b", "c@d"], Some("EXAMPLE.COM")),
+        Krb5Principal::new(&["back\\slash", "tab\tnew\nline"], Some("EXAMPLE.COM")),
+        Krb5Principal::new(&["nul\0", "bs\x08"], Some("EXAMPLE.COM")),
+        Krb5Principal::new(&["svc", "host.example.com"], Some("ODD/REALM@X")),
+    ];
+    for p in &principals {
+        let name = Name::from_krb5_principal(p).unwrap();
+        // the library quotes the same way we do
+        assert_eq!(name.to_string(), p.to_string());
+        assert_eq!(&name.krb5_principal().unwrap(), p);
+        assert_eq!(&Krb5Principal::parse(&p.to_string()).unwrap(), p);
+    }
+    // no realm, the default is added
+    let p = Krb5Principal::new(&["a@b"], None);
+    let name = Name::from_krb5_principal(&p).unwrap();
+    let expected = Krb5Principal::new(&["a@b"], Some("EXAMPLE.COM"));
+    assert_eq!(name.krb5_principal().unwrap(), expected);
+}
+
+/// The source name of a context initiated with an anonymous ticket
+/// is the anonymous name, and a normal principal isn't
+#[cfg(feature = "mit_kerberos")]
+#[test]
+fn anonymous_handshake() {
+    use common::{handshake_with, server_builder, service_name};
+    use libgssapi::{
+        context::{ClientCtx, CtxFlags, SecurityContext},
+        credential::{Cred, CredStore, CredUsage},
+    };
+    common::krb5_env();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/krb5cc_anonymous");
+    let store = CredStore::ccache(&format!("FILE:{}", path));
+    let cred = Cred::acquire_from(None, &store, CredUsage::Initiate).unwrap();
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG | CtxFlags::GSS_C_ANON_FLAG;
+    let mech = Some(&GSS_MECH_KRB5);
+    let mut client = ClientCtx::new(Some(cred), service_name(), flags, mech);
+    let mut server = server_builder().build();
+    handshake_with(&mut client, &mut server, None).unwrap();
+    let source = server.source_name().unwrap();
+    assert!(source.is_anonymous().unwrap());
+    assert_eq!(source.to_string(), "WELLKNOWN/ANONYMOUS@WELLKNOWN:ANONYMOUS");
+    let (_, mut server) = common::established();
+    assert!(!server.source_name().unwrap().is_anonymous().unwrap());
+    assert!(!principal("user@EXAMPLE.COM").is_anonymous().unwrap());
+    assert!(!service_name().is_anonymous().unwrap());
+}
+
+/// The source name of a context initiated with a ticket carrying an
+/// MS-PAC, which MIT exposes as `urn:mspac:` attributes
+#[cfg(all(feature = "name_attributes", feature = "mit_kerberos"))]
+fn pac_source_name() -> Name {
+    use common::{handshake_with, server_builder, service_name};
+    use libgssapi::{
+        context::{ClientCtx, CtxFlags, SecurityContext},
+        credential::{Cred, CredStore, CredUsage},
+    };
+    common::krb5_env();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/krb5cc_pac");
+    let store = CredStore::ccache(&format!("FILE:{}", path));
+    let cred = Cred::acquire_from(None, &store, CredUsage::Initiate).unwrap();
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG;
+    let mech = Some(&GSS_MECH_KRB5);
+    let mut client = ClientCtx::new(Some(cred), service_name(), flags, mech);
+    let mut server = server_builder().build();
+    handshake_with(&mut client, &mut server, None).unwrap();
+    assert!(server.pac().unwrap().is_some());
+    server.source_name().unwrap()
+}
+
+#[cfg(all(feature = "name_attributes", feature = "mit_kerberos"))]
+fn attributes(name: &Name) -> Vec<Vec<u8>> {
+    name.attributes().unwrap().iter().map(|a| a.to_vec()).collect()
+}
+
+#[cfg(all(feature = "name_attributes", feature = "mit_kerberos"))]
+const CLIENT_INFO: &[u8] = b"urn:mspac:client-info";
+#[cfg(all(feature = "name_attributes", feature = "mit_kerberos"))]
+const LOGON_INFO: &[u8] = b"urn:mspac:logon-info";
+#[cfg(all(feature = "name_attributes", feature = "mit_kerberos"))]
+const UPN_DNS_INFO: &[u8] = b"urn:mspac:upn-dns-info";
+
+/// Changing the attributes of a duplicate leaves the original alone.
+/// `Name` isn't `Clone`, so there is no shared handle whose changes
+/// would be seen by both, short of borrowing the same `Name`.
+#[cfg(all(feature = "name_attributes", feature = "mit_kerberos"))]
+#[test]
+fn duplicate_is_independent() {
+    let name = pac_source_name();
+    let mut copy = name.duplicate().unwrap();
+    assert!(copy.equal(&name).unwrap());
+    assert_eq!(attributes(&copy), attributes(&name));
+    assert!(copy.attribute(CLIENT_INFO).unwrap().authenticated);
+    copy.set_attribute(LOGON_INFO, b"logon info", true).unwrap();
+    assert!(attributes(&copy).contains(&LOGON_INFO.to_vec()));
+    assert!(!attributes(&name).contains(&LOGON_INFO.to_vec()));
+    // MIT stops vouching for a PAC once it's changed, but only in the
+    // copy
+    let e = copy.attribute(CLIENT_INFO).unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::Unavailable));
+    assert!(name.attribute(CLIENT_INFO).unwrap().authenticated);
+}
+
+/// Set a local attribute, read it back and delete it. MIT only lets
+/// the PAC buffers be set, and once the PAC has been changed it is no
+/// longer verified, so none of it can be read back, and MIT can't
+/// delete from a PAC at all. This pins down each step.
+#[cfg(all(feature = "name_attributes", feature = "mit_kerberos"))]
+#[test]
+fn set_and_delete_attribute() {
+    let unavailable = |r: Result<(), _>| {
+        let e: libgssapi::error::Error = r.unwrap_err();
+        assert_eq!(e.kind(), Some(WellKnownError::Unavailable));
+    };
+    let mut name = pac_source_name();
+    assert!(!attributes(&name).contains(&UPN_DNS_INFO.to_vec()));
+    unavailable(name.attribute(UPN_DNS_INFO).map(|_| ()));
+    // not an attribute any mechanism knows
+    unavailable(name.set_attribute(b"urn:example:local", b"value", true));
+    name.set_attribute(UPN_DNS_INFO, b"upn dns info", true).unwrap();
+    assert!(attributes(&name).contains(&UPN_DNS_INFO.to_vec()));
+    unavailable(name.attribute(UPN_DNS_INFO).map(|_| ()));
+    unavailable(name.attribute(CLIENT_INFO).map(|_| ()));
+    unavailable(name.delete_attribute(UPN_DNS_INFO));
+    assert!(attributes(&name).contains(&UPN_DNS_INFO.to_vec()));
+}
+
+/// The trivial case, a principal in the default realm may act as the
+/// local account of the same name. The account has to exist, so this
+/// uses root.
+#[cfg(any(feature = "mit_kerberos", feature = "heimdal"))]
+#[test]
+fn authorized_for_itself() {
+    // authenticated names are mechanism names
+    let name = principal("root@EXAMPLE.COM").canonicalize(None).unwrap();
+    assert!(name.authorized_for_local_user("root").unwrap());
+    #[cfg(feature = "mit_kerberos")]
+    assert!(name.userok("root").unwrap());
+}
+
+#[cfg(feature = "localname")]
+#[test]
+fn localname_default_realm() {
+    let name = principal("user@EXAMPLE.COM");
+    assert_eq!(&*name.localname(Some(&GSS_MECH_KRB5)).unwrap(), b"user");
+}
+
+#[cfg(feature = "localname")]
+#[test]
+fn no_local_mapping() {
+    // the default rule only maps single component principals in the
+    // default realm
+    for s in ["user@OTHER.REALM", "HTTP/host.example.com@EXAMPLE.COM"] {
+        let e = principal(s).localname(Some(&GSS_MECH_KRB5)).unwrap_err();
+        assert_eq!(e.kind(), Some(WellKnownError::NoLocalMapping), "{}", s);
+    }
+}
+
+#[cfg(all(feature = "localname", feature = "mit_kerberos"))]
+#[test]
+fn no_local_uid() {
+    let e = principal("user@OTHER.REALM").to_uid(Some(&GSS_MECH_KRB5)).unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::NoLocalMapping));
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "tower")]
+mod common;
+
+use common::service_name;
+use http::{
+    header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    Request, Response, StatusCode,
+};
+use libgssapi::{
+    context::{ClientCtx, CtxFlags, SecurityContext},
+    credential::{Cred, CredUsage},
+    negotiate::{
+        client_step,
+        tower::{Authenticated, NegotiateLayer},
+    },
+    oid::GSS_MECH_SPNEGO,
+};
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+#[derive(Debug, Clone, Copy)]
+struct Hello;
+
+impl Service<Request<()>> for Hello {
+    type Response = Response<String>;
+    type Error = Infallible;
+    type Future = Ready<Result<Self::Response, Infallible>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<()>) -> Self::Future {
+        let auth = req.extensions().get::<Authenticated>().unwrap();
+        ready(Ok(Response::new(format!("hello {}", auth.name))))
+    }
+}
+
+fn request(authorization: Option<&str>) -> Request<()> {
+    let mut req = Request::builder();
+    if let Some(authorization) = authorization {
+        req = req.header(AUTHORIZATION, authorization);
+    }
+    req.body(()).unwrap()
+}
+
+fn layer() -> NegotiateLayer {
+    NegotiateLayer::new(|| {
+        let name = service_name();
+        Cred::acquire(Some(&name), None, CredUsage::Accept, None)
+    })
+}
+
+#[tokio::test]
+async fn handshake() {
+    let mut service = layer().layer(Hello);
+    let resp = service.call(request(None)).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers()[WWW_AUTHENTICATE], "Negotiate");
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG;
+    let mut ctx = ClientCtx::new(None, service_name(), flags, Some(&GSS_MECH_SPNEGO));
+    let mut auth = client_step(&mut ctx, None, None).unwrap();
+    let resp = loop {
+        let resp = service.call(request(auth.as_deref())).await.unwrap();
+        let challenge = resp.headers().get(WWW_AUTHENTICATE).map(|v| v.to_str().unwrap());
+        auth = client_step(&mut ctx, challenge, None).unwrap();
+        if resp.status() != StatusCode::UNAUTHORIZED {
+            break resp;
+        }
+    };
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.body(), "hello user@EXAMPLE.COM");
+    assert!(ctx.is_complete());
+}
+
+#[tokio::test]
+async fn bad_token_forbidden() {
+    let mut service = layer().layer(Hello);
+    let resp = service.call(request(Some("Negotiate AAAA"))).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    let resp = service.call(request(Some("Basic dXNlcjpwYXNz"))).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "postgres")]
+//! Run `connect` against a fake server on the other end of a socket
+//! pair, which checks the `GSSENCRequest` and the token framing and
+//! accepts the handshake with the test keytab.
+mod common;
+
+use common::server_builder;
+use libgssapi::{
+    context::SecurityContext,
+    postgres::{connect, Negotiated, MAX_TOKEN_SIZE},
+    stream::GssStream,
+};
+use std::{
+    convert::TryInto,
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    thread,
+};
+
+const HOST: &str = "host.example.com";
+const SERVICE: &str = "HTTP";
+
+fn read_request(stream: &mut UnixStream) {
+    let mut pkt = [0u8; 8];
+    stream.read_exact(&mut pkt).unwrap();
+    // length 8, then the request code 80877104
+    assert_eq!(pkt, [0, 0, 0, 8, 0x04, 0xd2, 0x16, 0x30]);
+}
+
+fn read_token(stream: &mut UnixStream) -> Vec<u8> {
+    let mut hdr = [0u8; 4];
+    stream.read_exact(&mut hdr).unwrap();
+    let mut tok = vec![0u8; u32::from_be_bytes(hdr) as usize];
+    stream.read_exact(&mut tok).unwrap();
+    tok
+}
+
+// accept the handshake, then echo one message over the encrypted stream
+fn serve(mut stream: UnixStream) {
+    read_request(&mut stream);
+    stream.write_all(b"G").unwrap();
+    let mut server = server_builder().build();
+    while !server.is_complete() {
+        let tok = read_token(&mut stream);
+        if let Some(tok) = server.step(&tok).unwrap() {
+            stream.write_all(&(tok.len() as u32).to_be_bytes()).unwrap();
+            stream.write_all(&tok).unwrap();
+        }
+    }
+    let mut stream = GssStream::new(server, stream, true).max_token_size(MAX_TOKEN_SIZE);
+    let mut msg = [0u8; 5];
+    stream.read_exact(&mut msg).unwrap();
+    stream.write_all(&msg).unwrap();
+}
+
+#[test]
+fn encrypted() {
+    common::krb5_env();
+    let (client, server) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || serve(server));
+    let mut stream = match connect(client, HOST, Some(SERVICE)).unwrap() {
+        Negotiated::Encrypted(stream) => stream,
+        Negotiated::Refused(_) => panic!("refused"),
+    };
+    stream.write_all(b"hello").unwrap();
+    let mut msg = [0u8; 5];
+    stream.read_exact(&mut msg).unwrap();
+    assert_eq!(&msg, b"hello");
+    server.join().unwrap();
+}
+
+#[test]
+fn refused() {
+    common::krb5_env();
+    let (client, mut server) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || {
+        read_request(&mut server);
+        server.write_all(b"N").unwrap();
+    });
+    assert!(matches!(
+        connect(client, HOST, Some(SERVICE)).unwrap(),
+        Negotiated::Refused(_)
+    ));
+    server.join().unwrap();
+}
+
+// the server rejects the client after its first token
+fn reject(mut stream: UnixStream) {
+    read_request(&mut stream);
+    stream.write_all(b"G").unwrap();
+    read_token(&mut stream);
+    let fields: &[u8] = b"SFATAL\0C28000\0Mno pg_hba.conf entry for host \"[local]\"\0\0";
+    let len: u32 = (4 + fields.len()).try_into().unwrap();
+    stream.write_all(b"E").unwrap();
+    stream.write_all(&len.to_be_bytes()).unwrap();
+    stream.write_all(fields).unwrap();
+}
+
+fn assert_rejected(e: io::Error) {
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionRefused);
+    assert_eq!(e.to_string(), "FATAL: no pg_hba.conf entry for host \"[local]\"");
+}
+
+#[test]
+fn error_during_handshake() {
+    common::krb5_env();
+    let (client, server) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || reject(server));
+    assert_rejected(connect(client, HOST, Some(SERVICE)).unwrap_err());
+    server.join().unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread")]
+async fn error_during_handshake_async() {
+    use libgssapi::postgres::connect_async;
+    common::krb5_env();
+    let (client, server) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || reject(server));
+    client.set_nonblocking(true).unwrap();
+    let client = tokio::net::UnixStream::from_std(client).unwrap();
+    assert_rejected(connect_async(client, HOST, Some(SERVICE)).await.unwrap_err());
+    server.join().unwrap();
+}
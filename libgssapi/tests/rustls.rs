@@ -0,0 +1,97 @@
+#![cfg(feature = "rustls")]
+//! Run a rustls handshake in memory and check both ends derive the
+//! same channel bindings. `tests/data/tls` holds a test CA and a
+//! certificate for localhost it signed, with its PKCS#8 key, made with
+//! `openssl req` and `openssl x509 -req`.
+mod common;
+
+use common::{client, handshake_with, server_builder};
+use libgssapi::{
+    channel_binding::{
+        tls_exporter, tls_server_end_point, tls_server_end_point_for_client,
+        TLS_EXPORTER,
+    },
+    context::{CtxFlags, SecurityContext},
+};
+use rustls::{
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName},
+    ClientConfig, ClientConnection, ConnectionCommon, RootCertStore, ServerConfig,
+    ServerConnection,
+};
+use std::{convert::TryFrom, fs, sync::Arc};
+
+fn read(name: &str) -> Vec<u8> {
+    let path = format!("{}/tests/data/tls/{}", env!("CARGO_MANIFEST_DIR"), name);
+    fs::read(path).unwrap()
+}
+
+fn transfer<A, B>(from: &mut ConnectionCommon<A>, to: &mut ConnectionCommon<B>) {
+    let mut buf = Vec::new();
+    while from.wants_write() {
+        from.write_tls(&mut buf).unwrap();
+    }
+    let mut rd = &buf[..];
+    while !rd.is_empty() {
+        to.read_tls(&mut rd).unwrap();
+    }
+    to.process_new_packets().unwrap();
+}
+
+fn connect() -> (ClientConnection, ServerConnection) {
+    let provider = Arc::new(ring::default_provider());
+    let cert = CertificateDer::from(read("localhost.der"));
+    let key = PrivateKeyDer::from(PrivatePkcs8KeyDer::from(read("localhost.key.der")));
+    let server_config = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .unwrap();
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from(read("ca.der"))).unwrap();
+    let client_config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let name = ServerName::try_from("localhost").unwrap();
+    let mut client = ClientConnection::new(Arc::new(client_config), name).unwrap();
+    let mut server = ServerConnection::new(Arc::new(server_config)).unwrap();
+    assert!(tls_exporter(&client).is_err());
+    while client.is_handshaking() || server.is_handshaking() {
+        transfer(&mut client, &mut server);
+        transfer(&mut server, &mut client);
+    }
+    (client, server)
+}
+
+#[test]
+fn tls_exporter_matches() {
+    let (client, server) = connect();
+    let cb = tls_exporter(&client).unwrap();
+    assert_eq!(&cb[..TLS_EXPORTER.len()], TLS_EXPORTER);
+    assert_eq!(cb.len(), TLS_EXPORTER.len() + 32);
+    assert_eq!(cb, tls_exporter(&server).unwrap());
+    // a different connection derives different bindings
+    let (other, _) = connect();
+    assert_ne!(cb, tls_exporter(&other).unwrap());
+}
+
+#[test]
+fn tls_server_end_point_matches() {
+    let (client, _server) = connect();
+    let cb = tls_server_end_point_for_client(&client).unwrap();
+    assert_eq!(cb, tls_server_end_point(&read("localhost.der")).unwrap());
+}
+
+#[test]
+fn bound_handshake() {
+    let (tls_client, tls_server) = connect();
+    let client_cb = tls_exporter(&tls_client).unwrap();
+    let server_cb = tls_exporter(&tls_server).unwrap();
+    let mut client = client();
+    let mut server = server_builder().channel_bindings(&server_cb).build();
+    handshake_with(&mut client, &mut server, Some(&client_cb)).unwrap();
+    assert!(server.flags().unwrap().contains(CtxFlags::CHANNEL_BOUND));
+}
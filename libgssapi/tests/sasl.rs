@@ -0,0 +1,127 @@
+#![cfg(feature = "sasl")]
+mod common;
+
+use common::{server_builder, service_name};
+use libgssapi::{
+    context::{ClientCtx, CtxFlags, SecurityContext},
+    error::{Error, WellKnownError},
+    oid::GSS_MECH_KRB5,
+    sasl::{SaslClient, SaslServer, SecurityLayers, MAX_SIZE},
+};
+
+const ALL: SecurityLayers = SecurityLayers::all();
+
+fn client_ctx() -> ClientCtx {
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG
+        | CtxFlags::GSS_C_SEQUENCE_FLAG
+        | CtxFlags::GSS_C_INTEG_FLAG
+        | CtxFlags::GSS_C_CONF_FLAG;
+    ClientCtx::new(None, service_name(), flags, Some(&GSS_MECH_KRB5))
+}
+
+/// Run the handshake, returning the server's wrapped offer of layers
+fn until_offer(client: &mut SaslClient, server: &mut SaslServer) -> Vec<u8> {
+    let mut challenge: Option<Vec<u8>> = None;
+    loop {
+        let response = client.step(challenge.as_deref()).unwrap().unwrap();
+        let done = client.context_mut().is_complete();
+        let tok = server.step(&response).unwrap().unwrap();
+        if done {
+            break tok;
+        }
+        challenge = Some(tok);
+    }
+}
+
+/// Run the whole exchange
+fn exchange(
+    acceptable: SecurityLayers,
+    offered: SecurityLayers,
+) -> Result<(SaslClient, SaslServer), Error> {
+    let mut client = SaslClient::new(client_ctx(), acceptable, 4096, Some("admin"));
+    let mut server = SaslServer::new(server_builder().build(), offered, 8192);
+    let offer = until_offer(&mut client, &mut server);
+    let choice = client.step(Some(&offer))?.unwrap();
+    assert_eq!(server.step(&choice)?, None);
+    assert!(client.is_complete() && server.is_complete());
+    assert_eq!(client.layer(), server.layer());
+    Ok((client, server))
+}
+
+#[test]
+fn strongest_layer() {
+    let (client, server) = exchange(ALL, ALL).unwrap();
+    assert_eq!(client.layer(), Some(SecurityLayers::CONFIDENTIALITY));
+    assert_eq!(client.server_max_size(), Some(8192));
+    assert_eq!(server.client_max_size(), Some(4096));
+    assert_eq!(server.authzid(), Some("admin"));
+}
+
+#[test]
+fn client_limits_layer() {
+    let acceptable = SecurityLayers::INTEGRITY | SecurityLayers::NONE;
+    let (client, _) = exchange(acceptable, ALL).unwrap();
+    assert_eq!(client.layer(), Some(SecurityLayers::INTEGRITY));
+}
+
+#[test]
+fn server_limits_layer() {
+    let (client, server) = exchange(ALL, SecurityLayers::NONE).unwrap();
+    assert_eq!(client.layer(), Some(SecurityLayers::NONE));
+    // no layer, so the client has no use for a message size
+    assert_eq!(server.client_max_size(), Some(0));
+}
+
+#[test]
+fn no_common_layer() {
+    let e = exchange(SecurityLayers::CONFIDENTIALITY, SecurityLayers::NONE)
+        .map(|_| ())
+        .unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::BadQop));
+}
+
+/// The server rejects a choice of layer it didn't offer, or of more
+/// than one layer
+#[test]
+fn unoffered_layer() {
+    let offered = SecurityLayers::INTEGRITY | SecurityLayers::NONE;
+    let layers = [
+        SecurityLayers::CONFIDENTIALITY,
+        SecurityLayers::INTEGRITY | SecurityLayers::NONE,
+    ];
+    for layer in layers {
+        let mut client = SaslClient::new(client_ctx(), ALL, 4096, None);
+        let mut server = SaslServer::new(server_builder().build(), offered, 8192);
+        until_offer(&mut client, &mut server);
+        let msg = [layer.bits(), 0, 0x10, 0];
+        let choice = client.context_mut().wrap(false, &msg).unwrap();
+        let e = server.step(&choice).unwrap_err();
+        assert_eq!(e.kind(), Some(WellKnownError::BadQop));
+        assert!(!server.is_complete());
+        assert_eq!(server.layer(), None);
+    }
+}
+
+/// A context without `GSS_C_INTEG_FLAG` and `GSS_C_CONF_FLAG` can't
+/// offer or choose a security layer, whatever the caller asked for
+#[cfg(feature = "mit_kerberos")]
+#[test]
+fn layer_needs_context_flag() {
+    use libgssapi::{
+        credential::{Cred, CredUsage},
+        oid::GSS_KRB5_CRED_NO_CI_FLAGS_X,
+    };
+    common::krb5_env();
+    let mut cred = Cred::acquire(None, None, CredUsage::Initiate, None).unwrap();
+    cred.set_option(&GSS_KRB5_CRED_NO_CI_FLAGS_X, None).unwrap();
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG | CtxFlags::GSS_C_SEQUENCE_FLAG;
+    let ctx = ClientCtx::new(Some(cred), service_name(), flags, Some(&GSS_MECH_KRB5));
+    let mut client = SaslClient::new(ctx, ALL, MAX_SIZE, None);
+    let mut server = SaslServer::new(server_builder().build(), ALL, MAX_SIZE);
+    let offer = until_offer(&mut client, &mut server);
+    let choice = client.step(Some(&offer)).unwrap().unwrap();
+    assert_eq!(server.step(&choice).unwrap(), None);
+    assert!(!client.context_mut().flags().unwrap().contains(CtxFlags::GSS_C_INTEG_FLAG));
+    assert_eq!(client.layer(), Some(SecurityLayers::NONE));
+    assert_eq!(server.layer(), Some(SecurityLayers::NONE));
+}
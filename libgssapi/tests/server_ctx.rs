@@ -0,0 +1,18 @@
+mod common;
+
+use common::{client, handshake_with, SERVICE};
+use libgssapi::context::{SecurityContext, ServerCtx};
+
+/// No credential is passed, the acceptor finds the service key in the
+/// keytab `KRB5_KTNAME` names
+#[test]
+fn new_default() {
+    let mut client = client();
+    let mut server = ServerCtx::new_default();
+    handshake_with(&mut client, &mut server, None).unwrap();
+    assert!(client.is_complete() && server.is_complete());
+    assert_eq!(server.source_name().unwrap().to_string(), "user@EXAMPLE.COM");
+    assert_eq!(server.target_name().unwrap().to_string().as_bytes(), SERVICE);
+    let tok = client.wrap(true, b"default").unwrap();
+    assert_eq!(&*server.unwrap(&tok).unwrap(), b"default");
+}
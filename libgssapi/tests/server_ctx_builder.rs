@@ -0,0 +1,106 @@
+mod common;
+
+use common::{client, handshake_with, server_builder, service_name};
+use libgssapi::{
+    context::{ClientCtx, CtxFlags, SecurityContext, ServerCtxBuilder},
+    credential::{Cred, CredUsage},
+    error::{MajorFlags, WellKnownError},
+    oid::{OidSet, GSS_MECH_IAKERB, GSS_MECH_KRB5, GSS_MECH_NTLM, GSS_MECH_SPNEGO},
+};
+
+const BINDINGS: &[u8] = b"tls-exporter:0123456789abcdef";
+
+#[test]
+fn matching_bindings() {
+    let mut client = client();
+    let mut server = server_builder().channel_bindings(BINDINGS).build();
+    handshake_with(&mut client, &mut server, Some(BINDINGS)).unwrap();
+    assert!(server.flags().unwrap().contains(CtxFlags::CHANNEL_BOUND));
+}
+
+#[test]
+fn missing_client_bindings_rejected() {
+    let mut client = client();
+    let mut server = server_builder().channel_bindings(BINDINGS).build();
+    let e = handshake_with(&mut client, &mut server, None).unwrap_err();
+    assert!(e.major.contains(MajorFlags::GSS_S_BAD_BINDINGS));
+    assert!(!server.is_complete());
+}
+
+#[test]
+fn mismatched_client_bindings_rejected() {
+    let mut client = client();
+    let mut server = server_builder().channel_bindings(BINDINGS).build();
+    let e = handshake_with(&mut client, &mut server, Some(b"other")).unwrap_err();
+    assert!(e.major.contains(MajorFlags::GSS_S_BAD_BINDINGS));
+}
+
+#[test]
+fn builder_bindings_used_when_none_passed() {
+    let mut client = client();
+    let mut server = server_builder().channel_bindings(BINDINGS).build();
+    let tok = client.step(None, Some(b"other")).unwrap().unwrap();
+    let e = server.step_with_channel_bindings(&tok, None).unwrap_err();
+    assert!(e.major.contains(MajorFlags::GSS_S_BAD_BINDINGS));
+}
+
+#[test]
+fn explicit_bindings_override_builder() {
+    let mut client = client();
+    let mut server = server_builder().channel_bindings(BINDINGS).build();
+    let tok = client.step(None, Some(b"other")).unwrap().unwrap();
+    let tok = server.step_with_channel_bindings(&tok, Some(b"other")).unwrap();
+    client.step(tok.as_deref(), Some(b"other")).unwrap();
+    assert!(server.is_complete() && client.is_complete());
+}
+
+#[test]
+fn no_bindings() {
+    let mut client = client();
+    let mut server = server_builder().build();
+    handshake_with(&mut client, &mut server, None).unwrap();
+    assert!(!server.flags().unwrap().contains(CtxFlags::CHANNEL_BOUND));
+}
+
+fn spnego_client() -> ClientCtx {
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG;
+    ClientCtx::new(None, service_name(), flags, Some(&GSS_MECH_SPNEGO))
+}
+
+#[test]
+fn allowed_mech_accepted() {
+    let mut client = spnego_client();
+    let mut server = server_builder().allowed_mechs(&[&GSS_MECH_KRB5]).build();
+    handshake_with(&mut client, &mut server, None).unwrap();
+    assert_eq!(server.mechanism().unwrap(), &GSS_MECH_KRB5);
+}
+
+#[test]
+fn mech_negotiated_then_rejected() {
+    // SPNEGO negotiates krb5, which the policy then refuses
+    let mut client = spnego_client();
+    let mut server = server_builder().allowed_mechs(&[&GSS_MECH_NTLM]).build();
+    let e = handshake_with(&mut client, &mut server, None).unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::BadMech));
+    assert!(!server.is_complete());
+    assert!(server.mechanism().is_err());
+}
+
+#[test]
+fn mech_never_offered() {
+    // the acceptor's SPNEGO won't negotiate krb5, the only mechanism
+    // the client has
+    let name = service_name();
+    let cred = Cred::acquire(Some(&name), None, CredUsage::Accept, None).unwrap();
+    let mut mechs = OidSet::new().unwrap();
+    mechs.add(&GSS_MECH_IAKERB).unwrap();
+    cred.set_neg_mechs(&mechs).unwrap();
+    let mut client = spnego_client();
+    let mut server = ServerCtxBuilder::new(Some(cred))
+        .allowed_mechs(&[&GSS_MECH_KRB5])
+        .build();
+    let tok = client.step(None, None).unwrap().unwrap();
+    let e = server.step(&tok).unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::Failure));
+    assert!(!server.is_complete());
+}
@@ -0,0 +1,91 @@
+#![cfg(feature = "tokio")]
+mod common;
+
+use common::{client, server_builder};
+use libgssapi::{
+    context::{ClientCtx, CtxState, SecurityContext, ServerCtx},
+    error::WellKnownError,
+};
+use std::sync::mpsc;
+use tokio::runtime::{Builder, Runtime};
+
+/// A runtime with one blocking thread, so a test can occupy it and
+/// drop a step's future before the step runs
+fn runtime() -> Runtime {
+    Builder::new_multi_thread()
+        .max_blocking_threads(1)
+        .build()
+        .unwrap()
+}
+
+async fn handshake(client: &mut ClientCtx, server: &mut ServerCtx) {
+    let mut server_tok = None;
+    while let Some(tok) = client.step_async(server_tok.as_deref(), None).await.unwrap() {
+        match server.step_async(&tok).await.unwrap() {
+            None => break,
+            Some(tok) => server_tok = Some(tok.to_vec()),
+        }
+    }
+}
+
+#[test]
+fn step_async() {
+    let rt = runtime();
+    let mut client = client();
+    let mut server = server_builder().build();
+    rt.block_on(handshake(&mut client, &mut server));
+    assert!(client.is_complete() && server.is_complete());
+}
+
+#[test]
+fn cancelled_step_then_redrive() {
+    let rt = runtime();
+    let mut client = client();
+    let mut server = server_builder().build();
+    let tok = rt.block_on(client.step_async(None, None)).unwrap().unwrap();
+    let tok = rt.block_on(server.step_async(&tok)).unwrap().unwrap();
+    // occupy the only blocking thread, so the step can't finish
+    // before its future is dropped
+    let (unblock, blocked) = mpsc::channel::<()>();
+    rt.spawn_blocking(move || blocked.recv());
+    rt.block_on(async {
+        tokio::select! {
+            biased;
+            _ = client.step_async(Some(&tok), None) => panic!("step finished"),
+            _ = async {} => (),
+        }
+    });
+    assert!(matches!(client.state(), CtxState::Failed));
+    let e = client.step(Some(&tok), None).unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::NoContext));
+    unblock.send(()).unwrap();
+    // the target, cred, flags and mechanism survived, so the
+    // handshake can start again
+    client.restart();
+    assert!(matches!(client.state(), CtxState::Uninit));
+    let mut server = server_builder().build();
+    rt.block_on(handshake(&mut client, &mut server));
+    assert!(client.is_complete() && server.is_complete());
+    let msg = client.wrap(true, b"still works").unwrap();
+    assert_eq!(&*server.unwrap(&msg).unwrap(), b"still works");
+}
+
+#[test]
+fn cancelled_server_step() {
+    let rt = runtime();
+    let mut client = client();
+    let mut server = server_builder().build();
+    let tok = rt.block_on(client.step_async(None, None)).unwrap().unwrap();
+    let (unblock, blocked) = mpsc::channel::<()>();
+    rt.spawn_blocking(move || blocked.recv());
+    rt.block_on(async {
+        tokio::select! {
+            biased;
+            _ = server.step_async(&tok) => panic!("step finished"),
+            _ = async {} => (),
+        }
+    });
+    assert!(matches!(server.state(), CtxState::Failed));
+    assert!(server.step(&tok).is_err());
+    unblock.send(()).unwrap();
+}
@@ -0,0 +1,285 @@
+mod common;
+
+use common::established;
+#[cfg(feature = "tokio")]
+use libgssapi::context::{ClientCtx, ServerCtx};
+use libgssapi::{
+    context::SecurityContext,
+    stream::{GssStream, WrapWriter},
+};
+use std::{
+    convert::TryInto,
+    io::{Cursor, Read, Write},
+};
+
+const MAX_TOKEN: usize = 0x10000;
+
+fn message(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+// the length of each record in `wire`
+fn records(wire: &[u8]) -> Vec<usize> {
+    let mut lens = Vec::new();
+    let mut rest = wire;
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        lens.push(len);
+        rest = &rest[4 + len..];
+    }
+    lens
+}
+
+fn round_trip(len: usize, encrypt: bool) -> Vec<usize> {
+    let (client, server) = established();
+    let msg = message(len);
+    let mut w = WrapWriter::new(client, Vec::new(), encrypt);
+    w.write_all(&msg).unwrap();
+    let (_, wire) = w.into_inner();
+    let lens = records(&wire);
+    assert!(lens.iter().all(|len| *len <= MAX_TOKEN));
+    let mut r = GssStream::new(server, Cursor::new(wire), encrypt);
+    let mut out = Vec::new();
+    r.read_to_end(&mut out).unwrap();
+    assert!(out == msg);
+    lens
+}
+
+#[test]
+fn multi_megabyte() {
+    round_trip(5 * 1024 * 1024 + 17, true);
+    round_trip(3 * 1024 * 1024 + 1, false);
+}
+
+#[test]
+fn exact_record_boundary() {
+    let (mut client, _) = established();
+    let max_input = client.wrap_size_limit(true, MAX_TOKEN).unwrap();
+    assert_eq!(round_trip(64 * max_input, true).len(), 64);
+    assert_eq!(round_trip(64 * max_input + 1, true).len(), 65);
+    assert_eq!(round_trip(64 * max_input - 1, true).len(), 64);
+    assert_eq!(round_trip(max_input, true).len(), 1);
+}
+
+// the async backends, each sends `msg` from `client` to `server`
+// through a pipe that holds `pipe` bytes, and reads it `read_len`
+// bytes at a time
+#[cfg(feature = "tokio")]
+async fn tokio_read<R>(mut r: R, read_len: usize) -> Vec<u8>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt;
+    let mut out = Vec::new();
+    let mut buf = vec![0u8; read_len];
+    loop {
+        match r.read(&mut buf).await.unwrap() {
+            0 => break out,
+            n => out.extend_from_slice(&buf[..n]),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+async fn tokio_transfer(
+    client: ClientCtx,
+    server: ServerCtx,
+    msg: Vec<u8>,
+    pipe: usize,
+    read_len: usize,
+) -> Vec<u8> {
+    use libgssapi::stream::{AsyncGssStream, AsyncWrapWriter};
+    use tokio::io::AsyncWriteExt;
+    let (tx, rx) = tokio::io::duplex(pipe);
+    let writer = tokio::spawn(async move {
+        let mut w = AsyncWrapWriter::new(client, tx, true);
+        w.write_all(&msg).await.unwrap();
+        w.shutdown().await.unwrap();
+    });
+    let out = tokio_read(AsyncGssStream::new(server, rx, true), read_len).await;
+    writer.await.unwrap();
+    out
+}
+
+// the same, but writing through AsyncGssStream
+#[cfg(feature = "tokio")]
+async fn tokio_stream_transfer(
+    client: ClientCtx,
+    server: ServerCtx,
+    msg: Vec<u8>,
+    pipe: usize,
+    read_len: usize,
+) -> Vec<u8> {
+    use libgssapi::stream::AsyncGssStream;
+    use tokio::io::AsyncWriteExt;
+    let (tx, rx) = tokio::io::duplex(pipe);
+    let writer = tokio::spawn(async move {
+        let mut w = AsyncGssStream::new(client, tx, true);
+        w.write_all(&msg).await.unwrap();
+        w.shutdown().await.unwrap();
+    });
+    let out = tokio_read(AsyncGssStream::new(server, rx, true), read_len).await;
+    writer.await.unwrap();
+    out
+}
+
+#[cfg(all(feature = "tokio", feature = "futures-io"))]
+async fn futures_transfer(
+    client: ClientCtx,
+    server: ServerCtx,
+    msg: Vec<u8>,
+    pipe: usize,
+    read_len: usize,
+) -> Vec<u8> {
+    use futures_util::io::{AsyncReadExt, AsyncWriteExt};
+    use libgssapi::stream::FuturesGssStream;
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+    let (tx, rx) = tokio::io::duplex(pipe);
+    let writer = tokio::spawn(async move {
+        let mut w = FuturesGssStream::new(client, tx.compat(), true);
+        w.write_all(&msg).await.unwrap();
+        w.close().await.unwrap();
+    });
+    let mut r = FuturesGssStream::new(server, rx.compat(), true);
+    let mut out = Vec::new();
+    let mut buf = vec![0u8; read_len];
+    loop {
+        match r.read(&mut buf).await.unwrap() {
+            0 => break,
+            n => out.extend_from_slice(&buf[..n]),
+        }
+    }
+    writer.await.unwrap();
+    out
+}
+
+#[cfg(feature = "tokio")]
+macro_rules! async_tests {
+    ($backend:ident, $transfer:ident) => {
+        mod $backend {
+            use super::*;
+
+            #[tokio::test]
+            async fn multi_megabyte() {
+                let (mut client, _) = established();
+                let max_input = client.wrap_size_limit(true, MAX_TOKEN).unwrap();
+                for len in [5 * 1024 * 1024 + 17, 64 * max_input] {
+                    let (client, server) = established();
+                    let msg = message(len);
+                    let out =
+                        $transfer(client, server, msg.clone(), MAX_TOKEN, 8192).await;
+                    assert!(out == msg);
+                }
+            }
+
+            #[tokio::test]
+            async fn small_pipe() {
+                let (client, server) = established();
+                let msg = message(100_000);
+                assert!($transfer(client, server, msg.clone(), 7, 8192).await == msg);
+            }
+
+            // records are much bigger than the reader's buffer, the
+            // rest of each is handed out by the following reads
+            #[tokio::test]
+            async fn tiny_read_buffer() {
+                let (client, server) = established();
+                let msg = message(3 * MAX_TOKEN + 5);
+                let out = $transfer(client, server, msg.clone(), MAX_TOKEN, 1).await;
+                assert!(out == msg);
+            }
+        }
+    };
+}
+
+#[cfg(feature = "tokio")]
+async_tests!(tokio_wrap_writer, tokio_transfer);
+#[cfg(feature = "tokio")]
+async_tests!(tokio_stream, tokio_stream_transfer);
+#[cfg(all(feature = "tokio", feature = "futures-io"))]
+async_tests!(futures_stream, futures_transfer);
+
+#[cfg(feature = "tokio")]
+mod codec {
+    use super::*;
+    use bytes::{Bytes, BytesMut};
+    use futures_util::{SinkExt, StreamExt};
+    use libgssapi::stream::GssCodec;
+    use std::io;
+    use tokio::io::{AsyncRead, AsyncWriteExt};
+    use tokio_util::codec::{Encoder, FramedRead, FramedWrite};
+
+    async fn read_all<R: AsyncRead + Unpin>(rx: R, server: ServerCtx) -> Vec<Bytes> {
+        let r = FramedRead::new(rx, GssCodec::new(server, true));
+        r.map(|msg| msg.unwrap()).collect().await
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let (mut client, server) = established();
+        let max_input = client.wrap_size_limit(true, MAX_TOKEN).unwrap();
+        let msgs = vec![message(1), message(1000), message(max_input)];
+        let (tx, rx) = tokio::io::duplex(MAX_TOKEN);
+        let sent = msgs.clone();
+        let writer = tokio::spawn(async move {
+            let mut w = FramedWrite::new(tx, GssCodec::new(client, true));
+            for msg in sent {
+                w.send(Bytes::from(msg)).await.unwrap();
+            }
+        });
+        let out = read_all(rx, server).await;
+        writer.await.unwrap();
+        assert!(out == msgs);
+    }
+
+    #[tokio::test]
+    async fn oversize() {
+        let (mut client, server) = established();
+        let max_input = client.wrap_size_limit(true, MAX_TOKEN).unwrap();
+        let msg = Bytes::from(message(2 * max_input + 1));
+        let mut codec = GssCodec::new(client, true);
+        let mut wire = BytesMut::new();
+        let e = codec.encode(msg.clone(), &mut wire).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert!(wire.is_empty());
+        // split, the peer decodes each record as a message
+        let (tx, rx) = tokio::io::duplex(MAX_TOKEN);
+        let sent = msg.clone();
+        let writer = tokio::spawn(async move {
+            let mut w = FramedWrite::new(tx, codec.split(true));
+            w.send(sent).await.unwrap();
+        });
+        let out = read_all(rx, server).await;
+        writer.await.unwrap();
+        assert_eq!(out.len(), 3);
+        assert!(out.concat() == msg);
+    }
+
+    #[tokio::test]
+    async fn oversize_record() {
+        let (_, server) = established();
+        let wire = (MAX_TOKEN as u32 + 1).to_be_bytes();
+        let codec = GssCodec::new(server, true).max_token_size(MAX_TOKEN);
+        let mut r = FramedRead::new(&wire[..], codec);
+        let e = r.next().await.unwrap().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn split_frame() {
+        let (client, server) = established();
+        let msgs = vec![message(10), message(5000), message(1)];
+        let mut codec = GssCodec::new(client, true);
+        let mut wire = BytesMut::new();
+        for msg in &msgs {
+            codec.encode(Bytes::from(msg.clone()), &mut wire).unwrap();
+        }
+        // the pipe moves 3 bytes at a time, so the length prefixes and
+        // tokens arrive in pieces
+        let (mut tx, rx) = tokio::io::duplex(3);
+        let writer = tokio::spawn(async move { tx.write_all(&wire).await.unwrap() });
+        let out = read_all(rx, server).await;
+        writer.await.unwrap();
+        assert!(out == msgs);
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::established;
+use libgssapi::{context::SecurityContext, error::WellKnownError};
+
+#[test]
+fn encrypted_round_trip() {
+    let (mut client, mut server) = established();
+    assert!(client.can_encrypt() && server.can_encrypt());
+    let tok = client.wrap(true, b"wrap").unwrap();
+    assert_eq!(&*server.unwrap(&tok).unwrap(), b"wrap");
+    let tok = server.encrypt(b"encrypt").unwrap();
+    assert_eq!(&*client.unwrap(&tok).unwrap(), b"encrypt");
+    let mut out = [0u8; 1024];
+    let (len, conf) = client.wrap_into_slice(true, b"slice", &mut out).unwrap();
+    assert!(conf);
+    assert_eq!(&*server.unwrap(&out[..len]).unwrap(), b"slice");
+}
+
+/// A context established without `GSS_C_CONF_FLAG`, which MIT only
+/// allows if the initiator credential asks for it
+#[cfg(feature = "mit_kerberos")]
+#[test]
+fn encrypt_without_conf_flag() {
+    use common::{handshake_with, server_builder, service_name};
+    use libgssapi::{
+        context::{ClientCtx, CtxFlags},
+        credential::{Cred, CredUsage},
+        oid::{GSS_KRB5_CRED_NO_CI_FLAGS_X, GSS_MECH_KRB5},
+    };
+    let name = service_name();
+    let mut cred = Cred::acquire(None, None, CredUsage::Initiate, None).unwrap();
+    cred.set_option(&GSS_KRB5_CRED_NO_CI_FLAGS_X, None).unwrap();
+    let flags = CtxFlags::GSS_C_MUTUAL_FLAG;
+    let mut client = ClientCtx::new(Some(cred), name, flags, Some(&GSS_MECH_KRB5));
+    let mut server = server_builder().build();
+    handshake_with(&mut client, &mut server, None).unwrap();
+    assert!(!client.flags().unwrap().contains(CtxFlags::GSS_C_CONF_FLAG));
+    assert!(!client.can_encrypt() && !server.can_encrypt());
+    let e = client.encrypt(b"secret").unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::NotEncrypted));
+    let e = server.encrypt(b"secret").unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::NotEncrypted));
+    assert!(e.to_string().contains("would not have been encrypted"));
+    // the contexts still work, integrity protection is fine
+    let tok = client.wrap(false, b"signed").unwrap();
+    assert_eq!(&*server.unwrap(&tok).unwrap(), b"signed");
+}
+
+#[test]
+fn wrap_into_slice_too_small() {
+    let (mut client, mut server) = established();
+    let mut out = [0u8; 8];
+    let e = client.wrap_into_slice(true, b"slice", &mut out).unwrap_err();
+    assert_eq!(e.kind(), Some(WellKnownError::BufferTooSmall));
+    let required = e.required_len().unwrap();
+    assert!(required > out.len());
+    assert!(e.to_string().contains(&format!("{} bytes", required)));
+    let mut out = vec![0u8; required];
+    let (len, _) = client.wrap_into_slice(true, b"slice", &mut out).unwrap();
+    assert_eq!(len, required);
+    // the failed call didn't use a sequence number, so the server sees
+    // no gap
+    assert_eq!(&*server.unwrap(&out).unwrap(), b"slice");
+    let e = client.wrap_into_slice(true, b"slice", &mut out[..len - 1]).unwrap_err();
+    assert_eq!(e.required_len(), Some(required));
+}